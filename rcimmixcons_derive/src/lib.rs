@@ -0,0 +1,57 @@
+// Copyright (c) <2015> <lummax>
+// Licensed under MIT (http://opensource.org/licenses/MIT)
+
+//! The `#[derive(Trace)]` macro for the `rcimmixcons` crate.
+//!
+//! It implements `rcimmixcons::Trace` for a struct by collecting the `Gc`
+//! pointer offsets of all its fields. Every field type must implement
+//! `Trace` and every type parameter is required to implement it as well.
+
+extern crate proc_macro;
+extern crate proc_macro2;
+extern crate syn;
+#[macro_use] extern crate quote;
+
+use proc_macro::TokenStream;
+use syn::{parse_macro_input, parse_quote, Data, DeriveInput, Error, GenericParam, Index};
+
+#[proc_macro_derive(Trace)]
+pub fn derive_trace(input: TokenStream) -> TokenStream {
+    let mut input = parse_macro_input!(input as DeriveInput);
+    let fields = match input.data {
+        Data::Struct(ref data) => data.fields.clone(),
+        _ => return Error::new_spanned(&input.ident, "Trace can only be derived for structs")
+                          .to_compile_error()
+                          .into(),
+    };
+    for param in &mut input.generics.params {
+        if let GenericParam::Type(ref mut param) = *param {
+            param.bounds.push(parse_quote!(::rcimmixcons::Trace));
+        }
+    }
+    let offsets = fields.iter().enumerate().map(|(num, field)| {
+        let ty = &field.ty;
+        let member = match field.ident {
+            Some(ref ident) => quote!(#ident),
+            None => {
+                let index = Index::from(num);
+                quote!(#index)
+            },
+        };
+        quote! {
+            <#ty as ::rcimmixcons::Trace>::gc_offsets(
+                base + ::std::mem::offset_of!(Self, #member), offsets);
+        }
+    });
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let expanded = quote! {
+        unsafe impl #impl_generics ::rcimmixcons::Trace for #name #ty_generics #where_clause {
+            #[allow(unused_variables)]
+            fn gc_offsets(base: usize, offsets: &mut ::std::vec::Vec<usize>) {
+                #(#offsets)*
+            }
+        }
+    };
+    expanded.into()
+}
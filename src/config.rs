@@ -0,0 +1,300 @@
+// Copyright (c) <2015> <lummax>
+// Licensed under MIT (http://opensource.org/licenses/MIT)
+
+extern crate libc;
+
+use std::error::Error;
+use std::fmt;
+
+use constants::{BLOCK_SIZE, HEAP_SIZE, EVAC_HEADROOM, CICLE_TRIGGER_THRESHHOLD,
+                EVAC_TRIGGER_THRESHHOLD, WRITE_BARRIER_COLLECT_THRESHOLD,
+                USE_RC_COLLECTOR, USE_EVACUATION, VERIFY_HEAP, SCAN_STACK,
+                USE_NURSERY, GC_THREADS};
+
+/// The `GCConfig` contains the runtime tuning knobs of the garbage collector.
+///
+/// Create it with `GCConfig::new()` (which uses the defaults from the
+/// `constants` module), adjust it with the `with_*()` methods and pass it to
+/// `RCImmixCons::with_config()`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GCConfig {
+    /// The size of the heap in bytes. Must be a multiple of `BLOCK_SIZE`.
+    heap_size: libc::size_t,
+
+    /// The number of blocks stored into the `EvacAllocator` for evacuation.
+    evac_headroom: libc::size_t,
+
+    /// Ratio of available blocks when to trigger cycle collection.
+    cycle_trigger_threshold: f32,
+
+    /// Ratio of available blocks when to trigger evacuation collection.
+    evac_trigger_threshold: f32,
+
+    /// Number of write barrier invocations after which a collection will be
+    /// triggered. If zero the write barrier will not trigger a collection.
+    write_barrier_collect_threshold: libc::size_t,
+
+    /// Whether the reference counting collector should be used. Otherwise
+    /// only the tracing immix collector will be utilized.
+    use_rc_collector: bool,
+
+    /// Whether evacuation should be used or not.
+    use_evacuation: bool,
+
+    /// Whether the heap should be verified after every collection.
+    verify_heap: bool,
+
+    /// Whether the stacks and registers of the mutator threads are scanned
+    /// conservatively for roots. Otherwise only the precise roots are used.
+    scan_stack: bool,
+
+    /// Whether new objects are allocated into nursery blocks whose survivors
+    /// are evacuated by every collection.
+    use_nursery: bool,
+
+    /// The number of threads tracing the heap and processing the reference
+    /// counting buffers during a collection.
+    gc_threads: libc::size_t,
+}
+
+/// The errors returned by `GCConfig::validate()`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GCConfigError {
+    /// The heap size is not a non-zero multiple of `BLOCK_SIZE`.
+    HeapSizeNotBlockAligned(usize),
+
+    /// The heap does not contain more blocks than the evacuation headroom.
+    HeapTooSmall(usize),
+
+    /// A trigger threshold ratio is not within `0.0..=1.0`.
+    InvalidThreshold(f32),
+
+    /// The write barrier threshold is set but the reference counting
+    /// collector (the only user of the write barrier) is disabled.
+    WriteBarrierWithoutRC,
+
+    /// The nursery is used but the reference counting collector or
+    /// evacuation is disabled.
+    NurseryWithoutRCEvacuation,
+
+    /// The number of GC threads is zero.
+    NoGCThreads,
+}
+
+impl GCConfig {
+    /// Create a new `GCConfig` with the default values.
+    pub fn new() -> GCConfig {
+        GCConfig {
+            heap_size: HEAP_SIZE as libc::size_t,
+            evac_headroom: EVAC_HEADROOM as libc::size_t,
+            cycle_trigger_threshold: CICLE_TRIGGER_THRESHHOLD,
+            evac_trigger_threshold: EVAC_TRIGGER_THRESHHOLD,
+            write_barrier_collect_threshold: WRITE_BARRIER_COLLECT_THRESHOLD as libc::size_t,
+            use_rc_collector: USE_RC_COLLECTOR,
+            use_evacuation: USE_EVACUATION,
+            verify_heap: VERIFY_HEAP,
+            scan_stack: SCAN_STACK,
+            use_nursery: USE_NURSERY,
+            gc_threads: GC_THREADS as libc::size_t,
+        }
+    }
+
+    /// Set the size of the heap in bytes.
+    pub fn with_heap_size(mut self, heap_size: usize) -> GCConfig {
+        self.heap_size = heap_size as libc::size_t;
+        self
+    }
+
+    /// Set the number of blocks stored for evacuation.
+    pub fn with_evac_headroom(mut self, evac_headroom: usize) -> GCConfig {
+        self.evac_headroom = evac_headroom as libc::size_t;
+        self
+    }
+
+    /// Set the ratio of available blocks when to trigger cycle collection.
+    pub fn with_cycle_trigger_threshold(mut self, threshold: f32) -> GCConfig {
+        self.cycle_trigger_threshold = threshold;
+        self
+    }
+
+    /// Set the ratio of available blocks when to trigger evacuation.
+    pub fn with_evac_trigger_threshold(mut self, threshold: f32) -> GCConfig {
+        self.evac_trigger_threshold = threshold;
+        self
+    }
+
+    /// Set the number of write barrier invocations after which a collection
+    /// will be triggered.
+    pub fn with_write_barrier_collect_threshold(mut self, threshold: usize) -> GCConfig {
+        self.write_barrier_collect_threshold = threshold as libc::size_t;
+        self
+    }
+
+    /// Set whether the reference counting collector should be used.
+    pub fn with_rc_collector(mut self, use_rc_collector: bool) -> GCConfig {
+        self.use_rc_collector = use_rc_collector;
+        self
+    }
+
+    /// Set whether evacuation should be used.
+    pub fn with_evacuation(mut self, use_evacuation: bool) -> GCConfig {
+        self.use_evacuation = use_evacuation;
+        self
+    }
+
+    /// Set whether the heap should be verified after every collection.
+    ///
+    /// This is slow and meant for debugging the collector and the write
+    /// barriers of the mutator program. Violations of the invariants are
+    /// logged and returned by `RCImmixCons::verify_violations()`.
+    pub fn with_verify_heap(mut self, verify_heap: bool) -> GCConfig {
+        self.verify_heap = verify_heap;
+        self
+    }
+
+    /// Set whether the stacks and registers of the mutator threads are
+    /// scanned conservatively for roots.
+    ///
+    /// Disable this if the mutator program registers all its roots precisely
+    /// (see `RCImmixCons::push_roots()` and `RCImmixCons::set_static_root()`).
+    pub fn with_scan_stack(mut self, scan_stack: bool) -> GCConfig {
+        self.scan_stack = scan_stack;
+        self
+    }
+
+    /// Set whether new objects are allocated into nursery blocks.
+    ///
+    /// Every collection then evacuates the surviving new objects out of the
+    /// nursery blocks, which are freed afterwards. Objects that are pinned
+    /// (or could not be evacuated) stay in place. This requires the
+    /// reference counting collector and evacuation.
+    pub fn with_nursery(mut self, use_nursery: bool) -> GCConfig {
+        self.use_nursery = use_nursery;
+        self
+    }
+
+    /// Set the number of threads tracing the heap and processing the
+    /// reference counting buffers during a collection.
+    ///
+    /// With more than one thread the tracing collection and the processing
+    /// of the modified and decrement buffers of the reference counting
+    /// collection are performed by `gc_threads` worker threads with
+    /// work-stealing stacks. The result is the same as the sequential
+    /// processing.
+    pub fn with_gc_threads(mut self, gc_threads: usize) -> GCConfig {
+        self.gc_threads = gc_threads as libc::size_t;
+        self
+    }
+
+    /// Return the size of the heap in bytes.
+    pub fn heap_size(&self) -> usize {
+        self.heap_size as usize
+    }
+
+    /// Return the number of blocks within the heap.
+    pub fn total_blocks(&self) -> usize {
+        self.heap_size() / BLOCK_SIZE
+    }
+
+    /// Return the number of blocks stored for evacuation.
+    pub fn evac_headroom(&self) -> usize {
+        self.evac_headroom as usize
+    }
+
+    /// Return the ratio of available blocks when to trigger cycle collection.
+    pub fn cycle_trigger_threshold(&self) -> f32 {
+        self.cycle_trigger_threshold
+    }
+
+    /// Return the ratio of available blocks when to trigger evacuation.
+    pub fn evac_trigger_threshold(&self) -> f32 {
+        self.evac_trigger_threshold
+    }
+
+    /// Return the number of write barrier invocations after which a
+    /// collection will be triggered.
+    pub fn write_barrier_collect_threshold(&self) -> usize {
+        self.write_barrier_collect_threshold as usize
+    }
+
+    /// Return whether the reference counting collector should be used.
+    pub fn use_rc_collector(&self) -> bool {
+        self.use_rc_collector
+    }
+
+    /// Return whether evacuation should be used.
+    pub fn use_evacuation(&self) -> bool {
+        self.use_evacuation
+    }
+
+    /// Return whether the heap should be verified after every collection.
+    pub fn verify_heap(&self) -> bool {
+        self.verify_heap
+    }
+
+    /// Return whether the stacks and registers of the mutator threads are
+    /// scanned conservatively for roots.
+    pub fn scan_stack(&self) -> bool {
+        self.scan_stack
+    }
+
+    /// Return whether new objects are allocated into nursery blocks.
+    pub fn use_nursery(&self) -> bool {
+        self.use_nursery
+    }
+
+    /// Return the number of threads tracing the heap and processing the
+    /// reference counting buffers during a collection.
+    pub fn gc_threads(&self) -> usize {
+        self.gc_threads as usize
+    }
+
+    /// Check this configuration for nonsense values and combinations.
+    pub fn validate(&self) -> Result<(), GCConfigError> {
+        if self.heap_size() == 0 || self.heap_size() % BLOCK_SIZE != 0 {
+            return Err(GCConfigError::HeapSizeNotBlockAligned(self.heap_size()));
+        }
+        if self.total_blocks() <= self.evac_headroom() {
+            return Err(GCConfigError::HeapTooSmall(self.heap_size()));
+        }
+        for &threshold in &[self.cycle_trigger_threshold, self.evac_trigger_threshold] {
+            if !(0.0 <= threshold && threshold <= 1.0) {
+                return Err(GCConfigError::InvalidThreshold(threshold));
+            }
+        }
+        if !self.use_rc_collector && self.write_barrier_collect_threshold() > 0 {
+            return Err(GCConfigError::WriteBarrierWithoutRC);
+        }
+        if self.use_nursery && !(self.use_rc_collector && self.use_evacuation) {
+            return Err(GCConfigError::NurseryWithoutRCEvacuation);
+        }
+        if self.gc_threads() == 0 {
+            return Err(GCConfigError::NoGCThreads);
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for GCConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            GCConfigError::HeapSizeNotBlockAligned(size) =>
+                write!(f, "heap size {} is not a multiple of the block size {}",
+                       size, BLOCK_SIZE),
+            GCConfigError::HeapTooSmall(size) =>
+                write!(f, "heap size {} leaves no blocks besides the evacuation headroom",
+                       size),
+            GCConfigError::InvalidThreshold(threshold) =>
+                write!(f, "threshold {} is not a ratio between 0 and 1", threshold),
+            GCConfigError::WriteBarrierWithoutRC =>
+                write!(f, "write barrier collect threshold requires the RC collector"),
+            GCConfigError::NurseryWithoutRCEvacuation =>
+                write!(f, "nursery requires the RC collector and evacuation"),
+            GCConfigError::NoGCThreads =>
+                write!(f, "at least one GC thread is required"),
+        }
+    }
+}
+
+impl Error for GCConfigError {}
@@ -1,18 +1,20 @@
 // Copyright (c) <2015> <lummax>
 // Licensed under MIT (http://opensource.org/licenses/MIT)
 
-/// The size of the heap in bytes.
+/// The default size of the heap in bytes (see `GCConfig`).
 pub const HEAP_SIZE: usize = 1024 * 1024 * 1024;
 
 /// The size of a block in bytes.
 pub const BLOCK_SIZE: usize = 32 * 1024;
 
-/// The number ob blocks within the heap.
-pub const TOTAL_BLOCKS: usize = HEAP_SIZE / BLOCK_SIZE;
-
 /// The size of a line in bytes.
 pub const LINE_SIZE: usize = 256;
 
+/// The number of bits of the `GCHeader` used for the reference count. A
+/// reference count reaching the maximum value is stuck until the next immix
+/// tracing collection recomputes it.
+pub const RC_BITS: usize = 3;
+
 /// The number of lines per block.
 pub const NUM_LINES_PER_BLOCK: usize = BLOCK_SIZE / LINE_SIZE;
 
@@ -23,22 +25,42 @@ pub const MEDIUM_OBJECT: usize = LINE_SIZE;
 /// Objects larger than LARGE_OBJECT are allocated using the `LargeObjectSpace`.
 pub const LARGE_OBJECT: usize = 8 * 1024;
 
-/// Whether the reference counting collector should be used. Otherwise only
-/// the tracing immix collector will be utilized.
+/// Whether the reference counting collector should be used by default.
+/// Otherwise only the tracing immix collector will be utilized.
 pub const USE_RC_COLLECTOR: bool = true;
 
-/// Number of write barrier invocations after which a collection will be
-/// triggered. If zero the write barrier will not trigger a collection.
+/// Default number of write barrier invocations after which a collection will
+/// be triggered. If zero the write barrier will not trigger a collection.
 pub const WRITE_BARRIER_COLLECT_THRESHOLD: usize = 0;
 
-/// Ratio when to trigger cycle collection.
+/// Default ratio when to trigger cycle collection.
 pub const CICLE_TRIGGER_THRESHHOLD: f32 = 0.01;
 
-/// Whether evacuation should be used or not.
+/// Whether evacuation should be used by default or not.
 pub const USE_EVACUATION: bool = true;
 
-/// The number of blocks stored into the `EvacAllocator` for evacuation.
+/// The default number of blocks stored into the `EvacAllocator` for
+/// evacuation.
 pub const EVAC_HEADROOM: usize = 5;
 
-/// Ratio when to trigger evacuation collection.
+/// Default ratio when to trigger evacuation collection.
 pub const EVAC_TRIGGER_THRESHHOLD: f32 = 0.01;
+
+/// Whether the heap should be verified after every collection by default.
+pub const VERIFY_HEAP: bool = false;
+
+/// Whether the stacks and registers of the mutator threads are scanned
+/// conservatively for roots by default.
+pub const SCAN_STACK: bool = true;
+
+/// Whether new objects are allocated into nursery blocks by default.
+pub const USE_NURSERY: bool = false;
+
+/// The default number of threads tracing the heap and processing the
+/// reference counting buffers during a collection.
+pub const GC_THREADS: usize = 1;
+
+/// The minimum number of objects in the modified or decrement buffer of the
+/// reference counting collector to process it with the GC worker threads.
+/// Smaller buffers are processed by the collecting thread alone.
+pub const PARALLEL_BUFFER_THRESHOLD: usize = 1024;
@@ -0,0 +1,129 @@
+// Copyright (c) <2015> <lummax>
+// Licensed under MIT (http://opensource.org/licenses/MIT)
+
+extern crate libc;
+
+use std::cell::Cell;
+use std::sync::Arc;
+use std::time::Duration;
+
+use spaces::CollectionType;
+
+thread_local!(
+    /// Whether the current thread runs the hooks of a
+    /// `GCEventKind::CollectionStart` event.
+    static IN_START_HOOK: Cell<bool> = Cell::new(false)
+);
+
+/// The kind of a `GCEvent`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GCEventKind {
+    /// A collection started. All threads are stopped.
+    CollectionStart,
+
+    /// A collection ended. The threads are restarted already.
+    CollectionEnd,
+}
+
+/// The `GCEvent` is passed to the event hooks registered with
+/// `RCImmixCons::add_event_hook()`.
+///
+/// The durations and block counts are only set for
+/// `GCEventKind::CollectionEnd`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GCEvent {
+    /// The kind of this event.
+    pub kind: GCEventKind,
+
+    /// The type of the collection.
+    pub collection_type: CollectionType,
+
+    /// The duration of the reference counting collection in nanoseconds.
+    pub rc_time_ns: u64,
+
+    /// The duration of the immix tracing collection in nanoseconds.
+    pub immix_time_ns: u64,
+
+    /// The duration of sweeping the blocks in nanoseconds.
+    pub sweep_time_ns: u64,
+
+    /// The number of blocks found free by the sweep.
+    pub freed_blocks: libc::size_t,
+
+    /// The number of blocks with holes found by the sweep.
+    pub recycled_blocks: libc::size_t,
+}
+
+impl GCEvent {
+    /// Create a new `GCEvent` of the given `kind` for a collection of
+    /// `collection_type` with all durations and block counts set to zero.
+    pub fn new(kind: GCEventKind, collection_type: CollectionType) -> GCEvent {
+        GCEvent {
+            kind: kind,
+            collection_type: collection_type,
+            rc_time_ns: 0,
+            immix_time_ns: 0,
+            sweep_time_ns: 0,
+            freed_blocks: 0,
+            recycled_blocks: 0,
+        }
+    }
+}
+
+/// A type alias for the event hooks.
+pub type EventHook = Arc<Fn(&GCEvent) + Send + Sync>;
+
+/// The `EventHooks` contains the registered event hooks.
+///
+/// It is cloned to fire the `GCEventKind::CollectionEnd` events after the
+/// GC lock was released.
+#[derive(Clone)]
+pub struct EventHooks {
+    /// The registered hooks in order of registration.
+    hooks: Vec<EventHook>,
+}
+
+impl EventHooks {
+    /// Create a new `EventHooks` without any hooks.
+    pub fn new() -> EventHooks {
+        EventHooks {
+            hooks: Vec::new(),
+        }
+    }
+
+    /// Register the given `hook`.
+    pub fn add(&mut self, hook: EventHook) {
+        self.hooks.push(hook);
+    }
+
+    /// Call all hooks with the given `event`.
+    ///
+    /// The hooks of a `GCEventKind::CollectionStart` event run while the GC
+    /// lock is held (see `debug_assert_not_in_start_hook()`).
+    pub fn fire(&self, event: &GCEvent) {
+        debug!("Fire event {:?} to {} hooks", event.kind, self.hooks.len());
+        let start = event.kind == GCEventKind::CollectionStart;
+        IN_START_HOOK.with(|in_start_hook| in_start_hook.set(start));
+        for hook in &self.hooks {
+            hook(event);
+        }
+        IN_START_HOOK.with(|in_start_hook| in_start_hook.set(false));
+    }
+}
+
+/// Panic in debug builds if the current thread runs the hooks of a
+/// `GCEventKind::CollectionStart` event.
+///
+/// These hooks run while the GC lock is held and the world is stopped, so
+/// using the garbage collector from them would deadlock.
+pub fn debug_assert_not_in_start_hook() {
+    debug_assert!(!IN_START_HOOK.with(|in_start_hook| in_start_hook.get()),
+                  "The garbage collector must not be used from a collection start hook");
+}
+
+/// Return the `duration` in nanoseconds.
+pub fn duration_ns(duration: Duration) -> u64 {
+    duration.as_secs() * 1_000_000_000 + duration.subsec_nanos() as u64
+}
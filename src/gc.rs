@@ -0,0 +1,240 @@
+// Copyright (c) <2015> <lummax>
+// Licensed under MIT (http://opensource.org/licenses/MIT)
+
+//! A typed interface to the garbage collector for Rust programs.
+//!
+//! A `Gc<T>` points to a `T` stored after a `GCObject` in the heap of a
+//! `RCImmixCons`. The `GCRTTI` of `T` is generated from its `Trace`
+//! implementation, which can be derived with `#[derive(Trace)]` of the
+//! `rcimmixcons_derive` crate:
+//!
+//! ```ignore
+//! #[derive(Trace)]
+//! struct Node {
+//!     value: usize,
+//!     next: GcCell<Option<Gc<Node>>>,
+//! }
+//!
+//! let first = Gc::new(&collector, Node { value: 1, next: GcCell::new(None) }).unwrap();
+//! let second = Gc::new(&collector, Node { value: 2, next: GcCell::new(None) }).unwrap();
+//! first.set(&collector, |node| &node.next, Some(second));
+//! ```
+//!
+//! Fields that are modified after the allocation are wrapped in a `GcCell`
+//! and can only be set with `Gc::set()`, which calls
+//! `RCImmixCons::write_barrier()` before the store.
+//!
+//! `Gc` pointers on the stack and in the registers are found by the
+//! conservative stack scan, which also pins their objects. `Gc` pointers
+//! stored anywhere else outside of the heap (e.g. in a `Vec`) are no roots.
+//! The `T` is dropped by a finalizer after the object was found dead, so
+//! its `Drop` implementation must not dereference its `Gc` fields.
+
+use std::any::TypeId;
+use std::cell::UnsafeCell;
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::ptr::{self, NonNull};
+use std::sync::{Mutex, OnceLock};
+use std::{fmt, mem};
+
+use gc_object::{GCObject, GCObjectRef, GCRTTI};
+use RCImmixCons;
+
+/// The types that can be stored in a `Gc<T>`.
+///
+/// This is unsafe because the collector relies on the offsets: a missing
+/// offset lets it reclaim a live object and an offset of something that is
+/// not a `Gc` pointer (or `None`) makes it follow arbitrary data.
+pub unsafe trait Trace: 'static {
+    /// Push the byte offsets of all `Gc` pointers within `Self` onto
+    /// `offsets`. The offsets are relative to `base`, the offset of `Self`
+    /// within the object.
+    fn gc_offsets(base: usize, offsets: &mut Vec<usize>);
+}
+
+/// A pointer to a `T` managed by the garbage collector.
+#[repr(transparent)]
+pub struct Gc<T: Trace> {
+    /// The object holding the value.
+    object: NonNull<GcBox<T>>,
+}
+
+/// A field of an object that can be set with `Gc::set()`.
+#[repr(transparent)]
+pub struct GcCell<T: Trace> {
+    /// The value of the field.
+    value: UnsafeCell<T>,
+}
+
+/// The layout of the objects of `Gc<T>`.
+#[repr(C)]
+struct GcBox<T: Trace> {
+    /// The header of the object.
+    object: GCObject,
+
+    /// The value stored in the object.
+    value: T,
+}
+
+impl<T: Trace> Gc<T> {
+    /// Allocate a new object holding `value` or return `None` if the heap
+    /// is exhausted (see `RCImmixCons::allocate()`).
+    pub fn new(collector: &RCImmixCons, value: T) -> Option<Gc<T>> {
+        let object = collector.allocate(GcBox::<T>::rtti())? as *mut GcBox<T>;
+        unsafe{ ptr::write(&mut (*object).value, value); }
+        Some(Gc {
+            object: unsafe{ NonNull::new_unchecked(object) },
+        })
+    }
+
+    /// Return a `Gc<T>` for an `object` allocated by `Gc::new()`.
+    ///
+    /// This is unsafe because the `object` must not be null and must hold a
+    /// `T`.
+    pub unsafe fn from_object(object: GCObjectRef) -> Gc<T> {
+        debug_assert!((*object).rtti() == GcBox::<T>::rtti(),
+                      "Object {:p} does not hold the requested type", object);
+        Gc {
+            object: NonNull::new_unchecked(object as *mut GcBox<T>),
+        }
+    }
+
+    /// Return the `GCObject` of this object.
+    pub fn as_object(&self) -> GCObjectRef {
+        self.object.as_ptr() as GCObjectRef
+    }
+
+    /// Return if both `Gc` point to the same object.
+    pub fn ptr_eq(this: &Gc<T>, other: &Gc<T>) -> bool {
+        this.object == other.object
+    }
+
+    /// Store `value` in the field of this object returned by `field` after
+    /// calling `RCImmixCons::write_barrier()` on this object.
+    ///
+    /// Panics if the field is not within this object.
+    pub fn set<F, S>(&self, collector: &RCImmixCons, field: S, value: F)
+        where F: Trace, S: FnOnce(&T) -> &GcCell<F> {
+        let cell = field(self) as *const GcCell<F>;
+        let start = &**self as *const T as usize;
+        assert!(start <= cell as usize
+                && cell as usize + mem::size_of::<GcCell<F>>() <= start + mem::size_of::<T>(),
+                "Field {:p} is not within the object {:p}", cell, self.as_object());
+        collector.write_barrier(self.as_object());
+        unsafe{ *(*cell).value.get() = value; }
+    }
+}
+
+impl<T: Trace> Clone for Gc<T> {
+    fn clone(&self) -> Gc<T> {
+        *self
+    }
+}
+
+impl<T: Trace> Copy for Gc<T> {}
+
+impl<T: Trace> Deref for Gc<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe{ &(*self.object.as_ptr()).value }
+    }
+}
+
+impl<T: Trace + fmt::Debug> fmt::Debug for Gc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Gc").field(&**self).finish()
+    }
+}
+
+impl<T: Trace> GcCell<T> {
+    /// Create a new `GcCell` with the initial `value`.
+    pub fn new(value: T) -> GcCell<T> {
+        GcCell {
+            value: UnsafeCell::new(value),
+        }
+    }
+}
+
+impl<T: Trace + Copy> GcCell<T> {
+    /// Return the current value.
+    pub fn get(&self) -> T {
+        unsafe{ *self.value.get() }
+    }
+}
+
+impl<T: Trace + Copy + fmt::Debug> fmt::Debug for GcCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("GcCell").field(&self.get()).finish()
+    }
+}
+
+impl<T: Trace> GcBox<T> {
+    /// Return the `GCRTTI` of the objects holding a `T`.
+    ///
+    /// It is created once for every type and never freed.
+    fn rtti() -> &'static GCRTTI {
+        static RTTIS: OnceLock<Mutex<HashMap<TypeId, &'static GCRTTI>>> = OnceLock::new();
+        let mut rttis = RTTIS.get_or_init(|| Mutex::new(HashMap::new())).lock().unwrap();
+        *rttis.entry(TypeId::of::<T>()).or_insert_with(|| {
+            assert!(mem::align_of::<GcBox<T>>() <= mem::size_of::<usize>(),
+                    "The alignment of the type exceeds the word size");
+            let mut offsets = Vec::new();
+            T::gc_offsets(mem::offset_of!(GcBox<T>, value), &mut offsets);
+            let offsets: &'static [usize] = Box::leak(offsets.into_boxed_slice());
+            let mut rtti = GCRTTI::new(mem::size_of::<GcBox<T>>(), 0)
+                                  .with_member_offsets(offsets);
+            if mem::needs_drop::<T>() {
+                rtti = rtti.with_finalizer(GcBox::<T>::finalize);
+            }
+            Box::leak(Box::new(rtti))
+        })
+    }
+
+    /// The finalizer dropping the value of a dead object.
+    extern fn finalize(object: GCObjectRef) {
+        unsafe{ ptr::drop_in_place(&mut (*(object as *mut GcBox<T>)).value); }
+    }
+}
+
+unsafe impl<T: Trace> Trace for Gc<T> {
+    fn gc_offsets(base: usize, offsets: &mut Vec<usize>) {
+        offsets.push(base);
+    }
+}
+
+// `None` is represented as null, which the collector skips.
+unsafe impl<T: Trace> Trace for Option<Gc<T>> {
+    fn gc_offsets(base: usize, offsets: &mut Vec<usize>) {
+        offsets.push(base);
+    }
+}
+
+unsafe impl<T: Trace> Trace for GcCell<T> {
+    fn gc_offsets(base: usize, offsets: &mut Vec<usize>) {
+        T::gc_offsets(base, offsets);
+    }
+}
+
+unsafe impl<T: Trace, const N: usize> Trace for [T; N] {
+    fn gc_offsets(base: usize, offsets: &mut Vec<usize>) {
+        for num in 0..N {
+            T::gc_offsets(base + num * mem::size_of::<T>(), offsets);
+        }
+    }
+}
+
+/// Implement `Trace` for types without `Gc` pointers.
+macro_rules! trace_without_pointers(
+    ($($type_: ty),*) => {
+        $(
+            unsafe impl Trace for $type_ {
+                fn gc_offsets(_: usize, _: &mut Vec<usize>) {}
+            }
+        )*
+    };
+);
+
+trace_without_pointers!((), bool, char, u8, u16, u32, u64, usize, i8, i16,
+                        i32, i64, isize, f32, f64, String);
@@ -3,10 +3,11 @@
 
 extern crate libc;
 
-use std::mem;
+use std::{mem, ptr};
 use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-use constants::LINE_SIZE;
+use constants::{LINE_SIZE, RC_BITS};
 
 /// Structs that comprise the structure of an object as needed by the garbage
 /// collector.
@@ -17,34 +18,112 @@ use constants::LINE_SIZE;
 /// will return an initialized `GCObject` with a valid `GCHeader`.
 
 /// The `GCHeader` contains field for the garbage collector algorithms.
+///
+/// It is packed into a single word. The lowest `RC_BITS` bits are the
+/// reference count followed by the flags (see the `*_BIT` constants) and the
+/// number of array elements in the remaining bits.
+///
+/// The word is updated atomically, so the parallel collector threads can
+/// mark objects and update their reference counts concurrently.
 #[repr(C)]
-#[derive(PartialEq)]
 #[allow(missing_copy_implementations)]
 pub struct GCHeader {
-    /// How many objects point to this object.
-    reference_count: libc::size_t,
+    /// The reference count, the flags and the array length.
+    word: AtomicUsize,
+}
 
-    /// If this object is greater than `LINE_SIZE`.
-    spans_lines: bool,
+/// The maximum reference count. A reference count reaching this value is
+/// sticky: it is neither incremented nor decremented anymore.
+pub const RC_MAX: usize = (1 << RC_BITS) - 1;
 
-    /// If the object at this address was forwarded somewhere else.
-    forwarded: bool,
+/// If this object is greater than `LINE_SIZE`.
+const SPANS_LINES_BIT: usize = 1 << RC_BITS;
 
-    /// If this object was pushed on the `modBuffer` in `RCCollector`.
-    logged: bool,
+/// If the object at this address was forwarded somewhere else.
+const FORWARDED_BIT: usize = 1 << (RC_BITS + 1);
 
-    /// If this object was already visited by the tracing collector.
-    ///
-    /// _Note_: true/false do not mean marked/unmarked. The tracing collector
-    /// will flip the meaning of the value for every collection cycle. See
-    /// `Spaces.current_live_mark`.
-    marked: bool,
+/// If this object was pushed on the `modBuffer` in `RCCollector`.
+const LOGGED_BIT: usize = 1 << (RC_BITS + 2);
+
+/// If this object was already visited by the tracing collector.
+///
+/// _Note_: set/unset do not mean marked/unmarked. The tracing collector
+/// will flip the meaning of the value for every collection cycle. See
+/// `Spaces.current_live_mark`.
+const MARKED_BIT: usize = 1 << (RC_BITS + 3);
+
+/// If this object must not be evacuated (moved) by the collector.
+const PINNED_BIT: usize = 1 << (RC_BITS + 4);
+
+/// If this object was never touched by the collectors.
+const NEW_BIT: usize = 1 << (RC_BITS + 5);
+
+/// The position of the array length in the header word.
+const LENGTH_SHIFT: usize = RC_BITS + 6;
+
+/// The maximum number of array elements of an object.
+const MAX_LENGTH: usize = !0 >> LENGTH_SHIFT;
+
+impl GCHeader {
+    /// Create a new `GCHeader` with a reference count of zero.
+    fn new(length: usize, spans_lines: bool, mark: bool) -> GCHeader {
+        let header = GCHeader {
+            word: AtomicUsize::new((length << LENGTH_SHIFT) | NEW_BIT),
+        };
+        header.set(SPANS_LINES_BIT, spans_lines);
+        header.set(MARKED_BIT, mark);
+        header
+    }
 
-    /// If this object must not be evacuated (moved) by the collector.
-    pinned: bool,
+    /// Return the header word.
+    fn load(&self) -> usize {
+        self.word.load(Ordering::Acquire)
+    }
+
+    /// Return if the flag `bit` is set.
+    fn get(&self, bit: usize) -> bool {
+        self.load() & bit != 0
+    }
+
+    /// Set the flag `bit` to `value` and return the previous value.
+    fn set(&self, bit: usize, value: bool) -> bool {
+        let previous = if value {
+            self.word.fetch_or(bit, Ordering::AcqRel)
+        } else {
+            self.word.fetch_and(!bit, Ordering::AcqRel)
+        };
+        previous & bit != 0
+    }
+
+    /// Return the reference count.
+    fn reference_count(&self) -> usize {
+        self.load() & RC_MAX
+    }
 
-    /// If this object was never touched by the collectors.
-    new: bool,
+    /// Set the reference count to `count`.
+    fn set_reference_count(&self, count: usize) {
+        debug_assert!(count <= RC_MAX, "Reference count {} is too large", count);
+        self.update(|word| Some((word & !RC_MAX) | count));
+    }
+
+    /// Atomically replace the header word with the result of `f` unless it
+    /// returns `None`. Return the previous word.
+    fn update<F>(&self, f: F) -> usize where F: FnMut(usize) -> Option<usize> {
+        match self.word.fetch_update(Ordering::AcqRel, Ordering::Acquire, f) {
+            Ok(word) | Err(word) => word,
+        }
+    }
+
+    /// Return the number of array elements.
+    fn length(&self) -> usize {
+        self.load() >> LENGTH_SHIFT
+    }
+}
+
+impl PartialEq for GCHeader {
+    fn eq(&self, other: &GCHeader) -> bool {
+        self.load() == other.load()
+    }
 }
 
 /// The `GCRTTI` contains runtime type information about an object for the
@@ -57,14 +136,38 @@ pub struct GCRTTI {
 
     /// How many pointers to other objects does this object contain.
     members: libc::size_t,
+
+    /// The function called after the object was found dead or `None`.
+    finalizer: Option<GCFinalizer>,
+
+    /// The byte offsets of the `members` pointers within the object or null
+    /// if they directly follow the `GCObject`.
+    member_offsets: *const libc::size_t,
+
+    /// The size of an array element in bytes or zero if the objects are no
+    /// arrays.
+    ///
+    /// The elements directly follow the `object_size` bytes of the object.
+    element_size: libc::size_t,
+
+    /// How many pointers to other objects each array element contains. The
+    /// pointers must be at the start of the element.
+    element_members: libc::size_t,
 }
 
+/// A finalizer called with an object that was found dead by a collection.
+///
+/// It runs after the collection completed and may resurrect the object by
+/// storing a reference to it. Every object is finalized at most once.
+pub type GCFinalizer = extern fn(GCObjectRef);
+
 /// The `GCObject` is the base struct for every object managed by the garbage
 /// collector.
 ///
 /// Please include this as the first member in your object structs. The
 /// members of this object _must_ be a contiguous array of `GCobject` pointers
-/// of size `rtti.members`.
+/// of size `rtti.members` directly following the `GCObject`, unless their
+/// offsets are set with `GCRTTI::with_member_offsets()`.
 #[repr(C)]
 #[derive(PartialEq)]
 pub struct GCObject {
@@ -79,6 +182,11 @@ pub struct GCObject {
 /// A type alias for the mutable `GCObject` pointer.
 pub type GCObjectRef = *mut GCObject;
 
+// A `GCRTTI` is never modified after its construction and may be shared by
+// the mutator threads. The member offsets must point to static data.
+unsafe impl Send for GCRTTI {}
+unsafe impl Sync for GCRTTI {}
+
 impl GCRTTI {
     /// Create a new `GCRTTI` for an object with `object_size` bytes and
     /// `members` members.
@@ -86,9 +194,41 @@ impl GCRTTI {
         GCRTTI {
             object_size: object_size as libc::size_t,
             members: members as libc::size_t,
+            finalizer: None,
+            member_offsets: ptr::null(),
+            element_size: 0,
+            element_members: 0,
         }
     }
 
+    /// Make the objects arrays of elements of `element_size` bytes, each
+    /// starting with `element_members` pointers.
+    ///
+    /// Allocate them with `RCImmixCons::allocate_array()`.
+    pub fn with_elements(mut self, element_size: usize, element_members: usize) -> GCRTTI {
+        debug_assert!(element_members * mem::size_of::<GCObjectRef>() <= element_size,
+                      "Element members exceed the element size {}", element_size);
+        self.element_size = element_size as libc::size_t;
+        self.element_members = element_members as libc::size_t;
+        self
+    }
+
+    /// Set the byte offsets of the pointers within the object. This also
+    /// sets the number of members.
+    ///
+    /// Use this if the pointers do not directly follow the `GCObject`.
+    pub fn with_member_offsets(mut self, member_offsets: &'static [usize]) -> GCRTTI {
+        self.members = member_offsets.len() as libc::size_t;
+        self.member_offsets = member_offsets.as_ptr() as *const libc::size_t;
+        self
+    }
+
+    /// Set the `finalizer` called for objects of this type.
+    pub fn with_finalizer(mut self, finalizer: GCFinalizer) -> GCRTTI {
+        self.finalizer = Some(finalizer);
+        self
+    }
+
     /// Return the objects size in bytes.
     pub fn object_size(&self) -> usize {
         self.object_size as usize
@@ -98,179 +238,336 @@ impl GCRTTI {
     pub fn members(&self) -> usize {
         self.members as usize
     }
+
+    /// Return the finalizer or `None`.
+    pub fn finalizer(&self) -> Option<GCFinalizer> {
+        self.finalizer
+    }
+
+    /// Return the size of an array element in bytes or zero if the objects
+    /// are no arrays.
+    pub fn element_size(&self) -> usize {
+        self.element_size as usize
+    }
+
+    /// Return the number of pointers in each array element.
+    pub fn element_members(&self) -> usize {
+        self.element_members as usize
+    }
+
+    /// Return the size in bytes of an object with `length` array elements
+    /// rounded up to the target_pointer_width or `None` on overflow.
+    ///
+    /// The `length` must fit into the `GCHeader` (see `MAX_LENGTH`).
+    pub fn instance_size(&self, length: usize) -> Option<usize> {
+        if length > MAX_LENGTH {
+            return None;
+        }
+        let align = mem::size_of::<usize>();
+        length.checked_mul(self.element_size())
+              .and_then(|size| size.checked_add(self.object_size() + align - 1))
+              .map(|size| size & !(align - 1))
+    }
+
+    /// Return the byte offset of the member at position `num` within the
+    /// object.
+    ///
+    /// The members of array elements follow the `members` of the object.
+    ///
+    /// Panics if `num` is beyond the `members` and the elements have no
+    /// members.
+    pub fn member_offset(&self, num: usize) -> usize {
+        if num >= self.members() {
+            let element_num = num - self.members();
+            let element_members = self.element_members();
+            assert!(element_members > 0, "Member {} is out of bounds", num);
+            self.object_size() + (element_num / element_members) * self.element_size()
+                + (element_num % element_members) * mem::size_of::<GCObjectRef>()
+        } else if self.member_offsets.is_null() {
+            mem::size_of::<GCObject>() + num * mem::size_of::<GCObjectRef>()
+        } else {
+            unsafe{ *self.member_offsets.offset(num as isize) as usize }
+        }
+    }
 }
 
 impl GCObject {
     /// Create a new `GCObject` with `rtti` as the runtime typeinformation
-    /// struct pointer and the current `mark` value.
-    pub fn new(rtti: *const GCRTTI, mark: bool) -> GCObject {
-        debug!("GCobject::new(rtti={:p}, mark={})", rtti, mark);
-        let size = unsafe{ (*rtti).object_size() };
+    /// struct pointer, `length` array elements and the current `mark` value.
+    ///
+    /// The `length` must be zero if the `rtti` does not describe arrays.
+    pub fn new(rtti: *const GCRTTI, length: usize, mark: bool) -> GCObject {
+        trace!("GCobject::new(rtti={:p}, length={}, mark={})", rtti, length, mark);
+        let size = unsafe{ (*rtti).instance_size(length) }
+                       .expect("Invalid array length");
         GCObject {
-            header: GCHeader {
-                reference_count: 0,
-                spans_lines: size > LINE_SIZE,
-                forwarded: false,
-                logged: false,
-                marked: mark,
-                pinned: false,
-                new: true,
-            },
+            header: GCHeader::new(length, size > LINE_SIZE, mark),
             rtti: rtti,
         }
     }
 
     /// Set the `logged` state and return the previous value.
-    pub fn set_logged(&mut self, new: bool) -> bool {
-        debug!("Set object {:p} logged={}", self, new);
-        let logged = self.header.logged;
-        self.header.logged = new;
-        logged
+    pub fn set_logged(&self, new: bool) -> bool {
+        trace!("Set object {:p} logged={}", self, new);
+        self.header.set(LOGGED_BIT, new)
+    }
+
+    /// Return if this object is in the `modBuffer` of the `RCCollector`.
+    pub fn is_logged(&self) -> bool {
+        self.header.get(LOGGED_BIT)
+    }
+
+    /// Return if this object was never touched by the collectors.
+    pub fn is_new(&self) -> bool {
+        self.header.get(NEW_BIT)
     }
 
     /// Set the `marked` state and return if the state has not
     /// changed.
-    pub fn set_marked(&mut self, next: bool) -> bool {
-        debug!("Set object {:p} marked={}", self, next);
-        let marked = self.header.marked;
-        self.header.marked = next;
-        marked == next
+    ///
+    /// Only one of several threads marking the same object concurrently
+    /// sees the state changed.
+    pub fn set_marked(&self, next: bool) -> bool {
+        trace!("Set object {:p} marked={}", self, next);
+        self.header.set(MARKED_BIT, next) == next
+    }
+
+    /// Set the `marked` state and return if the state has not changed. If
+    /// it changed the reference count is reset to zero in the same atomic
+    /// step (see `reset_reference_count()`).
+    ///
+    /// A thread that sees the object marked by another thread can increment
+    /// the reference count without racing with the reset.
+    pub fn set_marked_and_reset_count(&self, next: bool) -> bool {
+        trace!("Set object {:p} marked={} and reset the reference count", self, next);
+        let mark = if next { MARKED_BIT } else { 0 };
+        let word = self.header.update(|word| match word & MARKED_BIT == mark {
+            true => None,
+            false => Some((word & !MARKED_BIT & !RC_MAX) | mark),
+        });
+        word & MARKED_BIT == mark
     }
 
     /// Return if this object is currently marked with `next`.
     pub fn is_marked(&self, next: bool) -> bool {
-        self.header.marked == next
+        self.header.get(MARKED_BIT) == next
     }
 
     /// Set the `pinned` state for this object.
-    pub fn set_pinned(&mut self, pinned: bool) {
-        debug!("Set object {:p} pinned={}", self, pinned);
-        self.header.pinned = pinned;
+    pub fn set_pinned(&self, pinned: bool) {
+        trace!("Set object {:p} pinned={}", self, pinned);
+        self.header.set(PINNED_BIT, pinned);
     }
 
     /// Return if this object is pinned.
     pub fn is_pinned(&self) -> bool {
-        self.header.pinned
+        self.header.get(PINNED_BIT)
     }
 
     /// Set the `forwarded` state and install a forewarding pointer to `new`.
+    ///
+    /// The pointer is installed before the state is set, so a thread that
+    /// sees the `forwarded` state also sees the pointer.
     pub fn set_forwarded(&mut self, new: GCObjectRef) {
-        debug!("Set object {:p} forwarded to {:p}", self, new);
-        self.header.forwarded = true;
+        trace!("Set object {:p} forwarded to {:p}", self, new);
         self.rtti = new as *const GCRTTI;
+        self.header.set(FORWARDED_BIT, true);
     }
 
     /// Return a pointer to the forwarded object if this object was forwarded,
     /// otherwise `None`.
     pub fn is_forwarded(&self) -> Option<GCObjectRef> {
-        if self.header.forwarded {
+        if self.header.get(FORWARDED_BIT) {
             Some(self.rtti as GCObjectRef)
         } else {
             None
         }
     }
 
+    /// Return the finalizer from the `GCRTTI` of this object or `None`.
+    ///
+    /// This must not be called on a forwarded object.
+    pub fn finalizer(&self) -> Option<GCFinalizer> {
+        unsafe{ (*self.rtti).finalizer() }
+    }
+
     /// Returns if this object spans lines (is greater than `LINE_SIZE`
     /// bytes).
     pub fn spans_lines(&self) -> bool {
-        self.header.spans_lines
+        self.header.get(SPANS_LINES_BIT)
     }
 
-    /// Return the objects size in bytes.
-    ///
-    /// This rounds the size stored in the `GCRTTI` struct up to the
-    /// target_pointer_width.
-    #[cfg(target_pointer_width = "32")]
-    pub fn object_size(&self) -> usize {
-        let size = unsafe{ (*self.rtti).object_size() };
-        return size + (size % 4)
+    /// Return the number of array elements of this object.
+    pub fn length(&self) -> usize {
+        self.header.length()
     }
 
-    /// Return the objects size in bytes.
+    /// Return the objects size in bytes including its array elements.
     ///
-    /// This rounds the size stored in the `GCRTTI` struct up to the
-    /// target_pointer_width.
-    #[cfg(target_pointer_width = "64")]
+    /// This rounds the size up to the target_pointer_width (see
+    /// `GCRTTI::instance_size()`).
     pub fn object_size(&self) -> usize {
-        let size = unsafe{ (*self.rtti).object_size() };
-        size + (size % 8)
+        unsafe{ (*self.rtti).instance_size(self.length()) }
+            .expect("Invalid array length")
     }
 
     /// Decrement the referece counter and return true if the reference count
     /// is zero.
     ///
-    /// This will not decrement the reference count if it is already zero.
-    pub fn decrement(&mut self) -> bool {
-        if self.header.reference_count == 0 {
+    /// This will not decrement the reference count if it is already zero or
+    /// stuck at the maximum (see `RC_BITS`).
+    pub fn decrement(&self) -> bool {
+        let word = self.header.update(|word| match word & RC_MAX {
+            0 | RC_MAX => None,
+            _ => Some(word - 1),
+        });
+        let count = word & RC_MAX;
+        if count == 0 || count == RC_MAX {
             return false;
         }
-        self.header.reference_count -= 1;
-        debug!("Decrement object {:p} to {}", self, self.header.reference_count);
-        self.header.reference_count == 0
+        trace!("Decrement object {:p} to {}", self, count - 1);
+        count == 1
     }
 
     /// Increment the reference count, set the `new` state to `false` and
     /// return the previous `new` state.
-    pub fn increment(&mut self) -> bool {
-        self.header.reference_count += 1;
-        debug!("Increment object {:p} to {} (new={})", self,
-            self.header.reference_count, self.header.new);
-        if self.header.new {
-            self.header.new = false;
-            true
-        } else {
-            false
-        }
+    ///
+    /// The reference count sticks at the maximum (see `RC_BITS`).
+    pub fn increment(&self) -> bool {
+        let word = self.header.update(|word| {
+            let count = if word & RC_MAX < RC_MAX { 1 } else { 0 };
+            Some((word + count) & !NEW_BIT)
+        });
+        trace!("Increment object {:p} to {} (new={})", self,
+            self.header.reference_count(), word & NEW_BIT != 0);
+        word & NEW_BIT != 0
+    }
+
+    /// Return the reference count.
+    pub fn reference_count(&self) -> usize {
+        self.header.reference_count()
+    }
+
+    /// Return the `GCRTTI` of this object.
+    ///
+    /// This must not be called on a forwarded object.
+    pub fn rtti(&self) -> *const GCRTTI {
+        self.rtti
+    }
+
+    /// Reset the reference count to zero.
+    ///
+    /// The immix tracing collector recomputes the reference counts of all
+    /// reachable objects. This also resets stuck reference counts.
+    pub fn reset_reference_count(&self) {
+        self.header.set_reference_count(0);
+    }
+
+    /// Return the number of members of this object including the members of
+    /// its array elements.
+    pub fn member_count(&self) -> usize {
+        unsafe{ (*self.rtti).members() + self.length() * (*self.rtti).element_members() }
     }
 
     /// Set the member at position `num` in the member array to `member`.
     pub fn set_member(&mut self, num: usize, member: GCObjectRef) {
-        unsafe {
-            let base: *mut GCObjectRef = mem::transmute(&self.rtti);
-            let address = base.offset((num + 1) as isize);
-            *address = member;
-        }
+        unsafe{ *self.member_address(num) = member; }
+    }
+
+    /// Return the member at position `num` in the member array (which may be
+    /// null).
+    pub fn member(&mut self, num: usize) -> GCObjectRef {
+        unsafe{ *self.member_address(num) }
+    }
+
+    /// Set the member at position `num` to `member` and return the previous
+    /// member.
+    ///
+    /// This is the store of `RCImmixCons::write_member()`, which also
+    /// performs the write barrier.
+    pub fn store_member(&mut self, num: usize, member: GCObjectRef) -> GCObjectRef {
+        let address = self.member_address(num);
+        unsafe{ mem::replace(&mut *address, member) }
     }
 
     /// Return an iterator of all the members of this object that are not null.
     ///
-    /// The members of an objects are the `GCRTTI.members` pointers after the
-    /// `GCHeader.rtti` pointer in the `GCObject`.
+    /// The members of an objects are the `GCRTTI.members` pointers at the
+    /// offsets given by `GCRTTI::member_offset()` followed by the pointers in
+    /// its array elements.
     pub fn children(&mut self) -> GCObjectRefIter {
-        let base: *const GCObjectRef = unsafe{ mem::transmute(&self.rtti) };
-        let members = unsafe{ (*self.rtti).members() };
-        debug!("Requested children for object: {:p} (rtti: {:p}, count: {})",
+        GCObjectRefIter::iter(self.enumerate_children())
+    }
+
+    /// Return an iterator of all the members of this object that are not null
+    /// together with their position `num` (see `set_member()`).
+    pub fn enumerate_children(&mut self) -> GCObjectMemberIter {
+        let members = self.member_count();
+        trace!("Requested children for object: {:p} (rtti: {:p}, count: {})",
                self, self.rtti, members);
-        GCObjectRefIter::iter(1..(members + 1), base)
+        GCObjectMemberIter::iter(0..members, self)
     }
 }
 
-/// An `Iterator` over a `range` of `GCObjectRef`s starting at `base`.
-pub struct GCObjectRefIter {
+impl GCObject {
+    /// Return the address of the member at position `num`.
+    fn member_address(&mut self, num: usize) -> *mut GCObjectRef {
+        let offset = unsafe{ (*self.rtti).member_offset(num) };
+        debug_assert!(offset + mem::size_of::<GCObjectRef>() <= self.object_size(),
+                      "Member {} of object {:p} is out of bounds", num, self);
+        unsafe{ (self as *mut GCObject as *mut u8).offset(offset as isize) as *mut GCObjectRef }
+    }
+}
+
+/// An `Iterator` over a `range` of members of `object` that are not null
+/// yielding their position and value.
+pub struct GCObjectMemberIter {
     range: Range<usize>,
-    base: *const GCObjectRef,
+    object: GCObjectRef,
 }
 
-impl GCObjectRefIter {
-    /// Create a new `GCObjectRefIter` over `range`, starting at `base`.
-    pub fn iter(range: Range<usize>, base: *const GCObjectRef) -> GCObjectRefIter {
-        GCObjectRefIter {
+impl GCObjectMemberIter {
+    /// Create a new `GCObjectMemberIter` over `range` of `object`.
+    pub fn iter(range: Range<usize>, object: GCObjectRef) -> GCObjectMemberIter {
+        GCObjectMemberIter {
             range: range,
-            base: base,
+            object: object,
         }
     }
 }
 
-impl Iterator for GCObjectRefIter {
-    type Item = GCObjectRef;
+impl Iterator for GCObjectMemberIter {
+    type Item = (usize, GCObjectRef);
 
-    fn next(&mut self) -> Option<GCObjectRef> {
+    fn next(&mut self) -> Option<(usize, GCObjectRef)> {
         for num in self.range.by_ref() {
-            let child = unsafe{ *self.base.offset(num as isize) };
+            let child = unsafe{ *(*self.object).member_address(num) };
             if !child.is_null() {
-                return Some(child);
+                return Some((num, child));
             } else { continue }
         }
         None
     }
 }
+
+/// An `Iterator` over the members of an object that are not null.
+pub struct GCObjectRefIter {
+    members: GCObjectMemberIter,
+}
+
+impl GCObjectRefIter {
+    /// Create a new `GCObjectRefIter` over the given `members`.
+    pub fn iter(members: GCObjectMemberIter) -> GCObjectRefIter {
+        GCObjectRefIter {
+            members: members,
+        }
+    }
+}
+
+impl Iterator for GCObjectRefIter {
+    type Item = GCObjectRef;
+
+    fn next(&mut self) -> Option<GCObjectRef> {
+        self.members.next().map(|(_, child)| child)
+    }
+}
@@ -0,0 +1,202 @@
+// Copyright (c) <2015> <lummax>
+// Licensed under MIT (http://opensource.org/licenses/MIT)
+
+//! The heap dump file format.
+//!
+//! A heap dump is written by `RCImmixCons::dump_heap()` and can be read back
+//! with `HeapDump::read()`. All values are unsigned 64 bit little-endian
+//! integers (`u64`), except the magic:
+//!
+//! ```text
+//! magic         8 bytes "RCXHEAP\0"
+//! version       u64 (HEAP_DUMP_VERSION)
+//! root_count    u64
+//! roots         root_count * u64 (object addresses)
+//! object_count  u64
+//! objects       object_count * object
+//!
+//! object:
+//!   address          u64
+//!   size             u64 (in bytes, including the array elements)
+//!   rtti             u64 (address of the GCRTTI)
+//!   reference_count  u64
+//!   flags            u64 (bit 0: pinned, bit 1: marked)
+//!   block            u64 (address of the immix block or 0 for the LOS)
+//!   edge_count       u64
+//!   edges            edge_count * u64 (addresses of the members)
+//! ```
+//!
+//! The objects are sorted by address and the roots may contain duplicates.
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+/// The magic bytes at the start of every heap dump.
+pub const HEAP_DUMP_MAGIC: &'static [u8; 8] = b"RCXHEAP\0";
+
+/// The version of the heap dump format written by this library.
+pub const HEAP_DUMP_VERSION: u64 = 1;
+
+/// The flag of a pinned object.
+const PINNED_FLAG: u64 = 1 << 0;
+
+/// The flag of an object marked with the current live mark.
+const MARKED_FLAG: u64 = 1 << 1;
+
+/// A snapshot of the heap with all objects, their references and the roots.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeapDump {
+    /// The addresses of the roots found on the stacks, in the registers, in
+    /// the static roots and the objects waiting for finalization.
+    pub roots: Vec<usize>,
+
+    /// The objects that were not reclaimed yet sorted by address.
+    pub objects: Vec<DumpedObject>,
+}
+
+/// An object in a `HeapDump`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DumpedObject {
+    /// The address of the object.
+    pub address: usize,
+
+    /// The size of the object in bytes including its array elements.
+    pub size: usize,
+
+    /// The address of the `GCRTTI` of the object.
+    pub rtti: usize,
+
+    /// The reference count of the object.
+    pub reference_count: usize,
+
+    /// Whether the object is pinned.
+    pub pinned: bool,
+
+    /// Whether the object is marked with the current live mark (it was
+    /// reached by the last tracing collection or allocated afterwards).
+    pub marked: bool,
+
+    /// The address of the immix block of the object or `None` if it is in
+    /// the large object space.
+    pub block: Option<usize>,
+
+    /// The addresses of the members of the object that are not null.
+    pub edges: Vec<usize>,
+}
+
+impl HeapDump {
+    /// Read a heap dump from the file at `path`.
+    pub fn read<P: AsRef<Path>>(path: P) -> io::Result<HeapDump> {
+        HeapDump::read_from(&mut BufReader::new(File::open(path)?))
+    }
+
+    /// Write this heap dump to the file at `path`.
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()
+    }
+
+    /// Read a heap dump from the `reader`.
+    ///
+    /// Fails with `io::ErrorKind::InvalidData` if the magic or the version
+    /// do not match.
+    pub fn read_from<R: Read>(reader: &mut R) -> io::Result<HeapDump> {
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != HEAP_DUMP_MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      "Not a heap dump"));
+        }
+        let version = read_u64(reader)?;
+        if version != HEAP_DUMP_VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      format!("Unsupported heap dump version {}", version)));
+        }
+        let roots = read_addresses(reader)?;
+        let object_count = read_u64(reader)?;
+        let mut objects = Vec::new();
+        for _ in 0..object_count {
+            let address = read_u64(reader)? as usize;
+            let size = read_u64(reader)? as usize;
+            let rtti = read_u64(reader)? as usize;
+            let reference_count = read_u64(reader)? as usize;
+            let flags = read_u64(reader)?;
+            let block = read_u64(reader)? as usize;
+            objects.push(DumpedObject {
+                address: address,
+                size: size,
+                rtti: rtti,
+                reference_count: reference_count,
+                pinned: flags & PINNED_FLAG != 0,
+                marked: flags & MARKED_FLAG != 0,
+                block: if block == 0 { None } else { Some(block) },
+                edges: read_addresses(reader)?,
+            });
+        }
+        Ok(HeapDump {
+            roots: roots,
+            objects: objects,
+        })
+    }
+
+    /// Write this heap dump to the `writer`.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(HEAP_DUMP_MAGIC)?;
+        write_u64(writer, HEAP_DUMP_VERSION)?;
+        write_addresses(writer, &self.roots)?;
+        write_u64(writer, self.objects.len() as u64)?;
+        for object in &self.objects {
+            let mut flags = 0;
+            if object.pinned {
+                flags |= PINNED_FLAG;
+            }
+            if object.marked {
+                flags |= MARKED_FLAG;
+            }
+            write_u64(writer, object.address as u64)?;
+            write_u64(writer, object.size as u64)?;
+            write_u64(writer, object.rtti as u64)?;
+            write_u64(writer, object.reference_count as u64)?;
+            write_u64(writer, flags)?;
+            write_u64(writer, object.block.unwrap_or(0) as u64)?;
+            write_addresses(writer, &object.edges)?;
+        }
+        Ok(())
+    }
+
+    /// Return the object at `address` or `None`.
+    pub fn object(&self, address: usize) -> Option<&DumpedObject> {
+        self.objects.binary_search_by_key(&address, |o| o.address)
+                    .ok()
+                    .map(|index| &self.objects[index])
+    }
+}
+
+/// Read a little-endian `u64`.
+fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+/// Write a little-endian `u64`.
+fn write_u64<W: Write>(writer: &mut W, value: u64) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+/// Read a count followed by that many addresses.
+fn read_addresses<R: Read>(reader: &mut R) -> io::Result<Vec<usize>> {
+    let count = read_u64(reader)?;
+    (0..count).map(|_| read_u64(reader).map(|a| a as usize)).collect()
+}
+
+/// Write the number of `addresses` followed by the `addresses`.
+fn write_addresses<W: Write>(writer: &mut W, addresses: &[usize]) -> io::Result<()> {
+    write_u64(writer, addresses.len() as u64)?;
+    for &address in addresses {
+        write_u64(writer, address as u64)?;
+    }
+    Ok(())
+}
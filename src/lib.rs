@@ -1,9 +1,6 @@
 // Copyright (c) <2015> <lummax>
 // Licensed under MIT (http://opensource.org/licenses/MIT)
 
-#![feature(asm)]
-#![feature(link_llvm_intrinsics)]
-
 //! This is an implementation of the `RCImmixCons` garbage collector.
 //!
 //! A conservative reference counting garbage collector with the immix heap
@@ -25,38 +22,76 @@
 //!
 //! To use this garbage collector your objects must be structs derived from
 //! `GCObject`. Allocation and collection is done using `RCImmixCons`.
+//!
+//! Rust programs can use `Gc<T>` instead, which generates the `GCRTTI` of
+//! any `T` implementing `Trace` (see `#[derive(Trace)]` in the
+//! `rcimmixcons_derive` crate).
 
 extern crate libc;
-extern crate bit_set;
 extern crate vec_map;
+#[macro_use] extern crate log;
 
-use std::ptr;
+use std::{io, ptr};
+use std::ffi::{CStr, CString};
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
 
-pub use self::gc_object::{GCHeader, GCRTTI, GCObject, GCObjectRef};
+pub use self::gc_object::{GCHeader, GCRTTI, GCObject, GCObjectRef, GCFinalizer};
+pub use self::config::{GCConfig, GCConfigError};
+pub use self::spaces::WeakRef;
+pub use self::stats::GCStats;
+pub use self::events::{GCEvent, GCEventKind};
+pub use self::spaces::CollectionType;
+pub use self::heap_dump::{HeapDump, DumpedObject, HEAP_DUMP_VERSION};
+pub use self::gc::{Gc, GcCell, Trace};
 
 mod macros;
 mod constants;
+mod config;
+mod events;
+mod gc;
 mod gc_object;
+mod heap_dump;
 mod spaces;
 mod stack;
+mod stats;
 
 /// The `RCImmixCons` garbage collector.
 ///
 /// The `allocate()` function will return a pointer to a `GCObject`. Please
 /// see the documentation of `GCHeader`, `GCRTTI` and `GCObject` for details.
 ///
-/// Always call `write_barrier()` on an object before modifying its members.
+/// Always call `write_barrier()` on an object before modifying its members
+/// or set them with `write_member()`.
+///
+/// The `RCImmixCons` can be shared by multiple threads. Every thread except
+/// the one that created the `RCImmixCons` must call `register_thread()`
+/// before using it and every thread must call `unregister_thread()` before it
+/// exits. A collection stops all registered threads the next time they call
+/// into the garbage collector (or `safepoint_poll()`). Threads that execute
+/// code without calling into the garbage collector for a long time (or block)
+/// must poll or wrap this code in `enter_native()` and `leave_native()`.
 pub struct RCImmixCons {
     /// The different spaces of this garbage collector.
     spaces: spaces::Spaces,
 }
 
 impl RCImmixCons {
-    /// Create a new `RCImmixCons`.
+    /// Create a new `RCImmixCons` with the default `GCConfig`.
     pub fn new() -> RCImmixCons {
-        RCImmixCons {
-            spaces: spaces::Spaces::new(),
-        }
+        RCImmixCons::with_config(GCConfig::new())
+            .expect("The default configuration is invalid.")
+    }
+
+    /// Create a new `RCImmixCons` with the given `config` or return an error
+    /// if the configuration is invalid.
+    pub fn with_config(config: GCConfig) -> Result<RCImmixCons, GCConfigError> {
+        config.validate()?;
+        Ok(RCImmixCons {
+            spaces: spaces::Spaces::new(&config),
+        })
     }
 
     /// Allocate a new object described by the `rtti` or returns `None`.
@@ -64,10 +99,21 @@ impl RCImmixCons {
     /// This may trigger a garbage collection if the allocation was not
     /// succussful. If there is still no memory to fullfill the allocation
     /// request return `None`.
-    pub fn allocate(&mut self, rtti: *const GCRTTI) -> Option<GCObjectRef> {
-        self.spaces.allocate(rtti)
+    pub fn allocate(&self, rtti: *const GCRTTI) -> Option<GCObjectRef> {
+        self.allocate_array(rtti, 0)
+    }
+
+    /// Allocate a new array object described by the `rtti` with `length`
+    /// elements or returns `None`.
+    ///
+    /// The `rtti` must describe arrays (see `GCRTTI::with_elements()`). The
+    /// elements directly follow the `GCRTTI.object_size` bytes of the object
+    /// and the `length` is stored in its `GCHeader`. This may trigger a
+    /// garbage collection like `allocate()`.
+    pub fn allocate_array(&self, rtti: *const GCRTTI, length: usize) -> Option<GCObjectRef> {
+        self.spaces.allocate(rtti, length)
             .or_else(|| { self.collect(true, true);
-                          self.spaces.allocate(rtti) })
+                          self.spaces.allocate(rtti, length) })
     }
 
     /// Trigger a garbage collection.
@@ -75,12 +121,135 @@ impl RCImmixCons {
     /// This will always run the referece counting collector. If `evacuation`
     /// is set the collectors will try to evacuate. If `cycle_collect` is set
     /// the immix tracing collector will be used.
-    #[allow(unused_variables)]
-    pub fn collect(&mut self, evacuation: bool, cycle_collect: bool) {
+    pub fn collect(&self, evacuation: bool, cycle_collect: bool) {
+        // Calling this function befor **ANYTHING** is important to save the
+        // callee save registers.
+        let registers = stack::Stack::get_registers();
+        self.spaces.collect(evacuation, cycle_collect, registers)
+    }
+
+    /// Return a snapshot of the heap statistics.
+    ///
+    /// The live objects are counted by the last collection.
+    pub fn stats(&self) -> GCStats {
+        self.spaces.stats()
+    }
+
+    /// Return the violations found by the heap verification after the last
+    /// collection (see `GCConfig::with_verify_heap()`).
+    ///
+    /// Each violation describes a broken invariant with the addresses of the
+    /// objects involved. The list is empty if the heap is not verified.
+    pub fn verify_violations(&self) -> Vec<String> {
+        self.spaces.verify_violations()
+    }
+
+    /// Write a `HeapDump` of all objects, their members and the current roots
+    /// to the file at `path`.
+    ///
+    /// All other threads are stopped while the heap is inspected. Read the
+    /// file back using `HeapDump::read()`.
+    pub fn dump_heap<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        // Calling this function befor **ANYTHING** is important to save the
+        // callee save registers.
+        let registers = stack::Stack::get_registers();
+        self.spaces.dump_heap(registers).write(path)
+    }
+
+    /// Return the graph of the objects reachable from the current roots in
+    /// the Graphviz DOT format for debugging.
+    ///
+    /// Each node is labeled with the address, reference count, space and
+    /// flags of the object. Roots are drawn as bold boxes.
+    pub fn object_graph_dot(&self) -> String {
+        let registers = stack::Stack::get_registers();
+        self.spaces.object_graph_dot(None, registers)
+    }
+
+    /// Return the graph of the objects reachable from `object` in the
+    /// Graphviz DOT format like `object_graph_dot()`.
+    pub fn object_graph_dot_from(&self, object: GCObjectRef) -> String {
+        let registers = stack::Stack::get_registers();
+        self.spaces.object_graph_dot(Some(object), registers)
+    }
+
+    /// Register a `hook` called at the start and end of every collection
+    /// (see `GCEvent`).
+    ///
+    /// The hooks run on the collecting thread. At the start of a collection
+    /// all other threads are stopped and the GC lock is held, so they must
+    /// not use the garbage collector (this panics in debug builds). At the
+    /// end of a collection the threads are restarted and the GC lock is
+    /// released, so they may use it, for example to call `stats()`.
+    pub fn add_event_hook<F: Fn(&GCEvent) + Send + Sync + 'static>(&self, hook: F) {
+        self.spaces.add_event_hook(Arc::new(hook));
+    }
+
+    /// Register the current thread as a mutator thread.
+    ///
+    /// Returns `false` if the thread was already registered.
+    pub fn register_thread(&self) -> bool {
+        self.spaces.register_thread()
+    }
+
+    /// Unregister the current thread.
+    ///
+    /// The thread must not use the garbage collector or keep references to
+    /// garbage collected objects afterwards. Returns `false` if the thread was
+    /// not registered.
+    pub fn unregister_thread(&self) -> bool {
+        self.spaces.unregister_thread()
+    }
+
+    /// Poll for a requested collection.
+    ///
+    /// If another thread requested a collection, the current thread is parked
+    /// until the collection completed. Call this regularly in long running
+    /// code that does not call into the garbage collector otherwise.
+    pub fn safepoint_poll(&self) {
+        self.spaces.safepoint_poll();
+    }
+
+    /// Mark the current thread as executing native code.
+    ///
+    /// Until `leave_native()` is called, the current thread must not access
+    /// the heap and collections can happen without its cooperation. Object
+    /// references in the callers stack frames and registers remain roots.
+    /// Returns `false` if the thread is not registered or already in native
+    /// code.
+    pub fn enter_native(&self) -> bool {
         // Calling this function befor **ANYTHING** is important to save the
-        // callee save registers on the stack.
+        // callee save registers of the caller.
         let registers = stack::Stack::get_registers();
-        self.spaces.collect(evacuation, cycle_collect)
+        self.spaces.enter_native(stack::Stack::get_stack_top(), registers)
+    }
+
+    /// Mark the current thread as executing mutator code again.
+    ///
+    /// This waits for a running collection to complete.
+    pub fn leave_native(&self) {
+        self.spaces.leave_native();
+    }
+
+    /// Push a frame of `count` precise root slots starting at `slots` onto
+    /// the shadow stack of the current thread.
+    ///
+    /// The slots must contain null or object references until the frame is
+    /// popped with `pop_roots()`. The collector updates the slots if the
+    /// objects are moved. Together with `GCConfig::with_scan_stack(false)`
+    /// this avoids the false positives of the conservative stack scanning.
+    /// Returns `false` if the current thread is not registered.
+    pub fn push_roots(&self, slots: *mut GCObjectRef, count: usize) -> bool {
+        self.spaces.push_roots(slots, count)
+    }
+
+    /// Pop the last frame pushed with `push_roots()` from the shadow stack of
+    /// the current thread.
+    ///
+    /// Returns `false` if the current thread is not registered or its shadow
+    /// stack is empty.
+    pub fn pop_roots(&self) -> bool {
+        self.spaces.pop_roots()
     }
 
     /// Set an address to an object reference as static root.
@@ -88,18 +257,175 @@ impl RCImmixCons {
     /// Use this to mark global/static variables as roots. This is needed, if
     /// the pointer to a garbage collected object does not reside on the stack
     /// or in any register.
-    pub fn set_static_root(&mut self, address: *const GCObjectRef) {
+    pub fn set_static_root(&self, address: *const GCObjectRef) {
         self.spaces.set_static_root(address);
     }
 
+    /// Remove a static root set with `set_static_root()`.
+    ///
+    /// Returns `false` if the `address` is not a static root.
+    pub fn remove_static_root(&self, address: *const GCObjectRef) -> bool {
+        self.spaces.remove_root_range(address)
+    }
+
+    /// Set the `count` consecutive object references starting at `start` as
+    /// static roots.
+    ///
+    /// Use this for arrays of object references. Null references are
+    /// ignored.
+    pub fn add_root_range(&self, start: *const GCObjectRef, count: usize) {
+        self.spaces.add_root_range(start, count);
+    }
+
+    /// Remove a root range added with `add_root_range()`.
+    ///
+    /// Returns `false` if there is no range starting at `start`.
+    pub fn remove_root_range(&self, start: *const GCObjectRef) -> bool {
+        self.spaces.remove_root_range(start)
+    }
+
+    /// Return a new `RootScope` that removes the static roots added to it
+    /// when it is dropped.
+    pub fn root_scope(&self) -> RootScope {
+        RootScope { collector: self, roots: Vec::new() }
+    }
+
+    /// Create a weak reference to `object`.
+    ///
+    /// The weak reference does not keep the `object` alive and is cleared
+    /// when the `object` is reclaimed. Returns `None` if the `object` is not
+    /// managed by this garbage collector. Destroy the weak reference using
+    /// `weak_destroy()`.
+    pub fn weak_create(&self, object: GCObjectRef) -> Option<WeakRef> {
+        self.spaces.weak_create(object)
+    }
+
+    /// Return the referent of the weak reference or `None` if it was
+    /// reclaimed.
+    pub fn weak_get(&self, weak_ref: &WeakRef) -> Option<GCObjectRef> {
+        self.spaces.safepoint_poll();
+        weak_ref.get()
+    }
+
+    /// Destroy a weak reference created with `weak_create()`.
+    pub fn weak_destroy(&self, weak_ref: WeakRef) {
+        self.spaces.weak_destroy(weak_ref);
+    }
+
+    /// Pin the `object`.
+    ///
+    /// A pinned object is never moved by the collectors and kept alive like
+    /// a root. Use this for objects passed to native code. The pins are
+    /// counted: the `object` is pinned until `unpin()` was called as often
+    /// as `pin()`. Returns `false` if the `object` is not managed by this
+    /// garbage collector.
+    pub fn pin(&self, object: GCObjectRef) -> bool {
+        self.spaces.safepoint_poll();
+        self.spaces.pin(object)
+    }
+
+    /// Remove one pin of the `object` set with `pin()`.
+    ///
+    /// Returns `false` if the `object` is not pinned.
+    pub fn unpin(&self, object: GCObjectRef) -> bool {
+        self.spaces.safepoint_poll();
+        self.spaces.unpin(object)
+    }
+
+    /// Pin the `object` until the returned `PinGuard` is dropped or return
+    /// `None` if the `object` is not managed by this garbage collector.
+    pub fn pin_guard(&self, object: GCObjectRef) -> Option<PinGuard> {
+        if self.pin(object) {
+            Some(PinGuard { collector: self, object: object })
+        } else {
+            None
+        }
+    }
+
     /// A write barrier for the given `object`.
     ///
     /// Call this function before modifying the members of this object!
-    pub fn write_barrier(&mut self, object: GCObjectRef) {
+    pub fn write_barrier(&self, object: GCObjectRef) {
         if self.spaces.write_barrier(object) {
             self.collect(false, false);
         }
     }
+
+    /// Set the member at position `num` of the `object` to `member`.
+    ///
+    /// This performs the write barrier for this member only and the store,
+    /// so there is no need to call `write_barrier()` before. Returns `false`
+    /// without storing if the `object` is not managed by this garbage
+    /// collector or has no member `num`.
+    pub fn write_member(&self, object: GCObjectRef, num: usize, member: GCObjectRef) -> bool {
+        match self.spaces.write_member(object, num, member) {
+            Some(collect) => {
+                if collect {
+                    self.collect(false, false);
+                }
+                true
+            },
+            None => false,
+        }
+    }
+}
+
+/// A pin of an object created with `RCImmixCons::pin_guard()`.
+///
+/// The object is unpinned when the `PinGuard` is dropped.
+pub struct PinGuard<'a> {
+    /// The collector that pinned the object.
+    collector: &'a RCImmixCons,
+
+    /// The pinned object.
+    object: GCObjectRef,
+}
+
+impl<'a> PinGuard<'a> {
+    /// Return the pinned object.
+    pub fn object(&self) -> GCObjectRef {
+        self.object
+    }
+}
+
+impl<'a> Drop for PinGuard<'a> {
+    fn drop(&mut self) {
+        self.collector.unpin(self.object);
+    }
+}
+
+/// A scope of static roots created with `RCImmixCons::root_scope()`.
+///
+/// The roots added to the scope are removed when the `RootScope` is
+/// dropped. The object references must stay valid until then.
+pub struct RootScope<'a> {
+    /// The collector the roots are registered with.
+    collector: &'a RCImmixCons,
+
+    /// The start addresses of the root ranges added to this scope.
+    roots: Vec<*const GCObjectRef>,
+}
+
+impl<'a> RootScope<'a> {
+    /// Set the object reference at `address` as static root.
+    pub fn add(&mut self, address: *const GCObjectRef) {
+        self.add_range(address, 1);
+    }
+
+    /// Set the `count` consecutive object references starting at `start` as
+    /// static roots.
+    pub fn add_range(&mut self, start: *const GCObjectRef, count: usize) {
+        self.collector.add_root_range(start, count);
+        self.roots.push(start);
+    }
+}
+
+impl<'a> Drop for RootScope<'a> {
+    fn drop(&mut self) {
+        for &start in self.roots.iter().rev() {
+            self.collector.remove_root_range(start);
+        }
+    }
 }
 
 #[no_mangle]
@@ -108,6 +434,21 @@ pub extern fn rcx_create() -> *mut RCImmixCons {
     Box::into_raw(Box::new(RCImmixCons::new()))
 }
 
+#[no_mangle]
+#[doc(hidden)]
+pub extern fn rcx_default_config() -> GCConfig {
+    GCConfig::new()
+}
+
+#[no_mangle]
+#[doc(hidden)]
+pub extern fn rcx_create_with_config(config: *const GCConfig) -> *mut RCImmixCons {
+    match RCImmixCons::with_config(unsafe{ *config }) {
+        Ok(collector) => Box::into_raw(Box::new(collector)),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
 #[no_mangle]
 #[doc(hidden)]
 pub extern fn rcx_allocate(this: *mut RCImmixCons, rtti: *const GCRTTI)
@@ -115,6 +456,19 @@ pub extern fn rcx_allocate(this: *mut RCImmixCons, rtti: *const GCRTTI)
     unsafe { (*this).allocate(rtti).unwrap_or(ptr::null_mut()) }
 }
 
+#[no_mangle]
+#[doc(hidden)]
+pub extern fn rcx_allocate_array(this: *mut RCImmixCons, rtti: *const GCRTTI,
+                                 length: libc::size_t) -> GCObjectRef {
+    unsafe { (*this).allocate_array(rtti, length as usize).unwrap_or(ptr::null_mut()) }
+}
+
+#[no_mangle]
+#[doc(hidden)]
+pub extern fn rcx_array_length(object: GCObjectRef) -> libc::size_t {
+    unsafe { (*object).length() as libc::size_t }
+}
+
 #[no_mangle]
 #[doc(hidden)]
 pub extern fn rcx_collect(this: *mut RCImmixCons, evacuation: bool, cycle_collect: bool) {
@@ -127,12 +481,175 @@ pub extern fn rcx_set_static_root(this: *mut RCImmixCons, address: *const GCObje
     unsafe { (*this).set_static_root(address) };
 }
 
+#[no_mangle]
+#[doc(hidden)]
+pub extern fn rcx_push_roots(this: *mut RCImmixCons, slots: *mut GCObjectRef,
+                             count: libc::size_t) -> bool {
+    unsafe { (*this).push_roots(slots, count as usize) }
+}
+
+#[no_mangle]
+#[doc(hidden)]
+pub extern fn rcx_pop_roots(this: *mut RCImmixCons) -> bool {
+    unsafe { (*this).pop_roots() }
+}
+
+#[no_mangle]
+#[doc(hidden)]
+pub extern fn rcx_remove_static_root(this: *mut RCImmixCons, address: *const GCObjectRef)
+    -> bool {
+    unsafe { (*this).remove_static_root(address) }
+}
+
+#[no_mangle]
+#[doc(hidden)]
+pub extern fn rcx_add_root_range(this: *mut RCImmixCons, start: *const GCObjectRef,
+                                 count: libc::size_t) {
+    unsafe { (*this).add_root_range(start, count as usize) };
+}
+
+#[no_mangle]
+#[doc(hidden)]
+pub extern fn rcx_remove_root_range(this: *mut RCImmixCons, start: *const GCObjectRef)
+    -> bool {
+    unsafe { (*this).remove_root_range(start) }
+}
+
 #[no_mangle]
 #[doc(hidden)]
 pub extern fn rcx_write_barrier(this: *mut RCImmixCons, object: GCObjectRef) {
     unsafe { (*this).write_barrier(object) };
 }
 
+#[no_mangle]
+#[doc(hidden)]
+pub extern fn rcx_write_member(this: *mut RCImmixCons, object: GCObjectRef,
+                               num: libc::size_t, member: GCObjectRef) -> bool {
+    unsafe { (*this).write_member(object, num as usize, member) }
+}
+
+#[no_mangle]
+#[doc(hidden)]
+pub extern fn rcx_pin(this: *mut RCImmixCons, object: GCObjectRef) -> bool {
+    unsafe { (*this).pin(object) }
+}
+
+#[no_mangle]
+#[doc(hidden)]
+pub extern fn rcx_unpin(this: *mut RCImmixCons, object: GCObjectRef) -> bool {
+    unsafe { (*this).unpin(object) }
+}
+
+#[no_mangle]
+#[doc(hidden)]
+pub extern fn rcx_weak_create(this: *mut RCImmixCons, object: GCObjectRef)
+    -> *mut GCObjectRef {
+    unsafe { (*this).weak_create(object).map_or(ptr::null_mut(), |w| w.into_raw()) }
+}
+
+#[no_mangle]
+#[doc(hidden)]
+pub extern fn rcx_weak_get(this: *mut RCImmixCons, weak_ref: *mut GCObjectRef)
+    -> GCObjectRef {
+    unsafe { (*this).weak_get(&WeakRef::from_raw(weak_ref)).unwrap_or(ptr::null_mut()) }
+}
+
+#[no_mangle]
+#[doc(hidden)]
+pub extern fn rcx_weak_destroy(this: *mut RCImmixCons, weak_ref: *mut GCObjectRef) {
+    unsafe { (*this).weak_destroy(WeakRef::from_raw(weak_ref)) };
+}
+
+#[no_mangle]
+#[doc(hidden)]
+pub extern fn rcx_get_stats(this: *mut RCImmixCons) -> GCStats {
+    unsafe { (*this).stats() }
+}
+
+#[no_mangle]
+#[doc(hidden)]
+pub extern fn rcx_verify_violations(this: *mut RCImmixCons,
+                                    report: Option<extern fn(*const libc::c_char,
+                                                             *mut libc::c_void)>,
+                                    user_data: *mut libc::c_void) -> libc::size_t {
+    let violations = unsafe { (*this).verify_violations() };
+    if let Some(report) = report {
+        for violation in &violations {
+            let violation = CString::new(violation.as_str()).unwrap();
+            report(violation.as_ptr(), user_data);
+        }
+    }
+    violations.len() as libc::size_t
+}
+
+#[no_mangle]
+#[doc(hidden)]
+pub extern fn rcx_dump_heap(this: *mut RCImmixCons, path: *const libc::c_char) -> bool {
+    let path = unsafe{ CStr::from_ptr(path) }.to_string_lossy().into_owned();
+    unsafe { (*this).dump_heap(path).is_ok() }
+}
+
+#[no_mangle]
+#[doc(hidden)]
+pub extern fn rcx_write_dot(this: *mut RCImmixCons, object: GCObjectRef,
+                            path: *const libc::c_char) -> bool {
+    let path = unsafe{ CStr::from_ptr(path) }.to_string_lossy().into_owned();
+    let dot = if object.is_null() {
+        unsafe { (*this).object_graph_dot() }
+    } else {
+        unsafe { (*this).object_graph_dot_from(object) }
+    };
+    File::create(path).and_then(|mut file| file.write_all(dot.as_bytes())).is_ok()
+}
+
+/// The C event hook with its user data.
+struct CEventHook {
+    hook: extern fn(*const GCEvent, *mut libc::c_void),
+    user_data: *mut libc::c_void,
+}
+
+unsafe impl Send for CEventHook {}
+unsafe impl Sync for CEventHook {}
+
+#[no_mangle]
+#[doc(hidden)]
+pub extern fn rcx_add_event_hook(this: *mut RCImmixCons,
+                                 hook: extern fn(*const GCEvent, *mut libc::c_void),
+                                 user_data: *mut libc::c_void) {
+    let c_hook = CEventHook { hook: hook, user_data: user_data };
+    unsafe { (*this).add_event_hook(move |event| (c_hook.hook)(event, c_hook.user_data)) };
+}
+
+#[no_mangle]
+#[doc(hidden)]
+pub extern fn rcx_register_thread(this: *mut RCImmixCons) -> bool {
+    unsafe { (*this).register_thread() }
+}
+
+#[no_mangle]
+#[doc(hidden)]
+pub extern fn rcx_unregister_thread(this: *mut RCImmixCons) -> bool {
+    unsafe { (*this).unregister_thread() }
+}
+
+#[no_mangle]
+#[doc(hidden)]
+pub extern fn rcx_safepoint_poll(this: *mut RCImmixCons) {
+    unsafe { (*this).safepoint_poll() };
+}
+
+#[no_mangle]
+#[doc(hidden)]
+pub extern fn rcx_enter_native(this: *mut RCImmixCons) -> bool {
+    unsafe { (*this).enter_native() }
+}
+
+#[no_mangle]
+#[doc(hidden)]
+pub extern fn rcx_leave_native(this: *mut RCImmixCons) {
+    unsafe { (*this).leave_native() };
+}
+
 #[no_mangle]
 #[doc(hidden)]
 pub extern fn rcx_destroy(this: *mut RCImmixCons) {
@@ -3,14 +3,6 @@
 
 #![macro_use]
 
-macro_rules! debug(
-    ($($args:tt)*) => (
-        if cfg!(debug_assertions) {
-            println!("({}:{}) {}", module_path!(), line!(), format_args!($($args)*));
-        }
-    )
-);
-
 #[cfg(feature = "valgrind")]
 pub mod valgrind {
     extern crate vgrs;
@@ -33,7 +25,7 @@ macro_rules! valgrind_malloclike(
     ($addr:expr, $size:expr) => (
         if cfg!(feature = "valgrind") {
             unsafe{
-                debug!("Mark object {:p} with malloclike_block for valgrind", $addr);
+                trace!("Mark object {:p} with malloclike_block for valgrind", $addr);
                 ::macros::valgrind::malloclike_block($addr as *const (), $size, 0, true);
             }
         }
@@ -44,7 +36,7 @@ macro_rules! valgrind_freelike(
     ($addr:expr) => (
         if cfg!(feature = "valgrind") {
             unsafe{
-                debug!("Mark object {:p} with freelike_block for valgrind", $addr);
+                trace!("Mark object {:p} with freelike_block for valgrind", $addr);
                 ::macros:: valgrind::freelike_block($addr as *const (), 0);
             }
         }
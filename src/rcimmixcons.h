@@ -8,29 +8,17 @@
 #include <stdlib.h>
 
 /// The `GCHeader` contains field for the garbage collector algorithms.
+///
+/// It is packed into a single word: the lowest three bits are the reference
+/// count, followed by six flags and the number of array elements in the
+/// remaining bits. Use `rcx_array_length()` to read the number of elements.
 typedef struct {
-    /// How many objects point to this object.
-    size_t reference_count;
-
-    /// If this object is greater than `LINE_SIZE`.
-    uint8_t spans_lines;
-
-    /// If the object at this address was forwarded somewhere else.
-    uint8_t forwarded;
-
-    /// If this object was pushed on the `modBuffer` in `RCCollector`.
-    uint8_t logged;
-
-    /// If this object was already visited by the tracing collector.
-    uint8_t marked;
-
-    /// If this object must not be evacuated (moved) by the collector.
-    uint8_t pinned;
-
-    /// If this object was never touched by the collectors.
-    uint8_t new;
+    /// The reference count, the flags and the array length.
+    size_t word;
 } GCHeader;
 
+typedef struct GCObject GCObject;
+
 /// The `GCRTTI` contains runtime type information about an object for the
 /// garbage collector.
 typedef struct {
@@ -39,6 +27,27 @@ typedef struct {
 
     /// How many pointers to other objects does this object contain.
     size_t num_members;
+
+    /// The function called after the object was found dead or `NULL`.
+    ///
+    /// It runs after the collection completed and may resurrect the object
+    /// by storing a reference to it. Every object is finalized at most once.
+    void (*finalizer)(GCObject* object);
+
+    /// The byte offsets (see `offsetof()`) of the `num_members` pointers
+    /// within the object or `NULL` if they directly follow the `GCObject`.
+    const size_t* member_offsets;
+
+    /// The size of an array element in bytes or zero if the objects are no
+    /// arrays.
+    ///
+    /// The elements directly follow the `object_size` bytes of the object.
+    /// Allocate arrays using `rcx_allocate_array()`.
+    size_t element_size;
+
+    /// How many pointers to other objects each array element contains. The
+    /// pointers must be at the start of the element.
+    size_t element_members;
 } GCRTTI;
 
 /// The `GCObject` is the base struct for every object managed by the garbage
@@ -46,15 +55,167 @@ typedef struct {
 ///
 /// Please include this as the first member in your object structs. The
 /// members of this object _must_ be a contiguous array of `GCobject` pointers
-/// of size `rtti->members`.
-typedef struct {
+/// of size `rtti->members` directly following the `GCObject`, unless their
+/// offsets are set in `rtti->member_offsets`.
+struct GCObject {
     /// The `GCHeader` for this object. This is initialized by the allocation
     /// routine.
     GCHeader header;
 
     /// A pointer to the objects runtime type information struct.
     GCRTTI* rtti;
-} GCObject;
+};
+
+/// The `GCConfig` contains the runtime tuning knobs of the garbage collector.
+///
+/// Get the defaults with `rcx_default_config()`, adjust the fields and pass
+/// it to `rcx_create_with_config()`.
+typedef struct {
+    /// The size of the heap in bytes. Must be a multiple of the block size.
+    size_t heap_size;
+
+    /// The number of blocks stored for evacuation.
+    size_t evac_headroom;
+
+    /// Ratio of available blocks when to trigger cycle collection.
+    float cycle_trigger_threshold;
+
+    /// Ratio of available blocks when to trigger evacuation collection.
+    float evac_trigger_threshold;
+
+    /// Number of write barrier invocations after which a collection will be
+    /// triggered. If zero the write barrier will not trigger a collection.
+    size_t write_barrier_collect_threshold;
+
+    /// Whether the reference counting collector should be used. Otherwise
+    /// only the tracing immix collector will be utilized.
+    uint8_t use_rc_collector;
+
+    /// Whether evacuation should be used or not.
+    uint8_t use_evacuation;
+
+    /// Whether the heap should be verified after every collection. This is
+    /// slow and meant for debugging. Violations are logged and reported by
+    /// `rcx_verify_violations()`.
+    uint8_t verify_heap;
+
+    /// Whether the stacks and registers of the mutator threads are scanned
+    /// conservatively for roots. Disable this if all roots are registered
+    /// precisely (see `rcx_push_roots()` and `rcx_set_static_root()`).
+    uint8_t scan_stack;
+
+    /// Whether new objects are allocated into nursery blocks whose survivors
+    /// are evacuated by every collection. Requires `use_rc_collector` and
+    /// `use_evacuation`.
+    uint8_t use_nursery;
+
+    /// The number of threads tracing the heap and processing the reference
+    /// counting buffers during a collection. With more than one these are
+    /// performed by worker threads in parallel.
+    size_t gc_threads;
+} GCConfig;
+
+/// The `GCStats` is a snapshot of the heap statistics and the counters of
+/// the collections so far.
+///
+/// Get it with `rcx_get_stats()`.
+typedef struct {
+    /// The number of bytes allocated since the garbage collector was created.
+    size_t bytes_allocated;
+
+    /// The number of objects that survived the last collection.
+    size_t live_objects;
+
+    /// The number of unallocated blocks in the immix space.
+    size_t free_blocks;
+
+    /// The number of blocks with holes waiting to be recycled.
+    size_t recyclable_blocks;
+
+    /// The number of free blocks reserved for evacuation.
+    size_t evac_headroom_blocks;
+
+    /// The number of bytes of the objects in the large object space.
+    size_t los_bytes;
+
+    /// The number of reference counting collections.
+    size_t rc_collections;
+
+    /// The number of evacuating reference counting collections.
+    size_t rc_evac_collections;
+
+    /// The number of immix tracing (cycle) collections.
+    size_t immix_collections;
+
+    /// The number of evacuating immix tracing (cycle) collections.
+    size_t immix_evac_collections;
+
+    /// The number of objects evacuated by all collections.
+    size_t objects_evacuated;
+
+    /// The time all threads were stopped for collections in nanoseconds.
+    uint64_t pause_time_ns;
+} GCStats;
+
+/// The type of a collection.
+typedef enum {
+    /// A simple reference counting collection.
+    GC_RC_COLLECTION,
+
+    /// A reference counting collection with proactive opportunistic
+    /// evacuation.
+    GC_RC_EVAC_COLLECTION,
+
+    /// A reference counting collection followed by the immix tracing (cycle)
+    /// collection.
+    GC_IMMIX_COLLECTION,
+
+    /// A reference counting collection followed by the immix tracing (cycle)
+    /// collection. Both with opportunistict evacuation.
+    GC_IMMIX_EVAC_COLLECTION,
+} GCCollectionType;
+
+/// The kind of a `GCEvent`.
+typedef enum {
+    /// A collection started. All threads are stopped.
+    GC_COLLECTION_START,
+
+    /// A collection ended. The threads are restarted already.
+    GC_COLLECTION_END,
+} GCEventKind;
+
+/// The `GCEvent` is passed to the event hooks registered with
+/// `rcx_add_event_hook()`.
+///
+/// The durations and block counts are only set for `GC_COLLECTION_END`.
+typedef struct {
+    /// The kind of this event.
+    GCEventKind kind;
+
+    /// The type of the collection.
+    GCCollectionType collection_type;
+
+    /// The duration of the reference counting collection in nanoseconds.
+    uint64_t rc_time_ns;
+
+    /// The duration of the immix tracing collection in nanoseconds.
+    uint64_t immix_time_ns;
+
+    /// The duration of sweeping the blocks in nanoseconds.
+    uint64_t sweep_time_ns;
+
+    /// The number of blocks found free by the sweep.
+    size_t freed_blocks;
+
+    /// The number of blocks with holes found by the sweep.
+    size_t recycled_blocks;
+} GCEvent;
+
+/// A weak reference to a `GCObject`.
+///
+/// The referent is not kept alive by the weak reference. If it is reclaimed
+/// the weak reference is cleared.
+typedef struct GCWeakRef GCWeakRef;
 
 /// The `RCImmixCons` garbage collector.
 ///
@@ -66,12 +227,29 @@ typedef struct {
 /// details.
 ///
 /// Always call `rcx_write_barrier()` on an object before modifying its
-/// members.
+/// members or set them with `rcx_write_member()`.
+///
+/// The collector can be shared by multiple threads. Every thread except the
+/// one that created the collector must call `rcx_register_thread()` before
+/// using it and every thread must call `rcx_unregister_thread()` before it
+/// exits. A collection stops all registered threads the next time they call
+/// into the garbage collector (or `rcx_safepoint_poll()`). Threads that
+/// execute code without calling into the garbage collector for a long time
+/// (or block) must poll or wrap this code in `rcx_enter_native()` and
+/// `rcx_leave_native()`.
 typedef struct {} RCImmixCons;
 
-/// Create a new `RCImmixCons`.
+/// Create a new `RCImmixCons` with the default configuration.
 RCImmixCons* rcx_create(void);
 
+/// Return the default configuration.
+GCConfig rcx_default_config(void);
+
+/// Create a new `RCImmixCons` with the given configuration.
+///
+/// Returns `NULL` if the configuration is invalid.
+RCImmixCons* rcx_create_with_config(const GCConfig* config);
+
 /// Allocate a new object described by the `rtti` or returns `NULL`.
 ///
 /// This may trigger a garbage collection if the allocation was not
@@ -79,6 +257,19 @@ RCImmixCons* rcx_create(void);
 /// request return `NULL`.
 GCObject* rcx_allocate(RCImmixCons* collector, GCRTTI* rtti);
 
+/// Allocate a new array object described by the `rtti` with `length`
+/// elements or returns `NULL`.
+///
+/// The `rtti` must describe arrays (see `GCRTTI.element_size`). The elements
+/// directly follow the `rtti->object_size` bytes of the object and the
+/// `length` is stored in its `GCHeader`. This may trigger a garbage
+/// collection like `rcx_allocate()`.
+GCObject* rcx_allocate_array(RCImmixCons* collector, GCRTTI* rtti, size_t length);
+
+/// Return the number of elements of an `object` allocated with
+/// `rcx_allocate_array()`.
+size_t rcx_array_length(GCObject* object);
+
 /// Trigger a garbage collection.
 ///
 /// This will always run the referece counting collector. If `evacuation`
@@ -93,11 +284,156 @@ void rcx_collect(RCImmixCons* collector, uint8_t evacuation, uint8_t cycle_colle
 /// any register.
 void rcx_set_static_root(RCImmixCons* collector, void* address);
 
+/// Push a frame of `count` precise root slots starting at `slots` onto the
+/// shadow stack of the current thread.
+///
+/// The slots must contain `NULL` or object references until the frame is
+/// popped with `rcx_pop_roots()`. The collector updates the slots if the
+/// objects are moved. Returns 0 if the current thread is not registered.
+uint8_t rcx_push_roots(RCImmixCons* collector, void* slots, size_t count);
+
+/// Pop the last frame pushed with `rcx_push_roots()` from the shadow stack of
+/// the current thread.
+///
+/// Returns 0 if the current thread is not registered or its shadow stack is
+/// empty.
+uint8_t rcx_pop_roots(RCImmixCons* collector);
+
+/// Remove a static root set with `rcx_set_static_root()`.
+///
+/// Call this before the variable at `address` becomes invalid, e.g. when the
+/// module defining it is unloaded. Returns 0 if `address` is not a static
+/// root.
+uint8_t rcx_remove_static_root(RCImmixCons* collector, void* address);
+
+/// Set the `count` consecutive object references starting at `start` as
+/// static roots.
+///
+/// Use this for arrays of object references. Null references are ignored.
+void rcx_add_root_range(RCImmixCons* collector, void* start, size_t count);
+
+/// Remove a root range added with `rcx_add_root_range()`.
+///
+/// Returns 0 if there is no range starting at `start`.
+uint8_t rcx_remove_root_range(RCImmixCons* collector, void* start);
+
 /// A write barrier for the given `object`.
 ///
 /// Call this function before modifying the members of this object!
 void rcx_write_barrier(RCImmixCons* collector, GCObject* object);
 
+/// Set the member at position `num` of the `object` to `member`.
+///
+/// This performs the write barrier for this member only and the store, so
+/// there is no need to call `rcx_write_barrier()` before. Returns 0 without
+/// storing if the `object` is not managed by this garbage collector or has no
+/// member `num`.
+uint8_t rcx_write_member(RCImmixCons* collector, GCObject* object, size_t num,
+                         GCObject* member);
+
+/// Pin the `object`.
+///
+/// A pinned object is never moved by the collectors and kept alive like a
+/// root. Use this for objects passed to native code. The pins are counted:
+/// the `object` is pinned until `rcx_unpin()` was called as often as
+/// `rcx_pin()`. Returns 0 if the `object` is not managed by this garbage
+/// collector.
+uint8_t rcx_pin(RCImmixCons* collector, GCObject* object);
+
+/// Remove one pin of the `object` set with `rcx_pin()`.
+///
+/// Returns 0 if the `object` is not pinned.
+uint8_t rcx_unpin(RCImmixCons* collector, GCObject* object);
+
+/// Create a weak reference to `object`.
+///
+/// The weak reference does not keep the `object` alive and is cleared when
+/// the `object` is reclaimed. Returns `NULL` if the `object` is not managed
+/// by this garbage collector. Destroy it using `rcx_weak_destroy()`.
+GCWeakRef* rcx_weak_create(RCImmixCons* collector, GCObject* object);
+
+/// Return the referent of the weak reference or `NULL` if it was reclaimed.
+GCObject* rcx_weak_get(RCImmixCons* collector, GCWeakRef* weak_ref);
+
+/// Destroy a weak reference created with `rcx_weak_create()`.
+void rcx_weak_destroy(RCImmixCons* collector, GCWeakRef* weak_ref);
+
+/// Return a snapshot of the heap statistics.
+///
+/// The live objects are counted by the last collection.
+GCStats rcx_get_stats(RCImmixCons* collector);
+
+/// Call `report` with the `user_data` for every violation found by the heap
+/// verification after the last collection and return their number.
+///
+/// Each violation describes a broken invariant with the addresses of the
+/// objects involved. The `report` may be `NULL` to only count them. Nothing
+/// is reported if `verify_heap` is not set.
+size_t rcx_verify_violations(RCImmixCons* collector,
+                             void (*report)(const char* violation, void* user_data),
+                             void* user_data);
+
+/// Write a heap dump of all objects, their members and the current roots to
+/// the file at `path`.
+///
+/// All other threads are stopped while the heap is inspected. The format is
+/// documented in `src/heap_dump.rs`. Returns 0 if the file could not be
+/// written.
+uint8_t rcx_dump_heap(RCImmixCons* collector, const char* path);
+
+/// Write the graph of the objects reachable from `object` (or the current
+/// roots if `object` is `NULL`) in the Graphviz DOT format to the file at
+/// `path`.
+///
+/// Each node is labeled with the address, reference count, space and flags
+/// of the object. Roots are drawn as bold boxes. Returns 0 if the file could
+/// not be written.
+uint8_t rcx_write_dot(RCImmixCons* collector, GCObject* object, const char* path);
+
+/// Register a `hook` called with the `user_data` at the start and end of
+/// every collection.
+///
+/// The hooks run on the collecting thread. For `GC_COLLECTION_START` all
+/// other threads are stopped and the GC lock is held, so they must not use
+/// the garbage collector. For `GC_COLLECTION_END` the threads are restarted
+/// and the GC lock is released, so they may use it, for example to call
+/// `rcx_get_stats()`.
+void rcx_add_event_hook(RCImmixCons* collector,
+                        void (*hook)(const GCEvent* event, void* user_data),
+                        void* user_data);
+
+/// Register the current thread as a mutator thread.
+///
+/// Returns 0 if the thread was already registered.
+uint8_t rcx_register_thread(RCImmixCons* collector);
+
+/// Unregister the current thread.
+///
+/// The thread must not use the garbage collector or keep references to
+/// garbage collected objects afterwards. Returns 0 if the thread was not
+/// registered.
+uint8_t rcx_unregister_thread(RCImmixCons* collector);
+
+/// Poll for a requested collection.
+///
+/// If another thread requested a collection, the current thread is parked
+/// until the collection completed. Call this regularly in long running code
+/// that does not call into the garbage collector otherwise.
+void rcx_safepoint_poll(RCImmixCons* collector);
+
+/// Mark the current thread as executing native code.
+///
+/// Until `rcx_leave_native()` is called, the current thread must not access
+/// the heap and collections can happen without its cooperation. Object
+/// references in the callers stack frames and registers remain roots.
+/// Returns 0 if the thread is not registered or already in native code.
+uint8_t rcx_enter_native(RCImmixCons* collector);
+
+/// Mark the current thread as executing mutator code again.
+///
+/// This waits for a running collection to complete.
+void rcx_leave_native(RCImmixCons* collector);
+
 /// Destroy and cleanup the garbage collector.
 void rcx_destroy(RCImmixCons* collector);
 
@@ -3,6 +3,8 @@
 
 use std::collections::VecDeque;
 
+use spaces::collector::work_stacks::WorkStacks;
+use spaces::collector::worker_pool::WorkerPool;
 use spaces::immix_space::ImmixSpace;
 use gc_object::GCObjectRef;
 use spaces::CollectionType;
@@ -15,42 +17,116 @@ pub struct ImmixCollector;
 /// counts and the object map. To complete the collection the `Collector`
 /// sweeps the line counters of the blocks and reclaims unused lines and
 /// blocks.
+///
+/// It also recomputes the reference counts of the reachable objects from the
+/// references between them. This resets reference counts that are stuck at
+/// the maximum. The references of the roots are not counted (see
+/// `RCCollector::restore_root_counts()`).
+///
+/// During an evacuating collection unmarked objects in evacuation candidate
+/// blocks are moved. This includes the roots that are not pinned (the
+/// precise roots), whose slots are updated by the `Spaces` afterwards.
+///
+/// With more than one GC thread (see `GCConfig::with_gc_threads()`) the
+/// object graph is traversed by worker threads using `WorkStacks`. The mark
+/// bits, reference counts, line counters and object maps are updated
+/// atomically and only the thread that marks an object scans it, so the
+/// result is the same as the sequential trace.
 impl ImmixCollector {
-    /// Perform the immix tracing collection.
+    /// Perform the immix tracing collection with the `workers`.
     pub fn collect(collection_type: &CollectionType, roots: &[GCObjectRef],
-                   immix_space: &mut ImmixSpace,
-                   next_live_mark: bool) {
-        debug!("Start Immix collection with {} roots and next_live_mark: {}",
-               roots.len(), next_live_mark);
-        let mut object_queue: VecDeque<GCObjectRef> = roots.iter().map(|o| *o).collect();
-
-        while let Some(object) =  object_queue.pop_front() {
-            debug!("Process object {:p} in Immix closure", object);
-            if !unsafe { (*object).set_marked(next_live_mark) } {
-                if immix_space.is_in_space(object) {
-                    immix_space.set_gc_object(object);
-                    immix_space.increment_lines(object);
-                }
-                debug!("Object {:p} was unmarked: process children", object);
-                let children = unsafe{ (*object).children() };
-                for (num, mut child) in children.enumerate() {
-                    if let Some(new_child) = unsafe{ (*child).is_forwarded() } {
-                        debug!("Child {:p} is forwarded to {:p}", child, new_child);
-                        unsafe{ (*object).set_member(num, new_child); }
-                    } else if !unsafe{ (*child).is_marked(next_live_mark) } {
-                        if collection_type.is_evac() && immix_space.is_gc_object(child) {
-                            if let Some(new_child) = immix_space.maybe_evacuate(child) {
-                                debug!("Evacuated child {:p} to {:p}", child, new_child);
-                                unsafe{ (*object).set_member(num, new_child); }
-                                child = new_child;
-                            }
-                        }
-                        debug!("Push child {:p} into object queue", child);
-                        object_queue.push_back(child);
-                    }
-                }
+                   immix_space: &ImmixSpace, next_live_mark: bool,
+                   workers: &WorkerPool) {
+        debug!(target: "immix_collector",
+               "Start Immix collection with {} roots, next_live_mark: {} and {} threads",
+               roots.len(), next_live_mark, workers.threads());
+        let mut object_queue = VecDeque::new();
+        for &root in roots {
+            let root = ImmixCollector::evacuate(collection_type, immix_space,
+                                                root, next_live_mark);
+            if ImmixCollector::mark(immix_space, root, next_live_mark) {
+                object_queue.push_back(root);
+            }
+        }
+
+        if workers.threads() > 1 {
+            let stacks = WorkStacks::new(workers.threads(), object_queue.drain(..).collect());
+            stacks.process(workers, |object, children| {
+                ImmixCollector::scan(collection_type, immix_space, object,
+                                     next_live_mark, |child| children.push(child));
+            });
+        } else {
+            while let Some(object) = object_queue.pop_front() {
+                ImmixCollector::scan(collection_type, immix_space, object, next_live_mark,
+                                     |child| object_queue.push_back(child));
+            }
+        }
+        debug!(target: "immix_collector", "Complete collection");
+    }
+}
+
+impl ImmixCollector {
+    /// Process the children of the marked `object`: evacuate them, update
+    /// the members of the `object` that moved, increment their reference
+    /// counts and `push` the children that were unmarked.
+    fn scan<F>(collection_type: &CollectionType, immix_space: &ImmixSpace,
+               object: GCObjectRef, next_live_mark: bool, mut push: F)
+        where F: FnMut(GCObjectRef) {
+        trace!(target: "immix_collector",
+               "Process object {:p} in Immix closure", object);
+        let children = unsafe{ (*object).enumerate_children() };
+        for (num, child) in children {
+            let new_child = ImmixCollector::evacuate(collection_type, immix_space,
+                                                     child, next_live_mark);
+            if new_child != child {
+                trace!(target: "immix_collector",
+                       "Child {:p} moved to {:p}", child, new_child);
+                unsafe{ (*object).set_member(num, new_child); }
+            }
+            let child = new_child;
+            if ImmixCollector::mark(immix_space, child, next_live_mark) {
+                trace!(target: "immix_collector",
+                       "Push child {:p} into object queue", child);
+                push(child);
             }
+            unsafe{ (*child).increment(); }
+        }
+    }
+
+    /// Return the new address of the `object` if it is forwarded or was
+    /// evacuated during an evacuating collection, the `object` otherwise.
+    fn evacuate(collection_type: &CollectionType, immix_space: &ImmixSpace,
+                mut object: GCObjectRef, next_live_mark: bool) -> GCObjectRef {
+        while let Some(new_object) = unsafe{ (*object).is_forwarded() } {
+            trace!(target: "immix_collector",
+                   "Object {:p} is forwarded to {:p}", object, new_object);
+            object = new_object;
+        }
+        if collection_type.is_evac()
+           && !unsafe{ (*object).is_marked(next_live_mark) }
+           && immix_space.is_in_space(object) {
+            if let Some(new_object) = immix_space.maybe_evacuate(object) {
+                trace!(target: "immix_collector",
+                       "Evacuated object {:p} to {:p}", object, new_object);
+                return new_object;
+            }
+        }
+        object
+    }
+
+    /// Mark the `object`, restore its line counts and object map entry and
+    /// reset its reference count. Returns if it was unmarked before.
+    fn mark(immix_space: &ImmixSpace, object: GCObjectRef,
+            next_live_mark: bool) -> bool {
+        if unsafe { (*object).set_marked_and_reset_count(next_live_mark) } {
+            return false;
+        }
+        trace!(target: "immix_collector",
+               "Object {:p} was unmarked: process children", object);
+        if immix_space.is_in_space(object) {
+            immix_space.set_gc_object(object);
+            immix_space.increment_lines(object);
         }
-        debug!("Complete collection");
+        true
     }
 }
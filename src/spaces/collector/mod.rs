@@ -3,21 +3,26 @@
 
 mod rc_collector;
 mod immix_collector;
+mod work_stacks;
+mod worker_pool;
 
 use self::rc_collector::RCCollector;
 use self::immix_collector::ImmixCollector;
+use self::worker_pool::WorkerPool;
 
 use spaces::immix_space::BlockInfo;
 use spaces::immix_space::ImmixSpace;
 use spaces::large_object_space::LargeObjectSpace;
+use spaces::finalizers::Finalizers;
 
 use std::collections::HashSet;
+use std::time::Instant;
 
 use vec_map::VecMap;
 
-use constants::{TOTAL_BLOCKS, NUM_LINES_PER_BLOCK, USE_RC_COLLECTOR,
-                USE_EVACUATION, EVAC_HEADROOM, CICLE_TRIGGER_THRESHHOLD,
-                EVAC_TRIGGER_THRESHHOLD};
+use config::GCConfig;
+use events::{GCEvent, GCEventKind, duration_ns};
+use constants::NUM_LINES_PER_BLOCK;
 use gc_object::GCObjectRef;
 use spaces::CollectionType;
 
@@ -27,9 +32,15 @@ use spaces::CollectionType;
 /// It manages performs common tasks and manages the cooperation between the
 /// two collectors.
 pub struct Collector {
+    /// The runtime configuration of the garbage collector.
+    config: GCConfig,
+
     /// The reference counting collector.
     rc_collector: RCCollector,
 
+    /// The GC worker threads of the parallel collection steps.
+    workers: WorkerPool,
+
     /// A buffer to store all managed blocks during collection.
     all_blocks: Vec<*mut BlockInfo>,
 
@@ -43,28 +54,82 @@ pub struct Collector {
     /// The mark histogram used during collection to calculate the required
     /// space for evacuation.
     mark_histogram: VecMap<usize>,
+
+    /// The number of objects in the immix space that survived the last
+    /// collection.
+    live_objects: usize,
+
+    /// The durations and block counts of the last collection.
+    phase_stats: GCEvent,
 }
 
 impl Collector {
-    /// Create a new `Collector`.
-    pub fn new() -> Collector {
+    /// Create a new `Collector` using the given `config`.
+    pub fn new(config: &GCConfig) -> Collector {
         Collector {
-            rc_collector: RCCollector::new(),
+            config: *config,
+            rc_collector: RCCollector::new(config.write_barrier_collect_threshold()),
+            workers: WorkerPool::new(config.gc_threads()),
             all_blocks: Vec::new(),
             object_map_backup: HashSet::new(),
             mark_histogram: VecMap::with_capacity(NUM_LINES_PER_BLOCK),
+            live_objects: 0,
+            phase_stats: GCEvent::new(GCEventKind::CollectionEnd,
+                                      CollectionType::RCCollection),
+        }
+    }
+
+    /// Return the `GCEventKind::CollectionEnd` event for the last collection
+    /// of `collection_type` with its durations and block counts.
+    pub fn end_event(&self, collection_type: CollectionType) -> GCEvent {
+        GCEvent {
+            collection_type: collection_type,
+            .. self.phase_stats
+        }
+    }
+
+    /// Return the number of objects in the immix space that survived the
+    /// last collection.
+    pub fn live_objects(&self) -> usize {
+        self.live_objects
+    }
+
+    /// Return the runtime configuration of the garbage collector.
+    pub fn config(&self) -> &GCConfig {
+        &self.config
+    }
+
+    /// Return the roots counted in the reference counts or `None` if the
+    /// `RCCollector` is not used.
+    pub fn counted_roots(&self) -> Option<&[GCObjectRef]> {
+        if self.config.use_rc_collector() {
+            Some(self.rc_collector.old_roots())
+        } else {
+            None
         }
     }
 
     /// A write barrier for the given `object` used with the `RCCollector`.
     pub fn write_barrier(&mut self, object: GCObjectRef) -> bool {
-        if USE_RC_COLLECTOR {
+        if self.config.use_rc_collector() {
             self.rc_collector.write_barrier(object)
         } else {
             false
         }
     }
 
+    /// Set the member at position `num` of the `object` to `member` with the
+    /// write barrier of the `RCCollector` (see `RCCollector::write_member()`).
+    pub fn write_member(&mut self, object: GCObjectRef, num: usize,
+                        member: GCObjectRef) -> bool {
+        if self.config.use_rc_collector() {
+            self.rc_collector.write_member(object, num, member)
+        } else {
+            unsafe{ (*object).store_member(num, member); }
+            false
+        }
+    }
+
     /// Store the given blocks into the buffer for use during the collection.
     pub fn extend_all_blocks(&mut self, blocks: Vec<*mut BlockInfo>) {
         self.all_blocks.extend(blocks);
@@ -76,16 +141,23 @@ impl Collector {
     /// collection will be performed. If `evacuation` is set the collectors
     /// will try to evacuate. If `cycle_collect` is set the immix tracing
     /// collector will be used.
+    ///
+    /// If the nursery is used every collection evacuates and all nursery
+    /// blocks are evacuation candidates. The `RCCollector` then copies the
+    /// surviving new objects out of them when they are reached from the
+    /// roots or the modified objects, while the old objects are only visited
+    /// through the modified objects recorded by the write barrier.
     pub fn prepare_collection(&mut self, evacuation: bool, cycle_collect: bool,
                               available_blocks: usize, evac_headroom: usize)
                               -> CollectionType {
         let mut perform_evac = evacuation;
 
-        let evac_threshhold = ((TOTAL_BLOCKS as f32) * EVAC_TRIGGER_THRESHHOLD) as usize;
+        let total_blocks = self.config.total_blocks() as f32;
+        let evac_threshhold = (total_blocks * self.config.evac_trigger_threshold()) as usize;
         let available_evac_blocks = available_blocks + evac_headroom;
         if evacuation || available_evac_blocks < evac_threshhold {
             let hole_threshhold = self.establish_hole_threshhold(evac_headroom);
-            perform_evac = USE_EVACUATION && hole_threshhold > 0
+            perform_evac = self.config.use_evacuation() && hole_threshhold > 0
                                           && hole_threshhold < NUM_LINES_PER_BLOCK;
             if perform_evac {
                 debug!("Performing evacuation with hole_threshhold={} and evac_headroom={}",
@@ -95,11 +167,23 @@ impl Collector {
                 }
             }
         }
+        if self.config.use_nursery() && !perform_evac {
+            perform_evac = true;
+            let mut nursery_blocks = 0;
+            for block in &mut self.all_blocks {
+                if unsafe{ (**block).is_nursery() } {
+                    nursery_blocks += 1;
+                }
+                unsafe{ (**block).set_evacuation_candidate(NUM_LINES_PER_BLOCK); }
+            }
+            debug!("Performing nursery evacuation of {} blocks with evac_headroom={}",
+                   nursery_blocks, evac_headroom);
+        }
 
-        let cycle_theshold = ((TOTAL_BLOCKS as f32) * CICLE_TRIGGER_THRESHHOLD) as usize;
-        let perform_cycle_collect = cycle_collect && (available_blocks < cycle_theshold);
+        let cycle_theshold = (total_blocks * self.config.cycle_trigger_threshold()) as usize;
+        let perform_cycle_collect = cycle_collect || available_blocks < cycle_theshold;
 
-        match (USE_RC_COLLECTOR, perform_evac, perform_cycle_collect) {
+        match (self.config.use_rc_collector(), perform_evac, perform_cycle_collect) {
             (true, false, false) => CollectionType::RCCollection,
             (true, true, false) => CollectionType::RCEvacCollection,
             (true, false, true) => CollectionType::ImmixCollection,
@@ -111,21 +195,28 @@ impl Collector {
 
     /// Perform the collection.
     ///
-    /// See `Spaces.collect() how it is called.`
+    /// Dead objects with a finalizer are resurrected and enqueued in the
+    /// `finalizers`. See `Spaces.collect() how it is called.`
     pub fn collect(&mut self, collection_type: &CollectionType,
                    roots: &[GCObjectRef], immix_space: &mut ImmixSpace,
-                   large_object_space: &mut LargeObjectSpace, next_live_mark: bool) {
-        debug!("Perform collection (evacuation={}, cycle_collect={})",
+                   large_object_space: &mut LargeObjectSpace,
+                   finalizers: &mut Finalizers, next_live_mark: bool) {
+        info!("Perform collection (evacuation={}, cycle_collect={})",
                collection_type.is_evac(), collection_type.is_immix());
+        self.phase_stats = GCEvent::new(GCEventKind::CollectionEnd, *collection_type);
 
-        if USE_RC_COLLECTOR {
+        if self.config.use_rc_collector() {
+            let start = Instant::now();
             self.perform_rc_collection(collection_type, roots, immix_space,
-                                       large_object_space);
+                                       large_object_space, finalizers);
+            self.phase_stats.rc_time_ns = duration_ns(start.elapsed());
         }
 
         if collection_type.is_immix() {
-            self.perform_immix_collection(collection_type, roots,
-                                          immix_space, next_live_mark);
+            let start = Instant::now();
+            self.perform_immix_collection(collection_type, roots, immix_space,
+                                          finalizers, next_live_mark);
+            self.phase_stats.immix_time_ns = duration_ns(start.elapsed());
         }
     }
 
@@ -133,7 +224,8 @@ impl Collector {
     fn perform_rc_collection(&mut self, collection_type: &CollectionType,
                              roots: &[GCObjectRef],
                              immix_space: &mut ImmixSpace,
-                             large_object_space: &mut LargeObjectSpace) {
+                             large_object_space: &mut LargeObjectSpace,
+                             finalizers: &mut Finalizers) {
         if cfg!(feature = "valgrind") {
             for block in &mut self.all_blocks {
                 let block_new_objects = unsafe{ (**block).get_new_objects() };
@@ -145,7 +237,8 @@ impl Collector {
             unsafe{ (**block).remove_new_objects_from_map(); }
         }
 
-        self.rc_collector.collect(collection_type, roots, immix_space, large_object_space);
+        self.rc_collector.collect(collection_type, roots, immix_space,
+                                  large_object_space, finalizers, &self.workers);
 
         large_object_space.proccess_free_buffer();
 
@@ -163,10 +256,18 @@ impl Collector {
     }
 
     /// Perform the immix tracing collection.
+    ///
+    /// Objects with a finalizer that were not reached are enqueued for
+    /// finalization and resurrected by tracing from all enqueued objects.
+    /// Afterwards the reference counts of the roots are restored.
     pub fn perform_immix_collection(&mut self, collection_type: &CollectionType,
                                     roots: &[GCObjectRef],
                                     immix_space: &mut ImmixSpace,
+                                    finalizers: &mut Finalizers,
                                     next_live_mark: bool) {
+        // The blocks the reference counting collector evacuated into must be
+        // cleared as well, as the trace counts the evacuated objects again.
+        self.all_blocks.extend(immix_space.get_evac_blocks());
         if cfg!(feature = "valgrind") {
             for block in &mut self.all_blocks {
                 let block_object_map = unsafe{ (**block).get_object_map() };
@@ -179,7 +280,12 @@ impl Collector {
             unsafe{ (**block).clear_object_map(); }
         }
 
-        ImmixCollector::collect(collection_type, roots, immix_space, next_live_mark);
+        ImmixCollector::collect(collection_type, roots, immix_space, next_live_mark,
+                                &self.workers);
+        finalizers.enqueue_dead(|o| unsafe{ (*o).is_marked(next_live_mark) });
+        ImmixCollector::collect(collection_type, &finalizers.roots(), immix_space,
+                                next_live_mark, &self.workers);
+        self.rc_collector.restore_root_counts();
 
         if cfg!(feature = "valgrind") {
             let mut object_map = HashSet::new();
@@ -198,14 +304,17 @@ impl Collector {
     pub fn complete_collection(&mut self, collection_type: &CollectionType,
                                immix_space: &mut ImmixSpace,
                                large_object_space: &mut LargeObjectSpace) {
+        let start = Instant::now();
         self.mark_histogram.clear();
         let (recyclable_blocks, free_blocks) = self.sweep_all_blocks();
+        self.phase_stats.recycled_blocks = recyclable_blocks.len();
+        self.phase_stats.freed_blocks = free_blocks.len();
         immix_space.set_recyclable_blocks(recyclable_blocks);
 
         // XXX We should not use a constant here, but something that
         // XXX changes dynamically (see rcimmix: MAX heuristic).
-        let evac_headroom = if USE_EVACUATION {
-            EVAC_HEADROOM - immix_space.evac_headroom() } else { 0 };
+        let evac_headroom = if self.config.use_evacuation() {
+            self.config.evac_headroom() - immix_space.evac_headroom() } else { 0 };
         immix_space.extend_evac_headroom(free_blocks.iter().take(evac_headroom)
                                                     .map(|&b| b).collect());
         immix_space.return_blocks(free_blocks.iter().skip(evac_headroom)
@@ -214,6 +323,7 @@ impl Collector {
         if collection_type.is_immix() {
             large_object_space.sweep()
         }
+        self.phase_stats.sweep_time_ns = duration_ns(start.elapsed());
     }
 }
 
@@ -226,6 +336,7 @@ impl Collector {
         let mut unavailable_blocks = Vec::new();
         let mut recyclable_blocks = Vec::new();
         let mut free_blocks = Vec::new();
+        self.live_objects = 0;
         for block in self.all_blocks.drain(..) {
             if unsafe{ (*block).is_empty() } {
                 if cfg!(feature = "valgrind") {
@@ -235,9 +346,12 @@ impl Collector {
                     }
                 }
                 unsafe{ (*block).reset() ;}
-                debug!("Push block {:p} into free_blocks", block);
+                trace!("Push block {:p} into free_blocks", block);
                 free_blocks.push(block);
             } else {
+                self.live_objects += unsafe{ (*block).count_live_objects() };
+                // The objects left in a nursery block are tenured in place.
+                unsafe{ (*block).set_nursery(false); }
                 unsafe{ (*block).count_holes(); }
                 let (holes, marked_lines) = unsafe{ (*block).count_holes_and_marked_lines() };
                 if self.mark_histogram.contains_key(holes) {
@@ -245,15 +359,15 @@ impl Collector {
                         *val += marked_lines;
                     }
                 } else { self.mark_histogram.insert(holes, marked_lines); }
-                debug!("Found {} holes and {} marked lines in block {:p}",
+                trace!("Found {} holes and {} marked lines in block {:p}",
                        holes, marked_lines, block);
                 match holes {
                     0 => {
-                        debug!("Push block {:p} into unavailable_blocks", block);
+                        trace!("Push block {:p} into unavailable_blocks", block);
                         unavailable_blocks.push(block);
                     },
                     _ => {
-                        debug!("Push block {:p} into recyclable_blocks", block);
+                        trace!("Push block {:p} into recyclable_blocks", block);
                         recyclable_blocks.push(block);
                     }
                 }
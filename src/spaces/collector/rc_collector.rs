@@ -1,18 +1,31 @@
 // Copyright (c) <2015> <lummax>
 // Licensed under MIT (http://opensource.org/licenses/MIT)
 
-use std::collections::VecDeque;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Mutex;
 
+use spaces::collector::work_stacks::WorkStacks;
+use spaces::collector::worker_pool::WorkerPool;
 use spaces::immix_space::ImmixSpace;
 use spaces::large_object_space::LargeObjectSpace;
+use spaces::finalizers::Finalizers;
+use constants::PARALLEL_BUFFER_THRESHOLD;
 use gc_object::GCObjectRef;
 use spaces::CollectionType;
-use constants::WRITE_BARRIER_COLLECT_THRESHOLD;
 
 /// The `RCCollector` perform the steps for the deferred coalesced
 /// conservative reference counting. The `write_barrier()` must be called
-/// before an objects members are changed. The `collect()` function should be
-/// called periodically to incrementally collect garbage.
+/// before an objects members are changed (or `write_member()` used to change
+/// a single member). The `collect()` function should be called periodically
+/// to incrementally collect garbage.
+///
+/// With more than one GC thread (see `GCConfig::with_gc_threads()`) the
+/// modified buffer and the decrement buffer are processed by worker threads
+/// using `WorkStacks` if they hold at least `PARALLEL_BUFFER_THRESHOLD`
+/// objects. The reference counts, line counters and object maps
+/// are updated atomically and only the thread that increments a new object
+/// first (or decrements an object to zero) processes its members, so the
+/// result is the same as the sequential processing.
 pub struct RCCollector {
     /// The roots of the last collection.
     ///
@@ -33,22 +46,51 @@ pub struct RCCollector {
     /// using the `write_barrier()`.
     modified_buffer: VecDeque<GCObjectRef>,
 
+    /// The positions of the members changed with `write_member()` since the
+    /// last collection for every object.
+    ///
+    /// Only the overwritten member was decremented, so only its current
+    /// value is incremented by the next collection.
+    modified_members: HashMap<GCObjectRef, HashSet<usize>>,
+
     /// Flag if this collection is a evacuating collection.
     perform_evac: bool,
 
     /// Counter for write barrie invocations since last collection.
     write_barrier_counter: usize,
+
+    /// Number of write barrier invocations after which a collection will be
+    /// triggered. If zero the write barrier will not trigger a collection.
+    write_barrier_collect_threshold: usize,
 }
 
+/// The `Finalizers` and the old roots updated by the threads processing the
+/// decrement buffer when a dead object is resurrected.
+struct Resurrection<'a> {
+    /// The finalizers to enqueue dead objects in.
+    finalizers: &'a mut Finalizers,
+
+    /// The old roots of the `RCCollector` the resurrected objects are
+    /// pushed onto.
+    old_root_buffer: &'a mut Vec<GCObjectRef>,
+}
+
+// The `Resurrection` is only shared by the collector threads while the
+// mutator threads are stopped.
+unsafe impl<'a> Send for Resurrection<'a> {}
+
 impl RCCollector {
-    /// Create a new `RCCollector`.
-    pub fn new() -> RCCollector {
+    /// Create a new `RCCollector` that requests a collection after
+    /// `write_barrier_collect_threshold` write barrier invocations.
+    pub fn new(write_barrier_collect_threshold: usize) -> RCCollector {
         RCCollector {
             old_root_buffer: Vec::new(),
             decrement_buffer: VecDeque::new(),
             modified_buffer: VecDeque::new(),
+            modified_members: HashMap::new(),
             perform_evac: false,
             write_barrier_counter: 0,
+            write_barrier_collect_threshold: write_barrier_collect_threshold,
         }
     }
 
@@ -60,51 +102,122 @@ impl RCCollector {
     /// - process_current_roots()
     /// - process_los_new_objects()
     /// - process_mod_buffer()
+    /// - process_modified_members()
+    /// - process_dead_new_objects()
     /// - process_decrement_buffer()
+    ///
+    /// The buffers are processed with the `workers`.
     pub fn collect(&mut self, collection_type: &CollectionType,
-                   roots: &[GCObjectRef], immix_space: &mut ImmixSpace,
-                   large_object_space: &mut LargeObjectSpace) {
-        debug!("Start RC collection");
+                   roots: &[GCObjectRef], immix_space: &ImmixSpace,
+                   large_object_space: &mut LargeObjectSpace,
+                   finalizers: &mut Finalizers, workers: &WorkerPool) {
+        debug!(target: "rc_collector", "Start RC collection");
         self.perform_evac = collection_type.is_evac();
         self.process_old_roots();
         self.process_current_roots(immix_space, roots);
         self.process_los_new_objects(immix_space, large_object_space.get_new_objects());
-        self.process_mod_buffer(immix_space);
-        self.process_decrement_buffer(immix_space, large_object_space);
+        self.process_mod_buffer(immix_space, workers);
+        self.process_modified_members(immix_space);
+        self.process_dead_new_objects(immix_space, finalizers, workers);
+        self.process_decrement_buffer(immix_space, large_object_space, finalizers, workers);
         self.write_barrier_counter = 0;
-        debug!("Complete collection");
+        debug!(target: "rc_collector", "Complete collection");
+    }
+
+    /// Return the roots of the last collection. Every occurrence is counted
+    /// in the reference count of the object.
+    pub fn old_roots(&self) -> &[GCObjectRef] {
+        &self.old_root_buffer
+    }
+
+    /// Restore the references of the old roots to the reference counts.
+    ///
+    /// This must be called after the immix tracing collection recomputed the
+    /// reference counts (see `ImmixCollector`). Old roots that were evacuated
+    /// are replaced by their new address.
+    pub fn restore_root_counts(&mut self) {
+        debug!(target: "rc_collector",
+               "Restore reference counts of old roots (size {})",
+               self.old_root_buffer.len());
+        for root in &mut self.old_root_buffer {
+            while let Some(new_root) = unsafe{ (**root).is_forwarded() } {
+                *root = new_root;
+            }
+            unsafe{ (**root).increment(); }
+        }
     }
 
     /// The write barrier for an object in deferred coalesced reference
     /// counting pushes the object into the modified buffer and enqueues a
-    /// decrement for the old children.
+    /// decrement for the old children (except for the members remembered by
+    /// `write_member()`, which are forgotten).
     ///
     /// Returns if a collection should be triggered (see
-    /// constants::WRITE_BARRIER_COLLECT_THRESHOLD).
+    /// `GCConfig::write_barrier_collect_threshold()`).
     pub fn write_barrier(&mut self, object: GCObjectRef) -> bool {
         if !unsafe{ (*object).set_logged(true) } {
-            debug!("Write barrier on object {:p}", object);
+            trace!(target: "rc_collector", "Write barrier on object {:p}", object);
             self.modified(object);
-            for child in unsafe{ (*object).children() } {
-                self.decrement(child);
+            // The current values of the remembered members were not counted.
+            let remembered = self.modified_members.remove(&object)
+                                                  .unwrap_or_else(HashSet::new);
+            for (num, child) in unsafe{ (*object).enumerate_children() } {
+                if !remembered.contains(&num) {
+                    self.decrement(child);
+                }
             }
             self.write_barrier_counter += 1;
         }
-        WRITE_BARRIER_COLLECT_THRESHOLD > 0 &&
-            self.write_barrier_counter >= WRITE_BARRIER_COLLECT_THRESHOLD
+        self.write_barrier_collect_threshold > 0 &&
+            self.write_barrier_counter >= self.write_barrier_collect_threshold
+    }
+
+    /// Set the member at position `num` of the `object` to `member` with a
+    /// write barrier for this member only.
+    ///
+    /// The first time a member is changed after a collection a decrement is
+    /// enqueued for its old value and the member is remembered. Members of
+    /// objects that are logged (see `write_barrier()`) or new are not
+    /// remembered because all their members are incremented anyway.
+    ///
+    /// Returns if a collection should be triggered (see
+    /// `GCConfig::write_barrier_collect_threshold()`).
+    pub fn write_member(&mut self, object: GCObjectRef, num: usize,
+                        member: GCObjectRef) -> bool {
+        let is_counted = unsafe{ !(*object).is_logged() && !(*object).is_new() };
+        if is_counted && !self.is_modified_member(object, num) {
+            trace!(target: "rc_collector",
+                   "Write barrier on member {} of object {:p}", num, object);
+            self.modified_members.entry(object).or_insert_with(HashSet::new).insert(num);
+            let old_member = unsafe{ (*object).store_member(num, member) };
+            if !old_member.is_null() {
+                self.decrement(old_member);
+            }
+            self.write_barrier_counter += 1;
+        } else {
+            unsafe{ (*object).store_member(num, member); }
+        }
+        self.write_barrier_collect_threshold > 0 &&
+            self.write_barrier_counter >= self.write_barrier_collect_threshold
     }
 }
 
 impl RCCollector {
+    /// Return if the member at position `num` of the `object` was remembered
+    /// by `write_member()`.
+    fn is_modified_member(&self, object: GCObjectRef, num: usize) -> bool {
+        self.modified_members.get(&object).map_or(false, |members| members.contains(&num))
+    }
+
     /// Push an object into the modified buffer.
     fn modified(&mut self, object: GCObjectRef) {
-        debug!("Push object {:p} into mod buffer", object);
+        trace!(target: "rc_collector", "Push object {:p} into mod buffer", object);
         self.modified_buffer.push_back(object);
     }
 
     /// Enqueue a decrement for an object.
     fn decrement(&mut self, object: GCObjectRef) {
-        debug!("Push object {:p} into dec buffer", object);
+        trace!(target: "rc_collector", "Push object {:p} into dec buffer", object);
         self.decrement_buffer.push_back(object);
     }
 
@@ -116,45 +229,84 @@ impl RCCollector {
     /// If `try_evacuate` is set, the object is new an in the immix space and
     /// the collectors performs an opportunistic evacuation, this function
     /// tries to evacuate the object into a free block.
-    fn increment(&mut self, immix_space: &mut ImmixSpace,
+    fn increment(&mut self, immix_space: &ImmixSpace,
                  object: GCObjectRef, try_evacuate: bool) -> Option<GCObjectRef> {
-        debug!("Increment object {:p}", object);
-        if unsafe{ (*object).increment() } {
-            if try_evacuate && self.perform_evac && immix_space.is_gc_object(object) {
-                if let Some(new_object) = immix_space.maybe_evacuate(object) {
-                    debug!("Evacuated object {:p} to {:p}", object, new_object);
-                    immix_space.decrement_lines(object);
-                    self.modified(new_object);
-                    return Some(new_object);
-                }
+        let modified_buffer = &mut self.modified_buffer;
+        RCCollector::increment_object(immix_space, object,
+                                      try_evacuate && self.perform_evac,
+                                      |object| modified_buffer.push_back(object))
+    }
+
+    /// Increment the `object` and call `modified` with it if this is the
+    /// first time the reference counting collector encounters it. Return
+    /// the new address if it was evacuated.
+    ///
+    /// If `evacuate` is set, a new object in the immix space is evacuated
+    /// before the increment. Threads evacuating the same object concurrently
+    /// get the same new address (see `ImmixSpace::maybe_evacuate()`), so
+    /// every increment is applied to the new object.
+    fn increment_object<F>(immix_space: &ImmixSpace, mut object: GCObjectRef,
+                           evacuate: bool, mut modified: F) -> Option<GCObjectRef>
+        where F: FnMut(GCObjectRef) {
+        trace!(target: "rc_collector", "Increment object {:p}", object);
+        let mut evacuated = None;
+        if evacuate && unsafe{ (*object).is_new() } && immix_space.is_in_space(object) {
+            if let Some(new_object) = immix_space.maybe_evacuate(object) {
+                trace!(target: "rc_collector",
+                       "Evacuated object {:p} to {:p}", object, new_object);
+                object = new_object;
+                evacuated = Some(new_object);
             }
-            self.modified(object);
         }
-        None
+        if unsafe{ (*object).increment() } {
+            RCCollector::set_live(immix_space, object);
+            trace!(target: "rc_collector", "Push object {:p} into mod buffer", object);
+            modified(object);
+        }
+        evacuated
+    }
+
+    /// Set a new object that was reached for the first time as a valid
+    /// object and increment the lines it is allocated on.
+    fn set_live(immix_space: &ImmixSpace, object: GCObjectRef) {
+        if immix_space.is_in_space(object) {
+            immix_space.set_gc_object(object);
+            immix_space.increment_lines(object);
+        }
     }
 
     /// The old roots are enqueued for a decrement.
     fn process_old_roots(&mut self) {
-        debug!("Process old roots (size {})", self.old_root_buffer.len());
+        debug!(target: "rc_collector",
+               "Process old roots (size {})", self.old_root_buffer.len());
         self.decrement_buffer.extend(self.old_root_buffer.drain(..));
     }
 
-    /// The current roots are incremented (but never evacuated) and stored as
-    /// old roots.
-    fn process_current_roots(&mut self, immix_space: &mut ImmixSpace,
+    /// The current roots are incremented and stored as old roots.
+    ///
+    /// New roots that are not pinned (precise roots) may be evacuated. The
+    /// `Spaces` update their slots after the collection.
+    fn process_current_roots(&mut self, immix_space: &ImmixSpace,
                              roots: &[GCObjectRef]) {
-        debug!("Process current roots (size {})", roots.len());
-        for root in roots.iter().map(|o| *o) {
-            debug!("Process root object: {:p}", root);
-            self.increment(immix_space, root, false);
+        debug!(target: "rc_collector",
+               "Process current roots (size {})", roots.len());
+        for mut root in roots.iter().map(|o| *o) {
+            trace!(target: "rc_collector", "Process root object: {:p}", root);
+            while let Some(new_root) = unsafe{ (*root).is_forwarded() } {
+                root = new_root;
+            }
+            if let Some(new_root) = self.increment(immix_space, root, true) {
+                root = new_root;
+            }
             self.old_root_buffer.push(root);
         }
     }
 
     /// Objects (roots) in the large object space are temporarily incremented.
-    fn process_los_new_objects(&mut self, immix_space: &mut ImmixSpace,
+    fn process_los_new_objects(&mut self, immix_space: &ImmixSpace,
                                new_objects: Vec<GCObjectRef>) {
-        debug!("Process los new_objects (size {})", new_objects.len());
+        debug!(target: "rc_collector",
+               "Process los new_objects (size {})", new_objects.len());
         for object in new_objects {
             self.increment(immix_space, object, false);
             self.decrement(object);
@@ -163,52 +315,168 @@ impl RCCollector {
 
     /// For deferred coalesced reference counting every remembered object will
     /// be processed to increment (and potentially evacuate) the members.
-    fn process_mod_buffer(&mut self, immix_space: &mut ImmixSpace) {
-        debug!("Process mod buffer (size {})", self.modified_buffer.len());
-        while let Some(object) = self.modified_buffer.pop_front() {
-            debug!("Process object {:p} in mod buffer", object);
-            unsafe { (*object).set_logged(false); }
-            if immix_space.is_in_space(object) {
-                immix_space.set_gc_object(object);
-                immix_space.increment_lines(object);
+    fn process_mod_buffer(&mut self, immix_space: &ImmixSpace, workers: &WorkerPool) {
+        debug!(target: "rc_collector",
+               "Process mod buffer (size {})", self.modified_buffer.len());
+        let evacuate = self.perform_evac;
+        if workers.threads() > 1 && self.modified_buffer.len() >= PARALLEL_BUFFER_THRESHOLD {
+            let objects = self.modified_buffer.drain(..).collect();
+            WorkStacks::new(workers.threads(), objects).process(workers, |object, modified| {
+                RCCollector::process_modified(immix_space, evacuate, object,
+                                              |child| modified.push(child));
+            });
+        } else {
+            let modified_buffer = &mut self.modified_buffer;
+            while let Some(object) = modified_buffer.pop_front() {
+                RCCollector::process_modified(immix_space, evacuate, object,
+                                              |child| modified_buffer.push_back(child));
+            }
+        }
+    }
+
+    /// Increment (and potentially evacuate if `evacuate` is set) the members
+    /// of a modified `object`. The members reached for the first time are
+    /// passed to `modified`.
+    fn process_modified<F>(immix_space: &ImmixSpace, evacuate: bool,
+                           mut object: GCObjectRef, mut modified: F)
+        where F: FnMut(GCObjectRef) {
+        trace!(target: "rc_collector",
+               "Process object {:p} in mod buffer", object);
+        while let Some(new_object) = unsafe{ (*object).is_forwarded() } {
+            object = new_object;
+        }
+        unsafe { (*object).set_logged(false); }
+        let children = unsafe{ (*object).enumerate_children() };
+        for (num, child) in children {
+            if let Some(new_child) = unsafe{ (*child).is_forwarded() } {
+                trace!(target: "rc_collector",
+                       "Child {:p} is forwarded to {:p}", child, new_child);
+                unsafe{ (*object).set_member(num, new_child); }
+                RCCollector::increment_object(immix_space, new_child, false, &mut modified);
+            } else if let Some(new_child) = RCCollector::increment_object(immix_space, child,
+                                                                          evacuate,
+                                                                          &mut modified) {
+                unsafe{ (*object).set_member(num, new_child); }
             }
-            let children = unsafe{ (*object).children() };
-            for (num, child) in children.enumerate() {
+        }
+    }
+
+    /// Every remembered member (see `write_member()`) is incremented (and
+    /// potentially evacuated) like the members of the remembered objects.
+    fn process_modified_members(&mut self, immix_space: &ImmixSpace) {
+        debug!(target: "rc_collector",
+               "Process modified members (size {})", self.modified_members.len());
+        let modified_members: Vec<_> = self.modified_members.drain().collect();
+        for (mut object, members) in modified_members {
+            while let Some(new_object) = unsafe{ (*object).is_forwarded() } {
+                object = new_object;
+            }
+            for num in members {
+                let child = unsafe{ (*object).member(num) };
+                trace!(target: "rc_collector",
+                       "Process member {} ({:p}) of object {:p}", num, child, object);
+                if child.is_null() {
+                    continue;
+                }
                 if let Some(new_child) = unsafe{ (*child).is_forwarded() } {
-                    debug!("Child {:p} is forwarded to {:p}", child, new_child);
                     unsafe{ (*object).set_member(num, new_child); }
-                    self.increment(immix_space, child, false);
-                } else {
-                    if let Some(new_child) = self.increment(immix_space,
-                                                            child, true) {
-                        unsafe{ (*object).set_member(num, new_child); }
-                    }
+                    self.increment(immix_space, new_child, false);
+                } else if let Some(new_child) = self.increment(immix_space, child, true) {
+                    unsafe{ (*object).set_member(num, new_child); }
                 }
             }
         }
     }
 
+    /// New objects in the immix space that were not reached are dead. Those
+    /// with a finalizer are enqueued for finalization and resurrected like
+    /// roots (they are decremented by the next collection).
+    fn process_dead_new_objects(&mut self, immix_space: &ImmixSpace,
+                                finalizers: &mut Finalizers, workers: &WorkerPool) {
+        let dead_objects = finalizers.enqueue_dead(|o| !immix_space.is_in_space(o)
+                                                       || immix_space.is_gc_object(o));
+        debug!(target: "rc_collector",
+               "Process dead new objects with finalizer (size {})", dead_objects.len());
+        for object in dead_objects {
+            self.increment(immix_space, object, false);
+            self.old_root_buffer.push(object);
+        }
+        self.process_mod_buffer(immix_space, workers);
+    }
+
     /// The enqueued decrements are applied.
     ///
     /// If the reference counter drops to zero the memory is reclaimed and the
-    /// members are enqueued for a decrement.
-    fn process_decrement_buffer(&mut self, immix_space: &mut ImmixSpace,
-                                large_object_space: &mut LargeObjectSpace) {
-        debug!("Process dec buffer (size {})", self.decrement_buffer.len());
-        while let Some(object) =  self.decrement_buffer.pop_front() {
-            debug!("Process object {:p} in dec buffer", object);
-            if unsafe{ (*object).decrement() && !(*object).is_pinned() }  {
-                for child in unsafe{ (*object).children() } {
-                    self.decrement(child);
-                }
-                if immix_space.is_gc_object(object) {
-                    immix_space.decrement_lines(object);
-                    immix_space.unset_gc_object(object);
-                    valgrind_freelike!(object);
-                } else if large_object_space.is_gc_object(object) {
-                    large_object_space.enqueue_free(object);
-                }
+    /// members are enqueued for a decrement. Objects with a finalizer are
+    /// enqueued for finalization and resurrected instead (they are
+    /// decremented by the next collection).
+    fn process_decrement_buffer(&mut self, immix_space: &ImmixSpace,
+                                large_object_space: &LargeObjectSpace,
+                                finalizers: &mut Finalizers, workers: &WorkerPool) {
+        debug!(target: "rc_collector",
+               "Process dec buffer (size {})", self.decrement_buffer.len());
+        let mut resurrection = Resurrection {
+            finalizers: finalizers,
+            old_root_buffer: &mut self.old_root_buffer,
+        };
+        if workers.threads() > 1 && self.decrement_buffer.len() >= PARALLEL_BUFFER_THRESHOLD {
+            let objects = self.decrement_buffer.drain(..).collect();
+            let resurrection = Mutex::new(resurrection);
+            WorkStacks::new(workers.threads(), objects).process(workers, |object, decrements| {
+                RCCollector::process_decrement(immix_space, large_object_space, object,
+                                               |o| resurrection.lock().unwrap().resurrect(o),
+                                               |child| decrements.push(child));
+            });
+        } else {
+            let decrement_buffer = &mut self.decrement_buffer;
+            while let Some(object) = decrement_buffer.pop_front() {
+                RCCollector::process_decrement(immix_space, large_object_space, object,
+                                               |o| resurrection.resurrect(o),
+                                               |child| decrement_buffer.push_back(child));
+            }
+        }
+    }
+
+    /// Apply a decrement to the `object`.
+    ///
+    /// If its reference count drops to zero it is passed to `resurrect`. If
+    /// that returns `false` its members are passed to `decrement` and its
+    /// memory is reclaimed.
+    fn process_decrement<R, D>(immix_space: &ImmixSpace,
+                               large_object_space: &LargeObjectSpace,
+                               object: GCObjectRef, mut resurrect: R, mut decrement: D)
+        where R: FnMut(GCObjectRef) -> bool, D: FnMut(GCObjectRef) {
+        trace!(target: "rc_collector",
+               "Process object {:p} in dec buffer", object);
+        if unsafe{ (*object).decrement() && !(*object).is_pinned() }  {
+            if resurrect(object) {
+                unsafe{ (*object).increment(); }
+                return;
+            }
+            for child in unsafe{ (*object).children() } {
+                trace!(target: "rc_collector", "Push object {:p} into dec buffer", child);
+                decrement(child);
+            }
+            if immix_space.is_gc_object(object) {
+                immix_space.decrement_lines(object);
+                immix_space.unset_gc_object(object);
+                valgrind_freelike!(object);
+            } else if large_object_space.is_gc_object(object) {
+                large_object_space.enqueue_free(object);
             }
         }
     }
 }
+
+impl<'a> Resurrection<'a> {
+    /// Enqueue the dead `object` for finalization and push it onto the old
+    /// roots. Return `false` if it has no (pending) finalizer.
+    fn resurrect(&mut self, object: GCObjectRef) -> bool {
+        if self.finalizers.enqueue(object) {
+            self.old_root_buffer.push(object);
+            true
+        } else {
+            false
+        }
+    }
+}
@@ -0,0 +1,118 @@
+// Copyright (c) <2015> <lummax>
+// Licensed under MIT (http://opensource.org/licenses/MIT)
+
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use spaces::collector::worker_pool::WorkerPool;
+use gc_object::GCObjectRef;
+
+/// The `WorkStacks` hold the objects still to be processed by the GC worker
+/// threads of a parallel collection step.
+///
+/// Every thread pushes onto and pops from its own stack. A thread whose
+/// stack ran empty steals half of the objects of another stack. The step is
+/// complete when no object is pending anymore.
+pub struct WorkStacks {
+    /// The stack of every thread.
+    stacks: Vec<Mutex<Vec<GCObjectRef>>>,
+
+    /// The number of objects pushed but not yet processed.
+    pending: AtomicUsize,
+}
+
+// The objects are only exchanged between the collector threads while the
+// mutator threads are stopped.
+unsafe impl Sync for WorkStacks {}
+
+impl WorkStacks {
+    /// Create new `WorkStacks` for `threads` threads and distribute the
+    /// `objects` among them.
+    pub fn new(threads: usize, objects: Vec<GCObjectRef>) -> WorkStacks {
+        let mut stacks: Vec<Vec<GCObjectRef>> = (0..threads).map(|_| Vec::new()).collect();
+        let pending = objects.len();
+        for (num, object) in objects.into_iter().enumerate() {
+            stacks[num % threads].push(object);
+        }
+        WorkStacks {
+            stacks: stacks.into_iter().map(Mutex::new).collect(),
+            pending: AtomicUsize::new(pending),
+        }
+    }
+
+    /// Process all objects with the `workers`, one per stack, and return
+    /// when no object is pending anymore.
+    ///
+    /// `process` is called for every object with a buffer to push further
+    /// objects to process onto.
+    pub fn process<F>(&self, workers: &WorkerPool, process: F)
+        where F: Fn(GCObjectRef, &mut Vec<GCObjectRef>) + Sync {
+        debug_assert!(workers.threads() == self.stacks.len(),
+                      "The number of workers does not match the stacks");
+        workers.run(|thread| {
+            let mut objects = Vec::new();
+            while let Some(object) = self.pop(thread) {
+                process(object, &mut objects);
+                self.push(thread, &mut objects);
+                self.done();
+            }
+        });
+    }
+}
+
+impl WorkStacks {
+    /// Move the `objects` onto the stack of `thread`.
+    fn push(&self, thread: usize, objects: &mut Vec<GCObjectRef>) {
+        if objects.is_empty() {
+            return;
+        }
+        self.pending.fetch_add(objects.len(), Ordering::SeqCst);
+        self.stacks[thread].lock().unwrap().extend(objects.drain(..));
+    }
+
+    /// Pop an object from the stack of `thread` or steal one. Return `None`
+    /// if no object is pending anymore.
+    ///
+    /// Call `done()` after the object was processed.
+    fn pop(&self, thread: usize) -> Option<GCObjectRef> {
+        loop {
+            if let Some(object) = self.stacks[thread].lock().unwrap().pop() {
+                return Some(object);
+            }
+            if self.steal(thread) {
+                continue;
+            }
+            if self.pending.load(Ordering::SeqCst) == 0 {
+                return None;
+            }
+            thread::yield_now();
+        }
+    }
+
+    /// Mark an object returned by `pop()` as processed. The objects pushed
+    /// while processing it must be pushed before.
+    fn done(&self) {
+        self.pending.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    /// Move half of the objects of another stack onto the stack of `thread`
+    /// and return if any object was stolen.
+    fn steal(&self, thread: usize) -> bool {
+        for offset in 1..self.stacks.len() {
+            let victim = (thread + offset) % self.stacks.len();
+            let stolen: Vec<GCObjectRef> = {
+                let mut stack = self.stacks[victim].lock().unwrap();
+                let half = (stack.len() + 1) / 2;
+                stack.drain(..half).collect()
+            };
+            if !stolen.is_empty() {
+                trace!("Thread {} stole {} objects from thread {}", thread,
+                       stolen.len(), victim);
+                self.stacks[thread].lock().unwrap().extend(stolen);
+                return true;
+            }
+        }
+        false
+    }
+}
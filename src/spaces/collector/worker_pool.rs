@@ -0,0 +1,169 @@
+// Copyright (c) <2015> <lummax>
+// Licensed under MIT (http://opensource.org/licenses/MIT)
+
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+
+/// The `WorkerPool` owns the GC worker threads of the parallel collection
+/// steps (see `GCConfig::with_gc_threads()`).
+///
+/// The threads are started once with the `Collector` and wait between the
+/// steps, so a step only costs a wakeup. The thread performing the
+/// collection takes part in every step as the first worker.
+pub struct WorkerPool {
+    /// The state shared with the worker threads.
+    shared: Arc<Shared>,
+
+    /// The worker threads besides the collecting thread.
+    threads: Vec<JoinHandle<()>>,
+}
+
+/// The state shared between the `WorkerPool` and its threads.
+struct Shared {
+    /// The current step.
+    state: Mutex<State>,
+
+    /// Signaled when a step is started or the pool is shut down.
+    start: Condvar,
+
+    /// Signaled when the last worker thread finished a step.
+    finished: Condvar,
+}
+
+/// The current step of a `WorkerPool`.
+struct State {
+    /// The work of the current step.
+    job: Option<Job>,
+
+    /// The number of the current step. A worker thread runs a step once.
+    step: usize,
+
+    /// The number of worker threads still running the current step.
+    running: usize,
+
+    /// Whether a worker thread panicked during the current step.
+    panicked: bool,
+
+    /// Whether the worker threads should exit.
+    shutdown: bool,
+}
+
+/// The work of a step: a closure borrowed from `WorkerPool::run()` and the
+/// function calling it with the index of a worker.
+#[derive(Clone, Copy)]
+struct Job {
+    /// The address of the closure.
+    work: *const (),
+
+    /// Call the closure at `work` with the index of a worker.
+    call: unsafe fn(*const (), usize),
+}
+
+// The closure of a `Job` is `Sync` and `WorkerPool::run()` does not return
+// before every worker thread finished calling it.
+unsafe impl Send for Job {}
+
+impl WorkerPool {
+    /// Create a new `WorkerPool` with `threads` workers, including the
+    /// collecting thread.
+    pub fn new(threads: usize) -> WorkerPool {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                job: None,
+                step: 0,
+                running: 0,
+                panicked: false,
+                shutdown: false,
+            }),
+            start: Condvar::new(),
+            finished: Condvar::new(),
+        });
+        let threads = (1..threads).map(|worker| {
+            let shared = shared.clone();
+            thread::Builder::new().name(format!("rcx-gc-worker-{}", worker))
+                                  .spawn(move || shared.work(worker))
+                                  .expect("Could not start a GC worker thread")
+        }).collect();
+        WorkerPool {
+            shared: shared,
+            threads: threads,
+        }
+    }
+
+    /// Return the number of workers, including the collecting thread.
+    pub fn threads(&self) -> usize {
+        self.threads.len() + 1
+    }
+
+    /// Call `work` with the index of every worker in parallel and return
+    /// when all calls returned.
+    ///
+    /// A panic of any worker is propagated to the caller.
+    pub fn run<F>(&self, work: F) where F: Fn(usize) + Sync {
+        unsafe fn call<F>(work: *const (), worker: usize) where F: Fn(usize) + Sync {
+            (*(work as *const F))(worker)
+        }
+        if !self.threads.is_empty() {
+            let mut state = self.shared.state.lock().unwrap();
+            state.job = Some(Job {
+                work: &work as *const F as *const (),
+                call: call::<F>,
+            });
+            state.step += 1;
+            state.running = self.threads.len();
+            self.shared.start.notify_all();
+        }
+        let result = panic::catch_unwind(AssertUnwindSafe(|| work(0)));
+        let mut state = self.shared.state.lock().unwrap();
+        while state.running > 0 {
+            state = self.shared.finished.wait(state).unwrap();
+        }
+        state.job = None;
+        let panicked = mem::replace(&mut state.panicked, false);
+        drop(state);
+        if let Err(payload) = result {
+            panic::resume_unwind(payload);
+        }
+        assert!(!panicked, "A GC worker thread panicked");
+    }
+}
+
+impl Drop for WorkerPool {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().shutdown = true;
+        self.shared.start.notify_all();
+        for thread in self.threads.drain(..) {
+            thread.join().unwrap();
+        }
+    }
+}
+
+impl Shared {
+    /// The loop of the worker thread with the index `worker`: wait for a
+    /// step, run its job and report that it finished.
+    fn work(&self, worker: usize) {
+        let mut step = 0;
+        loop {
+            let job = {
+                let mut state = self.state.lock().unwrap();
+                while state.step == step && !state.shutdown {
+                    state = self.start.wait(state).unwrap();
+                }
+                if state.shutdown {
+                    return;
+                }
+                step = state.step;
+                state.job.expect("A step was started without a job")
+            };
+            let result = panic::catch_unwind(|| unsafe{ (job.call)(job.work, worker) });
+            let mut state = self.state.lock().unwrap();
+            state.panicked |= result.is_err();
+            state.running -= 1;
+            if state.running == 0 {
+                self.finished.notify_all();
+            }
+        }
+    }
+}
@@ -0,0 +1,120 @@
+// Copyright (c) <2015> <lummax>
+// Licensed under MIT (http://opensource.org/licenses/MIT)
+
+use std::collections::{HashSet, VecDeque};
+
+use spaces::immix_space::ImmixSpace;
+use spaces::large_object_space::LargeObjectSpace;
+use gc_object::GCObjectRef;
+
+/// The `Finalizers` keeps track of the objects with a finalizer (see
+/// `GCRTTI::with_finalizer()`).
+///
+/// If a collector finds such an object dead, it enqueues the object and
+/// resurrects it (and the objects reachable from it) instead of reclaiming
+/// it. The enqueued objects are roots until their finalizer completed. As
+/// the object is not finalizable afterwards, it is reclaimed by the next
+/// collection that finds it dead again.
+pub struct Finalizers {
+    /// The objects with a finalizer that were not enqueued yet.
+    objects: HashSet<GCObjectRef>,
+
+    /// The objects whose finalizers are queued to run.
+    pending: VecDeque<GCObjectRef>,
+
+    /// The objects whose finalizers are currently running.
+    running: Vec<GCObjectRef>,
+}
+
+impl Finalizers {
+    /// Create a new `Finalizers`.
+    pub fn new() -> Finalizers {
+        Finalizers {
+            objects: HashSet::new(),
+            pending: VecDeque::new(),
+            running: Vec::new(),
+        }
+    }
+
+    /// Register newly allocated objects with a finalizer.
+    pub fn register(&mut self, objects: Vec<GCObjectRef>) {
+        self.objects.extend(objects);
+    }
+
+    /// Enqueue the `object` for finalization if it has a finalizer that was
+    /// not enqueued yet. Returns if it was enqueued.
+    pub fn enqueue(&mut self, object: GCObjectRef) -> bool {
+        if self.objects.remove(&object) {
+            trace!("Enqueue object {:p} for finalization", object);
+            self.pending.push_back(object);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Enqueue every object with a finalizer (following forwarding pointers)
+    /// for which `is_live` returns `false` and return them.
+    pub fn enqueue_dead<F: Fn(GCObjectRef) -> bool>(&mut self, is_live: F)
+                                                    -> Vec<GCObjectRef> {
+        let dead: Vec<GCObjectRef> = self.objects.iter()
+            .filter(|&&object| !is_live(Finalizers::resolve(object)))
+            .map(|&object| object)
+            .collect();
+        dead.into_iter().map(|object| {
+            self.objects.remove(&object);
+            let object = Finalizers::resolve(object);
+            trace!("Enqueue object {:p} for finalization", object);
+            self.pending.push_back(object);
+            object
+        }).collect()
+    }
+
+    /// Return the enqueued and running objects. These are roots.
+    pub fn roots(&self) -> Vec<GCObjectRef> {
+        self.pending.iter().chain(self.running.iter()).map(|&o| o).collect()
+    }
+
+    /// Take the next enqueued object to run its finalizer or return `None`.
+    ///
+    /// The object stays a root until `complete()` is called.
+    pub fn next(&mut self) -> Option<GCObjectRef> {
+        let object = self.pending.pop_front();
+        if let Some(object) = object {
+            self.running.push(object);
+        }
+        object
+    }
+
+    /// Mark the finalizer of `object` as completed.
+    pub fn complete(&mut self, object: GCObjectRef) {
+        if let Some(position) = self.running.iter().position(|&o| o == object) {
+            self.running.swap_remove(position);
+        }
+    }
+
+    /// Update the objects with a finalizer after a collection.
+    ///
+    /// Objects that were evacuated are replaced by their new address. This
+    /// must be called before any new object is allocated.
+    pub fn process(&mut self, immix_space: &ImmixSpace,
+                   large_object_space: &LargeObjectSpace) {
+        debug!("Process {} finalizable objects", self.objects.len());
+        self.objects = self.objects.drain()
+            .map(|object| if large_object_space.is_gc_object(object) { object }
+                          else { Finalizers::resolve(object) })
+            .filter(|&object| immix_space.is_gc_object(object)
+                              || large_object_space.is_gc_object(object))
+            .collect();
+    }
+}
+
+impl Finalizers {
+    /// Follow the forwarding pointers of `object`.
+    fn resolve(mut object: GCObjectRef) -> GCObjectRef {
+        while let Some(new_object) = unsafe{ (*object).is_forwarded() } {
+            object = new_object;
+        }
+        object
+    }
+}
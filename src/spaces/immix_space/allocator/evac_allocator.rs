@@ -74,7 +74,7 @@ impl Allocator for EvacAllocator {
     }
 
     fn handle_full_block(&mut self, block: *mut BlockInfo) {
-        debug!("Push block {:p} into unavailable_blocks", block);
+        trace!("Push block {:p} into unavailable_blocks", block);
         self.unavailable_blocks.push(block);
     }
 }
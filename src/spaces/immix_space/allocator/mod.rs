@@ -49,7 +49,7 @@ pub trait Allocator {
     /// Is no hole `handle_no_hole()` will be called. If this function returns
     /// `None` a 'get_new_block()' is requested.
     fn allocate(&mut self, size: usize) -> Option<GCObjectRef> {
-        debug!("Request to allocate an object of size {}", size);
+        trace!("Request to allocate an object of size {}", size);
         self.take_current_block()
             .and_then(|tp| self.scan_for_hole(size, tp))
             .or_else(|| self.handle_no_hole(size))
@@ -71,7 +71,7 @@ pub trait Allocator {
         let (block, low, high) = block_tuple;
         match (high - low) as usize >= size {
             true => {
-                debug!("Found hole in block {:p}", block);
+                trace!("Found hole in block {:p}", block);
                 Some(block_tuple)
             },
             false => match unsafe{ (*block).scan_block(high) } {
@@ -95,7 +95,7 @@ pub trait Allocator {
         -> (BlockTuple, GCObjectRef) {
             let (block, low, high) = block_tuple;
             let object = unsafe { (*block).offset(low as usize) };
-            debug!("Allocated object {:p} of size {} in {:p} (object={})",
+            trace!("Allocated object {:p} of size {} in {:p} (object={})",
                    object, size, block, size >= LINE_SIZE);
             ((block, low + size as u16, high), object)
         }
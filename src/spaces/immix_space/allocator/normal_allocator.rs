@@ -6,50 +6,51 @@ use spaces::immix_space::block_allocator::BlockAllocator;
 use spaces::immix_space::allocator::BlockTuple;
 use spaces::immix_space::allocator::Allocator;
 
-use std::rc::Rc;
-use std::cell::RefCell;
+use std::sync::{Arc, Mutex};
 
 use constants::{BLOCK_SIZE, LINE_SIZE};
 
 /// The `NormalAllocator` is the standard allocator to allocate objects within
 /// the immix space.
 ///
-/// Objects smaller than `MEDIUM_OBJECT` bytes are
+/// Objects smaller than `MEDIUM_OBJECT` bytes are allocated into the holes of
+/// recyclable blocks or into new blocks. Every mutator thread owns its own
+/// `NormalAllocator`.
+///
+/// In nursery mode new blocks are marked as nursery blocks and preferred over
+/// recyclable blocks, whose holes are only used if no free block is left.
 pub struct NormalAllocator {
     /// The global `BlockAllocator` to get new blocks from.
-    block_allocator: Rc<RefCell<BlockAllocator>>,
+    block_allocator: Arc<Mutex<BlockAllocator>>,
 
     /// The exhausted blocks.
     unavailable_blocks: Vec<*mut BlockInfo>,
 
-    /// The blocks with holes to recycle before requesting new blocks..
-    recyclable_blocks: Vec<*mut BlockInfo>,
-
     /// The current block to allocate from.
     current_block: Option<BlockTuple>,
+
+    /// Whether new blocks are nursery blocks.
+    nursery: bool,
 }
 
 impl NormalAllocator {
     /// Create a new `NormalAllocator` backed by the given `BlockAllocator`.
-    pub fn new(block_allocator: Rc<RefCell<BlockAllocator>>) -> NormalAllocator {
+    ///
+    /// If `nursery` is set new blocks are marked as nursery blocks.
+    pub fn new(block_allocator: Arc<Mutex<BlockAllocator>>,
+               nursery: bool) -> NormalAllocator {
         NormalAllocator {
             block_allocator: block_allocator,
             unavailable_blocks: Vec::new(),
-            recyclable_blocks: Vec::new(),
             current_block: None,
+            nursery: nursery,
         }
     }
-
-    /// Set the recyclable blocks.
-    pub fn set_recyclable_blocks(&mut self, blocks: Vec<*mut BlockInfo>) {
-        self.recyclable_blocks = blocks;
-    }
 }
 
 impl Allocator for NormalAllocator {
     fn get_all_blocks(&mut self) -> Vec<*mut BlockInfo> {
         self.unavailable_blocks.drain(..)
-            .chain(self.recyclable_blocks.drain(..))
             .chain(self.current_block.take().map(|b| b.0))
             .collect()
     }
@@ -64,32 +65,38 @@ impl Allocator for NormalAllocator {
 
     fn get_new_block(&mut self) -> Option<BlockTuple> {
         debug!("Request new block");
-        self.block_allocator.borrow_mut()
+        let nursery = self.nursery;
+        self.block_allocator.lock().unwrap()
             .get_block()
-            .map(|b| unsafe{ (*b).set_allocated(); b })
+            .map(|b| unsafe{ (*b).set_allocated(); (*b).set_nursery(nursery); b })
             .map(|block| (block, LINE_SIZE as u16, (BLOCK_SIZE - 1) as u16))
     }
 
     fn handle_no_hole(&mut self, size: usize) -> Option<BlockTuple> {
-        if size >= LINE_SIZE {
-            None
-        } else {
-            match self.recyclable_blocks.pop() {
-                None => None,
-                Some(block) => match unsafe{ (*block).scan_block((LINE_SIZE - 1) as u16) } {
-                    None => {
-                        self.handle_full_block(block);
-                        self.handle_no_hole(size)
-                    },
-                    Some((low, high)) => self.scan_for_hole(size, (block, low, high))
-                                            .or_else(|| self.handle_no_hole(size)),
-                }
+        let recyclable_block = {
+            let mut block_allocator = self.block_allocator.lock().unwrap();
+            if size >= LINE_SIZE
+               || (self.nursery && block_allocator.available_blocks() > 0) {
+                None
+            } else {
+                block_allocator.get_recyclable_block()
+            }
+        };
+        match recyclable_block {
+            None => None,
+            Some(block) => match unsafe{ (*block).scan_block((LINE_SIZE - 1) as u16) } {
+                None => {
+                    self.handle_full_block(block);
+                    self.handle_no_hole(size)
+                },
+                Some((low, high)) => self.scan_for_hole(size, (block, low, high))
+                                        .or_else(|| self.handle_no_hole(size)),
             }
         }
     }
 
     fn handle_full_block(&mut self, block: *mut BlockInfo) {
-        debug!("Push block {:p} into unavailable_blocks", block);
+        trace!("Push block {:p} into unavailable_blocks", block);
         self.unavailable_blocks.push(block);
     }
 }
@@ -6,8 +6,7 @@ use spaces::immix_space::block_allocator::BlockAllocator;
 use spaces::immix_space::allocator::BlockTuple;
 use spaces::immix_space::allocator::Allocator;
 
-use std::rc::Rc;
-use std::cell::RefCell;
+use std::sync::{Arc, Mutex};
 
 use constants::{BLOCK_SIZE, LINE_SIZE};
 
@@ -16,22 +15,29 @@ use constants::{BLOCK_SIZE, LINE_SIZE};
 /// limit fragmentation in the `NormalAllocator`.
 pub struct OverflowAllocator {
     /// The global `BlockAllocator` to get new blocks from.
-    block_allocator: Rc<RefCell<BlockAllocator>>,
+    block_allocator: Arc<Mutex<BlockAllocator>>,
 
     /// The exhausted blocks.
     unavailable_blocks: Vec<*mut BlockInfo>,
 
     /// The current block to allocate from.
     current_block: Option<BlockTuple>,
+
+    /// Whether new blocks are nursery blocks.
+    nursery: bool,
 }
 
 impl OverflowAllocator {
     /// Create a new `OverflowAllocator` backed by the given `BlockAllocator`.
-    pub fn new(block_allocator: Rc<RefCell<BlockAllocator>>) -> OverflowAllocator {
+    ///
+    /// If `nursery` is set new blocks are marked as nursery blocks.
+    pub fn new(block_allocator: Arc<Mutex<BlockAllocator>>,
+               nursery: bool) -> OverflowAllocator {
         OverflowAllocator {
             block_allocator: block_allocator,
             unavailable_blocks: Vec::new(),
             current_block: None,
+            nursery: nursery,
         }
     }
 }
@@ -53,9 +59,10 @@ impl Allocator for OverflowAllocator {
 
     fn get_new_block(&mut self) -> Option<BlockTuple> {
         debug!("Request new block");
-        self.block_allocator.borrow_mut()
+        let nursery = self.nursery;
+        self.block_allocator.lock().unwrap()
             .get_block()
-            .map(|b| unsafe{ (*b).set_allocated(); b })
+            .map(|b| unsafe{ (*b).set_allocated(); (*b).set_nursery(nursery); b })
             .map(|block| (block, LINE_SIZE as u16, (BLOCK_SIZE - 1) as u16))
 
     }
@@ -66,7 +73,7 @@ impl Allocator for OverflowAllocator {
     }
 
     fn handle_full_block(&mut self, block: *mut BlockInfo) {
-        debug!("Push block {:p} into unavailable_blocks", block);
+        trace!("Push block {:p} into unavailable_blocks", block);
         self.unavailable_blocks.push(block);
     }
 }
@@ -7,21 +7,23 @@ use libc;
 
 use spaces::immix_space::block_info::BlockInfo;
 
-use constants::{BLOCK_SIZE, HEAP_SIZE, TOTAL_BLOCKS};
-use gc_object::GCObjectRef;
+use constants::BLOCK_SIZE;
 
 /// A simple wrapper for a heap mmap.
 struct MemoryMap{
     /// The pointer to the mmap'ed region.
     mmap: *mut libc::c_void,
+
+    /// The usable size of the mmap'ed region in bytes.
+    size: usize,
 }
 
 impl MemoryMap {
-    /// Create a new `MemoryMap` of `HEAP_SIZE + BLOCK_SIZE` to be aligned to
+    /// Create a new `MemoryMap` of `size + BLOCK_SIZE` to be aligned to
     /// `BLOCK_SIZE` boundaries.
-    fn new() -> MemoryMap {
+    fn new(size: usize) -> MemoryMap {
         let mmap = unsafe {
-            libc::mmap(ptr::null_mut(), (HEAP_SIZE + BLOCK_SIZE) as libc::size_t,
+            libc::mmap(ptr::null_mut(), (size + BLOCK_SIZE) as libc::size_t,
                        libc::PROT_READ | libc::PROT_WRITE,
                       libc::MAP_PRIVATE | libc::MAP_ANON, -1, 0)
         };
@@ -32,6 +34,7 @@ impl MemoryMap {
 
         MemoryMap {
             mmap: mmap,
+            size: size,
         }
     }
 
@@ -48,14 +51,14 @@ impl MemoryMap {
 
     /// Return a pointer to the end of the mmap'ed region.
     fn bound(&self) -> *mut u8 {
-        unsafe{ self.mmap.offset(HEAP_SIZE as isize) as *mut u8 }
+        unsafe{ self.mmap.offset(self.size as isize) as *mut u8 }
     }
 }
 
 impl Drop for MemoryMap {
     fn drop(&mut self) {
         unsafe {
-            libc::munmap(self.mmap, (HEAP_SIZE + BLOCK_SIZE) as libc::size_t);
+            libc::munmap(self.mmap, (self.size + BLOCK_SIZE) as libc::size_t);
         }
     }
 }
@@ -64,7 +67,7 @@ impl Drop for MemoryMap {
 /// The `BlockAllocator` is the global resource for blocks for the immix
 /// space.
 ///
-/// On initialization it will allocate a memory map of `HEAP_SIZE` and align
+/// On initialization it will allocate a memory map of `heap_size` and align
 /// it to `BLOCK_SIZE`. During normal runtime it will allocate blocks on the
 /// fly from this memory map and store returned blocks in a list.
 ///
@@ -74,8 +77,11 @@ impl Drop for MemoryMap {
 /// first exhaust the returned free blocks and then fall back to allocating
 /// new blocks from the memory map. This means it will return recently
 /// returned blocks first.
+///
+/// It is shared by the thread-local allocators of all mutator threads and
+/// also hands out the recyclable blocks found by the last collection.
 pub struct BlockAllocator {
-    /// The memory map of `HEAP_SIZE`.
+    /// The memory map of `heap_size`.
     mmap: MemoryMap,
 
     /// The pointer to the last allocated block.
@@ -86,15 +92,20 @@ pub struct BlockAllocator {
 
     /// A list of returned (free) blocks.
     free_blocks: Vec<*mut BlockInfo>,
+
+    /// The blocks with holes to recycle before requesting new blocks.
+    recyclable_blocks: Vec<*mut BlockInfo>,
 }
 
+unsafe impl Send for BlockAllocator {}
+
 impl BlockAllocator {
-    /// Create a new `BlockAllocator`.
+    /// Create a new `BlockAllocator` managing `heap_size` bytes.
     ///
-    /// This will `panic` if no memory map of size `HEAP_SIZE` can be
+    /// This will `panic` if no memory map of size `heap_size` can be
     /// allocared.
-    pub fn new() -> BlockAllocator {
-        let mmap = MemoryMap::new();
+    pub fn new(heap_size: usize) -> BlockAllocator {
+        let mmap = MemoryMap::new(heap_size);
         let data = mmap.aligned();
         let bound = mmap.bound();
         debug_assert!((data as usize) % BLOCK_SIZE == 0,
@@ -104,29 +115,69 @@ impl BlockAllocator {
             mmap: mmap,
             data: data,
             data_bound: bound,
-            free_blocks: Vec::with_capacity(TOTAL_BLOCKS),
+            free_blocks: Vec::with_capacity(heap_size / BLOCK_SIZE),
+            recyclable_blocks: Vec::new(),
         }
     }
 
     /// Get a new block aligned to `BLOCK_SIZE`.
     pub fn get_block(&mut self) -> Option<*mut BlockInfo> {
-        self.free_blocks.pop().or_else(|| self.build_next_block())
+        let block = self.free_blocks.pop().or_else(|| self.build_next_block());
+        trace!(target: "block_allocator", "Hand out block {:?}", block);
+        block
     }
 
     /// Return a collection of blocks.
     pub fn return_blocks(&mut self, blocks: Vec<*mut BlockInfo>) {
+        debug!(target: "block_allocator", "Return {} free blocks", blocks.len());
         self.free_blocks.extend(blocks);
     }
 
-    /// Return the number of unallocated blocks.
-    pub fn available_blocks(&self) -> usize {
-        (((self.data_bound as usize) - (self.data as usize)) % BLOCK_SIZE) + self.free_blocks.len()
+    /// Get a block with holes to recycle or `None`.
+    pub fn get_recyclable_block(&mut self) -> Option<*mut BlockInfo> {
+        self.recyclable_blocks.pop()
+    }
+
+    /// Set the recyclable blocks.
+    pub fn set_recyclable_blocks(&mut self, blocks: Vec<*mut BlockInfo>) {
+        debug!(target: "block_allocator", "Set {} recyclable blocks", blocks.len());
+        self.recyclable_blocks = blocks;
+    }
+
+    /// Return the number of recyclable blocks.
+    pub fn recyclable_blocks(&self) -> usize {
+        self.recyclable_blocks.len()
     }
 
-    /// Return if an address is within the bounds of the memory map.
-    pub fn is_in_space(&self, object: GCObjectRef) -> bool {
-        self.mmap.start() < (object as *mut u8)
-            && (object as *mut u8) < self.data_bound
+    /// Get the recyclable blocks, draining the list.
+    pub fn get_recyclable_blocks(&mut self) -> Vec<*mut BlockInfo> {
+        self.recyclable_blocks.drain(..).collect()
+    }
+
+    /// Return the lower bound of the memory map.
+    pub fn space_start(&self) -> *mut u8 {
+        self.mmap.start()
+    }
+
+    /// Return the upper bound of the memory map.
+    pub fn space_bound(&self) -> *mut u8 {
+        self.data_bound
+    }
+
+    /// Return the blocks built from the memory map that are allocated (see
+    /// `BlockInfo::is_allocated()`).
+    pub fn allocated_blocks(&self) -> Vec<*mut BlockInfo> {
+        let aligned = self.mmap.aligned() as usize;
+        let num_blocks = (self.data as usize - aligned) / BLOCK_SIZE;
+        (1..(num_blocks + 1))
+            .map(|num| (aligned + num * BLOCK_SIZE) as *mut BlockInfo)
+            .filter(|&block| unsafe{ (*block).is_allocated() })
+            .collect()
+    }
+
+    /// Return the number of unallocated blocks.
+    pub fn available_blocks(&self) -> usize {
+        (((self.data_bound as usize) - (self.data as usize)) / BLOCK_SIZE) + self.free_blocks.len()
     }
 }
 
@@ -144,6 +195,7 @@ impl BlockAllocator {
             unsafe{ ptr::write(block as *mut BlockInfo, BlockInfo::new()); }
             Some(block as *mut BlockInfo)
         } else {
+            debug!(target: "block_allocator", "Memory map is exhausted");
             None
         }
     }
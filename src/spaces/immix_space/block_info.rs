@@ -2,63 +2,85 @@
 // Licensed under MIT (http://opensource.org/licenses/MIT)
 
 use std::collections::HashSet;
-
-use bit_set::BitSet;
-use vec_map::{VecMap, Entry};
+use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use constants::{BLOCK_SIZE, LINE_SIZE, NUM_LINES_PER_BLOCK};
 use gc_object::GCObjectRef;
 
+/// The number of bits in a word of an `ObjectMap`.
+const WORD_BITS: usize = usize::BITS as usize;
 
 /// A per block object map.
+///
+/// It is a bitmap of atomic words, so that the parallel collector threads
+/// can set objects concurrently.
 struct ObjectMap {
-    set: BitSet,
+    words: Vec<AtomicUsize>,
 }
 
 impl ObjectMap {
     /// Create a new `ObjectMap`.
     fn new() -> ObjectMap {
         ObjectMap {
-            set: BitSet::with_capacity(BLOCK_SIZE),
+            words: (0..BLOCK_SIZE / WORD_BITS).map(|_| AtomicUsize::new(0)).collect(),
         }
     }
 
-    /// Reduce the objects address to an offset within the block.
-    fn index(object: GCObjectRef) -> usize {
-        (object as usize) % BLOCK_SIZE
+    /// Reduce the objects address to the word and the bit within the word
+    /// of its offset within the block.
+    fn position(object: GCObjectRef) -> (usize, usize) {
+        let index = (object as usize) % BLOCK_SIZE;
+        (index / WORD_BITS, 1 << (index % WORD_BITS))
     }
 
     /// Set the address as a valid object.
-    fn set_object(&mut self, object: GCObjectRef) {
-        self.set.insert(ObjectMap::index(object));
+    fn set_object(&self, object: GCObjectRef) {
+        let (word, bit) = ObjectMap::position(object);
+        self.words[word].fetch_or(bit, Ordering::Relaxed);
     }
 
     /// Unset the address as a valid object.
-    fn unset_object(&mut self, object: GCObjectRef) {
-        self.set.remove(ObjectMap::index(object));
+    fn unset_object(&self, object: GCObjectRef) {
+        let (word, bit) = ObjectMap::position(object);
+        self.words[word].fetch_and(!bit, Ordering::Relaxed);
     }
 
     /// Return `true` is the address is a valid object.
     fn is_object(&self, object: GCObjectRef) -> bool {
-        self.set.contains(ObjectMap::index(object))
+        let (word, bit) = ObjectMap::position(object);
+        self.words[word].load(Ordering::Relaxed) & bit != 0
     }
 
     /// Update this `ObjectMap` with the difference of this `ObjectMap` and
     /// the other.
     fn difference(&mut self, other: &ObjectMap) {
-        self.set.difference_with(&other.set);
+        for (word, other_word) in self.words.iter().zip(&other.words) {
+            word.fetch_and(!other_word.load(Ordering::Relaxed), Ordering::Relaxed);
+        }
     }
 
     /// Clear all entries.
     fn clear(&mut self) {
-        self.set.clear();
+        for word in &self.words {
+            word.store(0, Ordering::Relaxed);
+        }
+    }
+
+    /// Return an iterator of the offsets of the valid objects.
+    fn iter<'a>(&'a self) -> impl Iterator<Item=usize> + 'a {
+        self.words.iter().enumerate().flat_map(|(num, word)| {
+            let bits = word.load(Ordering::Relaxed);
+            (0..WORD_BITS).filter(move |bit| bits & (1 << bit) != 0)
+                          .map(move |bit| num * WORD_BITS + bit)
+        })
     }
 
     /// Retrieve the values as a `HashSet`.
     fn as_hashset(&self, base: *mut u8) -> HashSet<GCObjectRef> {
-        self.set.iter()
-                .map(|i| unsafe{ base.offset(i as isize) as GCObjectRef})
-                .collect()
+        self.iter()
+            .map(|i| unsafe{ base.offset(i as isize) as GCObjectRef})
+            .collect()
     }
 }
 
@@ -69,7 +91,7 @@ impl ObjectMap {
 /// not exeed `LINE_SIZE` bytes in size.
 pub struct BlockInfo {
     /// A counter of live objects for every line in this block.
-    line_counter: VecMap<usize>,
+    line_counter: Vec<AtomicUsize>,
 
     /// A set of addresses that are valid objects. Needed for the conservative
     /// part.
@@ -87,22 +109,23 @@ pub struct BlockInfo {
 
     /// If this block is a candidate for opportunistic evacuation.
     evacuation_candidate: bool,
+
+    /// If this block is a nursery block holding only objects allocated since
+    /// the last collection.
+    nursery: bool,
 }
 
 impl BlockInfo {
     /// Create a new `BlockInfo`.
     pub fn new() -> BlockInfo {
-        let mut line_counter = VecMap::with_capacity(NUM_LINES_PER_BLOCK);
-        for index in 0..NUM_LINES_PER_BLOCK {
-            line_counter.insert(index, 0);
-        }
         BlockInfo {
-            line_counter: line_counter,
+            line_counter: (0..NUM_LINES_PER_BLOCK).map(|_| AtomicUsize::new(0)).collect(),
             object_map: ObjectMap::new(),
             new_objects: ObjectMap::new(),
             allocated: false,
             hole_count: 0,
             evacuation_candidate: false,
+            nursery: false,
         }
     }
 
@@ -111,8 +134,23 @@ impl BlockInfo {
         self.allocated = true;
     }
 
+    /// Return if this block is allocated (actually in use).
+    pub fn is_allocated(&self) -> bool {
+        self.allocated
+    }
+
+    /// Set if this block is a nursery block.
+    pub fn set_nursery(&mut self, nursery: bool) {
+        self.nursery = nursery;
+    }
+
+    /// Return if this block is a nursery block.
+    pub fn is_nursery(&self) -> bool {
+        self.nursery
+    }
+
     /// Set an address in this block as a valid object.
-    pub fn set_gc_object(&mut self, object: GCObjectRef) {
+    pub fn set_gc_object(&self, object: GCObjectRef) {
         debug_assert!(self.is_in_block(object),
             "set_gc_object() on invalid block: {:p} (allocated={})",
             self, self.allocated);
@@ -120,7 +158,7 @@ impl BlockInfo {
     }
 
     /// Unset an address in this block as a valid object.
-    pub fn unset_gc_object(&mut self, object: GCObjectRef) {
+    pub fn unset_gc_object(&self, object: GCObjectRef) {
         debug_assert!(self.is_in_block(object),
             "unset_gc_object() on invalid block: {:p} (allocated={})",
             self, self.allocated);
@@ -142,6 +180,16 @@ impl BlockInfo {
         self.object_map.as_hashset(self_ptr as *mut u8)
     }
 
+    /// Return the number of valid objects in this block that were not
+    /// evacuated.
+    pub fn count_live_objects(&mut self) -> usize {
+        let self_ptr = self as *mut BlockInfo as *mut u8;
+        self.object_map.iter()
+            .map(|i| unsafe{ self_ptr.offset(i as isize) as GCObjectRef })
+            .filter(|&object| unsafe{ (*object).is_forwarded().is_none() })
+            .count()
+    }
+
     /// Clear the object map.
     pub fn clear_object_map(&mut self) {
         self.object_map.clear();
@@ -169,11 +217,12 @@ impl BlockInfo {
     }
 
     /// Set as an evacuation candidate if this block has at least `hole_count`
-    /// holes.
+    /// holes or is a nursery block.
     pub fn set_evacuation_candidate(&mut self, hole_count: usize) {
-        debug!("Set block {:p} to evacuation_candidate={} ({} holes)",
-               &self, self.hole_count >= hole_count, self.hole_count);
-        self.evacuation_candidate = self.hole_count >= hole_count;
+        let candidate = self.nursery || self.hole_count >= hole_count;
+        trace!("Set block {:p} to evacuation_candidate={} ({} holes, nursery={})",
+               &self, candidate, self.hole_count, self.nursery);
+        self.evacuation_candidate = candidate;
     }
 
     /// Return if this is an evacuation candidate.
@@ -182,12 +231,12 @@ impl BlockInfo {
     }
 
     /// Increment the lines on which the object is allocated.
-    pub fn increment_lines(&mut self, object: GCObjectRef) {
+    pub fn increment_lines(&self, object: GCObjectRef) {
         self.update_line_nums(object, true);
     }
 
     /// Decrement the lines on which the object is allocated.
-    pub fn decrement_lines(&mut self, object: GCObjectRef) {
+    pub fn decrement_lines(&self, object: GCObjectRef) {
         self.update_line_nums(object, false);
     }
 
@@ -198,8 +247,8 @@ impl BlockInfo {
     /// _Note_: You must call count_holes() bevorhand to set the number of
     /// holes.
     pub fn count_holes_and_marked_lines(&self) -> (usize, usize) {
-        (self.hole_count, self.line_counter.values()
-                                           .filter(|&e| *e != 0)
+        (self.hole_count, self.line_counter.iter()
+                                           .filter(|e| e.load(Ordering::Relaxed) != 0)
                                            .count())
     }
 
@@ -210,15 +259,31 @@ impl BlockInfo {
     /// _Note_: You must call count_holes() bevorhand to set the number of
     /// holes.
     pub fn count_holes_and_available_lines(&self) -> (usize, usize) {
-        (self.hole_count, self.line_counter.values()
-                                           .filter(|&e| *e == 0)
+        (self.hole_count, self.line_counter.iter()
+                                           .filter(|e| e.load(Ordering::Relaxed) == 0)
                                            .count())
     }
 
+    /// Return the counter of live objects on the line `line`.
+    pub fn line_count(&self, line: usize) -> usize {
+        self.line_counter.get(line).map_or(0, |c| c.load(Ordering::Relaxed))
+    }
+
+    /// Return the lines whose counters are updated for the `object`.
+    ///
+    /// This calculates how many lines are affected starting from a
+    /// LINE_SIZE aligned address. So it might not mark enough lines. But
+    /// that does not matter as we always skip a line in scan_block()
+    pub fn object_lines(object: GCObjectRef) -> Range<usize> {
+        let line_num = BlockInfo::object_to_line_num(object);
+        let object_size = unsafe{ (*object).object_size() };
+        line_num..(line_num + (object_size / LINE_SIZE) + 1)
+    }
+
     /// Clear the line counter map.
     pub fn clear_line_counts(&mut self) {
-        for index in 0..NUM_LINES_PER_BLOCK {
-            self.line_counter.insert(index, 0);
+        for counter in &self.line_counter {
+            counter.store(0, Ordering::Relaxed);
         }
     }
 
@@ -229,11 +294,12 @@ impl BlockInfo {
         self.allocated = false;
         self.hole_count = 0;
         self.evacuation_candidate = false;
+        self.nursery = false;
     }
 
     /// Return true if no line is marked (every line has a count of zero).
     pub fn is_empty(&self) -> bool {
-        self.line_counter.values().all(|v| *v == 0)
+        self.line_counter.iter().all(|v| v.load(Ordering::Relaxed) == 0)
     }
 
     /// Get a pointer to an address `offset` bytes into this block.
@@ -252,11 +318,11 @@ impl BlockInfo {
     /// `None` is returned if no hole was found.
     pub fn scan_block(&self, last_high_offset: u16) -> Option<(u16, u16)> {
         let last_high_index = last_high_offset as usize / LINE_SIZE;
-        debug!("Scanning block {:p} for a hole with last_high_offset {}",
+        trace!("Scanning block {:p} for a hole with last_high_offset {}",
                self, last_high_index);
         let mut low_index = NUM_LINES_PER_BLOCK - 1;
         for index in (last_high_index + 1)..NUM_LINES_PER_BLOCK {
-            if self.line_counter.get(index).map_or(true, |c| *c == 0) {
+            if self.line_count(index) == 0 {
                 // +1 to skip the next line in case an object straddles lines
                 low_index = index + 1;
                 break;
@@ -264,21 +330,21 @@ impl BlockInfo {
         }
         let mut high_index = NUM_LINES_PER_BLOCK;
         for index in low_index..NUM_LINES_PER_BLOCK {
-            if self.line_counter.get(index).map_or(false, |c| *c != 0) {
+            if self.line_count(index) != 0 {
                 high_index = index;
                 break;
             }
         }
         if low_index == high_index && high_index != (NUM_LINES_PER_BLOCK - 1) {
-            debug!("Rescan: Found single line hole? in block {:p}", self);
+            trace!("Rescan: Found single line hole? in block {:p}", self);
             return self.scan_block((high_index * LINE_SIZE - 1) as u16);
         } else if low_index < (NUM_LINES_PER_BLOCK - 1) {
-            debug!("Found low index {} and high index {} in block {:p}",
+            trace!("Found low index {} and high index {} in block {:p}",
                    low_index, high_index, self);
             return Some(((low_index * LINE_SIZE) as u16,
                          (high_index * LINE_SIZE - 1) as u16));
         }
-        debug!("Found no hole in block {:p}", self);
+        trace!("Found no hole in block {:p}", self);
         None
     }
 
@@ -286,8 +352,9 @@ impl BlockInfo {
     ///
     /// Holes are lines with no objects allocated.
     pub fn count_holes(&mut self) {
-        let holes = self.line_counter.values()
-            .fold((0, false), |(holes, in_hole), &elem|
+        let holes = self.line_counter.iter()
+            .map(|elem| elem.load(Ordering::Relaxed))
+            .fold((0, false), |(holes, in_hole), elem|
                   match (in_hole, elem) {
                     (false, 0) => (holes + 1, true),
                     (_, _) => (holes, false),
@@ -320,31 +387,22 @@ impl BlockInfo{
 
     /// Update the line counter for the given object.
     ///
-    /// Increment if `increment`, otherwise do a saturating substraction.
-    fn update_line_nums(&mut self, object: GCObjectRef, increment: bool) {
-        // This calculates how many lines are affected starting from a
-        // LINE_SIZE aligned address. So it might not mark enough lines. But
-        // that does not matter as we always skip a line in scan_block()
-        let line_num = BlockInfo::object_to_line_num(object);
-        let object_size = unsafe{ (*object).object_size() };
-        for line in line_num..(line_num + (object_size / LINE_SIZE) + 1) {
-            match self.line_counter.entry(line) {
-                Entry::Vacant(view) => { view.insert(if increment { 1 } else { 0 }); },
-                Entry::Occupied(mut view) => {
-                    let val = view.get_mut();
-                    if increment {
-                        *val += 1;
-                    } else {
-                        *val = (*val).saturating_sub(1);
-                    }
-                }
+    /// Increment if `increment`, otherwise do a saturating substraction. The
+    /// counters are updated atomically. Lines beyond this block are skipped.
+    fn update_line_nums(&self, object: GCObjectRef, increment: bool) {
+        for line in BlockInfo::object_lines(object) {
+            let counter = match self.line_counter.get(line) {
+                Some(counter) => counter,
+                None => continue,
             };
             if increment {
-                debug!("Incremented line count for line {} to {}", line,
-                       self.line_counter.get(line).expect("a line count"));
+                let count = counter.fetch_add(1, Ordering::Relaxed) + 1;
+                trace!("Incremented line count for line {} to {}", line, count);
             } else {
-                debug!("Decremented line count for line {} to {}", line,
-                       self.line_counter.get(line).expect("a line count"));
+                let count = counter.fetch_update(Ordering::Relaxed, Ordering::Relaxed,
+                                                 |c| c.checked_sub(1))
+                                   .map_or(0, |c| c - 1);
+                trace!("Decremented line count for line {} to {}", line, count);
             }
         }
     }
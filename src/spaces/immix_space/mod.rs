@@ -14,8 +14,8 @@ use self::allocator::EvacAllocator;
 pub use self::block_info::BlockInfo;
 
 use std::{mem, ptr};
-use std::rc::Rc;
-use std::cell::RefCell;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use constants::{BLOCK_SIZE, MEDIUM_OBJECT};
 use gc_object::{GCRTTI, GCObject, GCObjectRef};
@@ -33,45 +33,111 @@ use gc_object::{GCRTTI, GCObject, GCObjectRef};
 /// maintained by the `RCCollector` and the `ImmixCollector`. If it drops to
 /// zero the line can be reclaimed. If a block has only free lines it can be
 /// returned to the global block allocator.
+///
+/// Every mutator thread allocates using its own `ThreadAllocator` created by
+/// `ImmixSpace::new_thread_allocator()`.
+///
+/// If the nursery is used new objects are allocated into nursery blocks.
+/// Every collection treats them as evacuation candidates, so the surviving
+/// new objects are copied into the blocks of the `EvacAllocator` and the
+/// nursery blocks are freed (see `GCConfig::with_nursery()`).
 pub struct ImmixSpace {
     /// The global `BlockAllocator` to get new blocks from.
-    block_allocator: Rc<RefCell<BlockAllocator>>,
+    block_allocator: Arc<Mutex<BlockAllocator>>,
+
+    /// The lower bound of the memory map of the `BlockAllocator`.
+    space_start: *mut u8,
+
+    /// The upper bound of the memory map of the `BlockAllocator`.
+    space_bound: *mut u8,
+
+    /// The evacuation allocator used during an evacuating collection.
+    ///
+    /// It is locked by the collector threads evacuating objects.
+    evac_allocator: Mutex<EvacAllocator>,
+
+    /// The blocks of `ThreadAllocator`s whose threads were unregistered.
+    abandoned_blocks: Vec<*mut BlockInfo>,
+
+    /// The current live mark for new objects. See `Spaces.current_live_mark`.
+    current_live_mark: bool,
 
+    /// The number of objects evacuated so far.
+    evacuated_objects: AtomicUsize,
+
+    /// Whether new objects are allocated into nursery blocks.
+    use_nursery: bool,
+}
+
+/// The thread-local allocators of a mutator thread within the immix space.
+///
+/// Only the owning thread allocates with it. The collector accesses it while
+/// the owning thread is stopped.
+pub struct ThreadAllocator {
     /// The nomal allocator for objects smaller than `MEDIUM_OBJECT` bytes.
     allocator: NormalAllocator,
 
     /// The overflow allocator for objects larger than `MEDIUM_OBJECT` bytes.
     overflow_allocator: OverflowAllocator,
 
-    /// The evacuation allocator used during an evacuating collection.
-    evac_allocator: EvacAllocator,
-
     /// The current live mark for new objects. See `Spaces.current_live_mark`.
     current_live_mark: bool,
+
+    /// The objects with a finalizer allocated since the last collection.
+    new_finalizable_objects: Vec<GCObjectRef>,
+
+    /// The number of bytes allocated by this allocator.
+    ///
+    /// Only written by the owning thread, but read by `Spaces::stats()`.
+    bytes_allocated: AtomicUsize,
 }
 
 impl ImmixSpace {
     /// Create a new `ImmixSpace`.
     ///
     /// This also initializes the `BlockAllocator` which will allocate a
-    /// memory map of `HEAP_SIZE` bytes. The allocation will fail if there is
-    /// not enough memory available.
-    pub fn new() -> ImmixSpace {
-        let block_allocator = Rc::new(RefCell::new(BlockAllocator::new()));
-        let normal_block_allocator = block_allocator.clone();
-        let overflow_block_allocator = block_allocator.clone();
+    /// memory map of `heap_size` bytes. The allocation will fail if there is
+    /// not enough memory available. If `use_nursery` is set the
+    /// `ThreadAllocator`s allocate into nursery blocks.
+    pub fn new(heap_size: usize, use_nursery: bool) -> ImmixSpace {
+        let block_allocator = BlockAllocator::new(heap_size);
+        let space_start = block_allocator.space_start();
+        let space_bound = block_allocator.space_bound();
         ImmixSpace {
-            block_allocator: block_allocator,
-            allocator: NormalAllocator::new(normal_block_allocator),
-            overflow_allocator: OverflowAllocator::new(overflow_block_allocator),
-            evac_allocator: EvacAllocator::new(),
+            block_allocator: Arc::new(Mutex::new(block_allocator)),
+            space_start: space_start,
+            space_bound: space_bound,
+            evac_allocator: Mutex::new(EvacAllocator::new()),
+            abandoned_blocks: Vec::new(),
             current_live_mark: false,
+            evacuated_objects: AtomicUsize::new(0),
+            use_nursery: use_nursery,
+        }
+    }
+
+    /// Create a new `ThreadAllocator` for a mutator thread backed by the
+    /// global `BlockAllocator`.
+    pub fn new_thread_allocator(&self) -> ThreadAllocator {
+        ThreadAllocator {
+            allocator: NormalAllocator::new(self.block_allocator.clone(),
+                                            self.use_nursery),
+            overflow_allocator: OverflowAllocator::new(self.block_allocator.clone(),
+                                                       self.use_nursery),
+            current_live_mark: self.current_live_mark,
+            new_finalizable_objects: Vec::new(),
+            bytes_allocated: AtomicUsize::new(0),
         }
     }
 
+    /// Take over the blocks of the `ThreadAllocator` of an unregistered
+    /// thread. They will be processed by the next collection.
+    pub fn abandon_thread_allocator(&mut self, mut allocator: ThreadAllocator) {
+        self.abandoned_blocks.extend(allocator.get_all_blocks());
+    }
+
     /// Decrement the lines on which the object is allocated.
     pub fn decrement_lines(&self, object: GCObjectRef) {
-        debug!("decrement_lines() on object {:p}", object);
+        trace!("decrement_lines() on object {:p}", object);
         debug_assert!(self.is_gc_object(object),
                      "decrement_lines() on invalid object {:p}", object);
         unsafe{ (*ImmixSpace::get_block_ptr(object)).decrement_lines(object); }
@@ -79,7 +145,7 @@ impl ImmixSpace {
 
     /// Increment the lines on which the object is allocated.
     pub fn increment_lines(&self, object: GCObjectRef) {
-        debug!("increment_lines() on object {:p}", object);
+        trace!("increment_lines() on object {:p}", object);
         debug_assert!(self.is_gc_object(object),
                       "increment_lines() on invalid object {:p}", object);
         unsafe{ (*ImmixSpace::get_block_ptr(object)).increment_lines(object); }
@@ -87,16 +153,16 @@ impl ImmixSpace {
 
     /// Set an address in this space as a valid object.
     pub fn set_gc_object(&self, object: GCObjectRef) {
-        debug!("set_gc_object() on object {:p}", object);
-        debug_assert!(self.block_allocator.borrow().is_in_space(object),
+        trace!("set_gc_object() on object {:p}", object);
+        debug_assert!(self.is_in_space(object),
                       "set_gc_object() on invalid object {:p}", object);
         unsafe{ (*ImmixSpace::get_block_ptr(object)).set_gc_object(object); }
     }
 
     /// Unset an address as a valid object within the immix space.
     pub fn unset_gc_object(&self, object: GCObjectRef) {
-        debug!("unset_gc_object() on object {:p}", object);
-        debug_assert!(self.block_allocator.borrow().is_in_space(object),
+        trace!("unset_gc_object() on object {:p}", object);
+        debug_assert!(self.is_in_space(object),
                       "unset_gc_object() on invalid object {:p}", object);
         unsafe{ (*ImmixSpace::get_block_ptr(object)).unset_gc_object(object); }
     }
@@ -104,7 +170,7 @@ impl ImmixSpace {
     /// Return if the object an the address is a valid object within the immix
     /// space.
     pub fn is_gc_object(&self, object: GCObjectRef) -> bool {
-        if self.block_allocator.borrow().is_in_space(object) {
+        if self.is_in_space(object) {
             unsafe{ (*ImmixSpace::get_block_ptr(object)).is_gc_object(object) }
         } else {
             false
@@ -113,29 +179,43 @@ impl ImmixSpace {
 
     /// Return a closure that behaves like `ImmixSpace::is_gc_object()`.
     pub fn is_gc_object_filter<'a>(&'a self) -> Box<Fn(GCObjectRef) -> bool + 'a> {
-        let block_allocator = self.block_allocator.borrow();
-        Box::new(move |object: GCObjectRef| block_allocator.is_in_space(object)
-            && unsafe{ (*ImmixSpace::get_block_ptr(object)).is_gc_object(object) })
+        Box::new(move |object: GCObjectRef| self.is_gc_object(object))
     }
 
     /// Return if the object an the address is within the immix space.
     pub fn is_in_space(&self, object: GCObjectRef) -> bool {
-        self.block_allocator.borrow().is_in_space(object)
+        self.space_start < (object as *mut u8)
+            && (object as *mut u8) < self.space_bound
     }
 
     /// Return the number of unallocated blocks.
     pub fn available_blocks(&self) -> usize {
-        self.block_allocator.borrow().available_blocks()
+        self.block_allocator.lock().unwrap().available_blocks()
+    }
+
+    /// Return all blocks in use by any allocator or holding objects.
+    pub fn allocated_blocks(&self) -> Vec<*mut BlockInfo> {
+        self.block_allocator.lock().unwrap().allocated_blocks()
+    }
+
+    /// Return the number of recyclable blocks not taken by an allocator.
+    pub fn recyclable_blocks(&self) -> usize {
+        self.block_allocator.lock().unwrap().recyclable_blocks()
+    }
+
+    /// Return the number of objects evacuated so far.
+    pub fn evacuated_objects(&self) -> usize {
+        self.evacuated_objects.load(Ordering::Relaxed)
     }
 
     /// Get the number of currently free blocks in the evacuation allocator.
     pub fn evac_headroom(&self) -> usize {
-        self.evac_allocator.evac_headroom()
+        self.evac_allocator.lock().unwrap().evac_headroom()
     }
 
     /// Return a collection of blocks to the global block allocator.
     pub fn return_blocks(&mut self, blocks: Vec<*mut BlockInfo>) {
-        self.block_allocator.borrow_mut().return_blocks(blocks);
+        self.block_allocator.lock().unwrap().return_blocks(blocks);
     }
 
     /// Set the current live mark to `current_live_mark`.
@@ -143,44 +223,48 @@ impl ImmixSpace {
         self.current_live_mark = current_live_mark;
     }
 
-    /// Set the recyclable blocks for the `NormalAllocator`.
+    /// Set the recyclable blocks for the `NormalAllocator`s.
     pub fn set_recyclable_blocks(&mut self, blocks: Vec<*mut BlockInfo>) {
-        self.allocator.set_recyclable_blocks(blocks);
+        self.block_allocator.lock().unwrap().set_recyclable_blocks(blocks);
     }
 
     /// Extend the list of free blocks in the `EvacAllocator` for evacuation.
     pub fn extend_evac_headroom(&mut self, blocks: Vec<*mut BlockInfo>) {
-        self.evac_allocator.extend_evac_headroom(blocks);
+        self.evac_allocator.lock().unwrap().extend_evac_headroom(blocks);
     }
 
-    /// Get all block managed by all allocators, draining any local
-    /// collections.
-    pub fn get_all_blocks(&mut self) -> Vec<*mut BlockInfo> {
-        let mut normal_blocks = self.allocator.get_all_blocks();
-        let mut overflow_blocks = self.overflow_allocator.get_all_blocks();
-        let mut evac_blocks = self.evac_allocator.get_all_blocks();
-        return normal_blocks.drain(..)
-                            .chain(overflow_blocks.drain(..))
-                            .chain(evac_blocks.drain(..))
-                            .collect();
+    /// Get the blocks the `EvacAllocator` evacuated objects into, draining
+    /// them from the allocator.
+    pub fn get_evac_blocks(&mut self) -> Vec<*mut BlockInfo> {
+        self.evac_allocator.lock().unwrap().get_all_blocks()
     }
 
-    /// Allocate an object of `size` bytes or return `None` if the allocation
-    /// failed.
+    /// Move free blocks from the global block allocator into the
+    /// `EvacAllocator` until it has `evac_headroom` free blocks or no free
+    /// block is left.
     ///
-    /// This object is initialized and ready to use.
-    pub fn allocate(&mut self, rtti: *const GCRTTI) -> Option<GCObjectRef> {
-        let size = unsafe{ (*rtti).object_size() };
-        debug!("Request to allocate an object of size {}", size);
-        if let Some(object) = if size < MEDIUM_OBJECT { self.allocator.allocate(size) }
-                              else { self.overflow_allocator.allocate(size) } {
-            unsafe { ptr::write(object, GCObject::new(rtti, self.current_live_mark)); }
-            unsafe{ (*ImmixSpace::get_block_ptr(object)).set_new_object(object); }
-            self.set_gc_object(object);
-            Some(object)
-        } else {
-            None
-        }
+    /// The nursery relies on this, as its collections always evacuate.
+    pub fn fill_evac_headroom(&mut self, evac_headroom: usize) {
+        let mut evac_allocator = self.evac_allocator.lock().unwrap();
+        let missing = evac_headroom.saturating_sub(evac_allocator.evac_headroom());
+        let mut block_allocator = self.block_allocator.lock().unwrap();
+        let blocks = (0..missing).filter_map(|_| block_allocator.get_block()).collect();
+        evac_allocator.extend_evac_headroom(blocks);
+    }
+
+    /// Get all block managed by the space-wide allocators, draining any
+    /// local collections.
+    ///
+    /// The blocks of the `ThreadAllocator`s are retrieved using
+    /// `ThreadAllocator::get_all_blocks()`.
+    pub fn get_all_blocks(&mut self) -> Vec<*mut BlockInfo> {
+        let mut recyclable_blocks = self.block_allocator.lock().unwrap()
+                                        .get_recyclable_blocks();
+        let mut evac_blocks = self.evac_allocator.lock().unwrap().get_all_blocks();
+        return recyclable_blocks.drain(..)
+                                .chain(evac_blocks.drain(..))
+                                .chain(self.abandoned_blocks.drain(..))
+                                .collect();
     }
 
     /// Evacuate the object to another block using the `EvacAllocator`
@@ -192,15 +276,23 @@ impl ImmixSpace {
     ///
     /// On successful evacuation the old object is marked as forewarded an an
     /// forewarding pointer is installed.
-    pub fn maybe_evacuate(&mut self, object: GCObjectRef) -> Option<GCObjectRef> {
+    ///
+    /// This may be called by several collector threads concurrently. An
+    /// object evacuated by another thread in the meantime is not copied
+    /// again, its new address is returned instead.
+    pub fn maybe_evacuate(&self, object: GCObjectRef) -> Option<GCObjectRef> {
         let block_info = unsafe{ ImmixSpace::get_block_ptr(object) };
         let is_pinned = unsafe{ (*object).is_pinned() };
         let is_candidate = unsafe{ (*block_info).is_evacuation_candidate() };
         if is_pinned || !is_candidate {
             return None;
         }
+        let mut evac_allocator = self.evac_allocator.lock().unwrap();
+        if let Some(new_object) = unsafe{ (*object).is_forwarded() } {
+            return Some(new_object);
+        }
         let size = unsafe{ (*object).object_size() };
-        if let Some(new_object) = self.evac_allocator.allocate(size) {
+        if let Some(new_object) = evac_allocator.allocate(size) {
             unsafe{
                 ptr::copy_nonoverlapping(object as *const u8,
                                          new_object as *mut u8, size);
@@ -209,22 +301,81 @@ impl ImmixSpace {
                 (*object).set_forwarded(new_object);
                 self.set_gc_object(object);
             }
-            debug!("Evacuated object {:p} from block {:p} to {:p}", object,
+            trace!("Evacuated object {:p} from block {:p} to {:p}", object,
                    block_info, new_object);
+            self.evacuated_objects.fetch_add(1, Ordering::Relaxed);
             valgrind_freelike!(object);
             return Some(new_object);
         }
-        debug!("Can't evacuation object {:p} from block {:p}", object, block_info);
+        trace!("Can't evacuation object {:p} from block {:p}", object, block_info);
         None
     }
 }
 
+// The `ImmixSpace` is shared with the collector threads during a parallel
+// collection (see `ImmixCollector`), which only use the methods taking
+// `&self`. These update the blocks atomically or lock the allocators.
+unsafe impl Sync for ImmixSpace {}
+
+impl ThreadAllocator {
+    /// Get all block managed by this allocator, draining any local
+    /// collections.
+    pub fn get_all_blocks(&mut self) -> Vec<*mut BlockInfo> {
+        let mut normal_blocks = self.allocator.get_all_blocks();
+        let mut overflow_blocks = self.overflow_allocator.get_all_blocks();
+        return normal_blocks.drain(..)
+                            .chain(overflow_blocks.drain(..))
+                            .collect();
+    }
+
+    /// Set the current live mark to `current_live_mark`.
+    pub fn set_current_live_mark(&mut self, current_live_mark: bool) {
+        self.current_live_mark = current_live_mark;
+    }
+
+    /// Return the number of bytes allocated by this allocator.
+    pub fn bytes_allocated(&self) -> usize {
+        self.bytes_allocated.load(Ordering::Relaxed)
+    }
+
+    /// Get the objects with a finalizer allocated since the last call.
+    pub fn get_new_finalizable_objects(&mut self) -> Vec<GCObjectRef> {
+        self.new_finalizable_objects.drain(..).collect()
+    }
+
+    /// Allocate an object described by `rtti` with `length` array elements
+    /// or return `None` if the allocation failed.
+    ///
+    /// This object is initialized and ready to use.
+    pub fn allocate(&mut self, rtti: *const GCRTTI, length: usize) -> Option<GCObjectRef> {
+        let size = unsafe{ (*rtti).instance_size(length) }?;
+        trace!("Request to allocate an object of size {}", size);
+        if let Some(object) = if size < MEDIUM_OBJECT { self.allocator.allocate(size) }
+                              else { self.overflow_allocator.allocate(size) } {
+            unsafe {
+                ptr::write(object, GCObject::new(rtti, length, self.current_live_mark));
+                let block = ImmixSpace::get_block_ptr(object);
+                (*block).set_new_object(object);
+                (*block).set_gc_object(object);
+                if (*rtti).finalizer().is_some() {
+                    self.new_finalizable_objects.push(object);
+                }
+            }
+            let bytes_allocated = self.bytes_allocated.load(Ordering::Relaxed);
+            self.bytes_allocated.store(bytes_allocated + size, Ordering::Relaxed);
+            Some(object)
+        } else {
+            None
+        }
+    }
+}
+
 impl ImmixSpace {
     /// Get the block for the given object.
     unsafe fn get_block_ptr(object: GCObjectRef) -> *mut BlockInfo {
         let block_offset = object as usize % BLOCK_SIZE;
         let block = mem::transmute((object as *mut u8).offset(-(block_offset as isize)));
-        debug!("Block for object {:p}: {:p} with offset: {}", object, block, block_offset);
+        trace!("Block for object {:p}: {:p} with offset: {}", object, block, block_offset);
         block
     }
 }
@@ -5,6 +5,7 @@ extern crate libc;
 
 use std::collections::HashSet;
 use std::ptr;
+use std::sync::Mutex;
 
 use gc_object::{GCRTTI, GCObject, GCObjectRef};
 
@@ -24,20 +25,31 @@ pub struct LargeObjectSpace  {
     new_objects: Vec<GCObjectRef>,
 
     /// A buffer of elements to be freed after the RC collection phase.
-    free_buffer: Vec<GCObjectRef>,
+    ///
+    /// It is locked by the collector threads processing the decrements.
+    free_buffer: Mutex<Vec<GCObjectRef>>,
 
     /// The current live mark for new objects. See `Spaces.current_live_mark`.
     current_live_mark: bool,
+
+    /// The number of bytes of the objects in this space.
+    bytes: usize,
 }
 
+// The `LargeObjectSpace` is shared with the collector threads processing
+// the decrements in parallel (see `RCCollector`), which only use the methods
+// taking `&self`.
+unsafe impl Sync for LargeObjectSpace {}
+
 impl LargeObjectSpace  {
     /// Create a new `LargeObjectSpace`.
     pub fn new() -> LargeObjectSpace {
         LargeObjectSpace {
             objects: HashSet::new(),
             new_objects: Vec::new(),
-            free_buffer: Vec::new(),
+            free_buffer: Mutex::new(Vec::new()),
             current_live_mark: false,
+            bytes: 0,
         }
     }
 
@@ -56,9 +68,26 @@ impl LargeObjectSpace  {
         Box::new(move |object: GCObjectRef| self.is_gc_object(object))
     }
 
+    /// Return the number of objects in this space.
+    pub fn objects(&self) -> usize {
+        self.objects.len()
+    }
+
+    /// Return all objects in this space.
+    pub fn get_objects(&self) -> Vec<GCObjectRef> {
+        self.objects.iter().map(|&o| o).collect()
+    }
+
+    /// Return the number of bytes of the objects in this space.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
     /// Enqueue an object to be freed after the RC collection phase.
-    pub fn enqueue_free(&mut self, object: GCObjectRef) {
-        self.free_buffer.push(object);
+    ///
+    /// This may be called by several collector threads concurrently.
+    pub fn enqueue_free(&self, object: GCObjectRef) {
+        self.free_buffer.lock().unwrap().push(object);
     }
 
     /// Get the new objects of the large object space.
@@ -71,21 +100,23 @@ impl LargeObjectSpace  {
         self.current_live_mark = current_live_mark;
     }
 
-    /// Allocate an object of `size` bytes or return `None` if the allocation
-    /// failed.
+    /// Allocate an object described by `rtti` with `length` array elements
+    /// or return `None` if the allocation failed.
     ///
     /// This object is initialized and ready to use.
-    pub fn allocate(&mut self, rtti: *const GCRTTI) -> Option<GCObjectRef> {
+    pub fn allocate(&mut self, rtti: *const GCRTTI, length: usize) -> Option<GCObjectRef> {
         if cfg!(feature = "no_large_object_space") {
             panic!("Large Object Space was disabled (`no_large_object_space`)");
         }
-        let size = unsafe{ (*rtti).object_size() };
-        debug!("Request to allocate an object of size {}", size);
+        let size = unsafe{ (*rtti).instance_size(length) }?;
+        trace!(target: "large_object_space",
+               "Request to allocate an object of size {}", size);
         let object = unsafe{ libc::malloc(size) } as GCObjectRef;
         if !object.is_null() {
-            unsafe { ptr::write(object, GCObject::new(rtti, self.current_live_mark)); }
+            unsafe { ptr::write(object, GCObject::new(rtti, length, self.current_live_mark)); }
             self.objects.insert(object);
             self.new_objects.push(object);
+            self.bytes += size;
             Some(object)
         } else {
             None
@@ -94,15 +125,20 @@ impl LargeObjectSpace  {
 
     /// Free the objects in the free buffer.
     pub fn proccess_free_buffer(&mut self) {
-        debug!("Starting processing free_buffer size={} after RC collection",
-               self.free_buffer.len());
-        for object in self.free_buffer.drain(..) {
-            debug!("Free object {:p} from RC collection", object);
+        let free_buffer: Vec<_> = self.free_buffer.get_mut().unwrap().drain(..).collect();
+        debug!(target: "large_object_space",
+               "Starting processing free_buffer size={} after RC collection",
+               free_buffer.len());
+        for object in free_buffer {
+            trace!(target: "large_object_space",
+                   "Free object {:p} from RC collection", object);
             if self.objects.remove(&object) {
+                self.bytes -= unsafe{ (*object).object_size() };
                 unsafe{ libc::free(object as *mut libc::c_void); }
             }
         }
-        debug!("Completed processing free_buffer after RC collection");
+        debug!(target: "large_object_space",
+               "Completed processing free_buffer after RC collection");
     }
 
     /// Sweep the objects within the large object space and free those that
@@ -110,14 +146,18 @@ impl LargeObjectSpace  {
     pub fn sweep(&mut self) {
         let next_live_mark = !self.current_live_mark;
         let is_marked = |o: &GCObjectRef| unsafe{ (**o).is_marked(next_live_mark) };
-        debug!("Sweep LOS with next_live_mark={}", next_live_mark);
+        debug!(target: "large_object_space",
+               "Sweep LOS with next_live_mark={}", next_live_mark);
         let (marked, unmarked) : (Vec<_>, Vec<_>) = self.objects.drain().partition(is_marked);
         self.objects = marked.into_iter().collect();
         for object in unmarked {
-            debug!("Free object {:p} in sweep", object);
+            trace!(target: "large_object_space",
+                   "Free object {:p} in sweep", object);
+            self.bytes -= unsafe{ (*object).object_size() };
             unsafe{ libc::free(object as *mut libc::c_void); }
         }
-        debug!("Completed sweeping LOS after Immix collection");
+        debug!(target: "large_object_space",
+               "Completed sweeping LOS after Immix collection");
     }
 }
 
@@ -4,16 +4,39 @@
 mod immix_space;
 mod large_object_space;
 mod collector;
+mod thread_registry;
+mod weak_refs;
+mod finalizers;
+mod pins;
+mod verifier;
+mod object_graph;
 
 use self::immix_space::ImmixSpace;
 use self::large_object_space::LargeObjectSpace;
 use self::collector::Collector;
+use self::thread_registry::{ThreadRegistry, MutatorThread};
+use self::weak_refs::WeakReferences;
+use self::finalizers::Finalizers;
+use self::pins::Pins;
+use self::verifier::HeapVerifier;
+use self::object_graph::ObjectGraph;
 
+pub use self::weak_refs::WeakRef;
+
+use std::sync::Mutex;
+use std::time::Instant;
+
+use config::GCConfig;
+use events::{EventHook, EventHooks, GCEvent, GCEventKind, duration_ns};
+use heap_dump::{HeapDump, DumpedObject};
 use constants::LARGE_OBJECT;
 use gc_object::{GCRTTI, GCObjectRef};
-use stack::Stack;
+use stack::{Stack, StaticRoots};
+use stats::GCStats;
 
 /// The type of collection that will be performed.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum CollectionType {
     /// A simple reference counting collection.
     RCCollection,
@@ -53,9 +76,37 @@ impl CollectionType {
 
 /// The `Spaces` contains the different garbage collector spaces in which
 /// objects can be allocated that are managed by some collector.
+///
+/// Every mutator thread must be registered with `register_thread()` before
+/// it uses the garbage collector (the thread creating the `Spaces` is
+/// registered automatically) and unregistered with `unregister_thread()`
+/// before it exits.
 pub struct Spaces {
-    /// The stack of the one execution thread.
-    stack: Stack,
+    /// The registered mutator threads with their stacks and thread-local
+    /// allocators.
+    threads: ThreadRegistry,
+
+    /// The spaces and collectors shared by all threads.
+    ///
+    /// Holding this lock is holding the GC lock. Only the holder of the GC
+    /// lock may stop the world. Always acquire it using
+    /// `ThreadRegistry::blocking()`.
+    shared: Mutex<SharedSpaces>,
+}
+
+/// The part of the `Spaces` shared by all mutator threads.
+struct SharedSpaces {
+    /// The static roots shared by all threads.
+    static_roots: StaticRoots,
+
+    /// The weak references cleared when their referents are reclaimed.
+    weak_refs: WeakReferences,
+
+    /// The objects with a finalizer and the queue of finalizers to run.
+    finalizers: Finalizers,
+
+    /// The objects pinned by the mutator.
+    pins: Pins,
 
     /// The default immix space.
     immix_space: ImmixSpace,
@@ -66,6 +117,17 @@ pub struct Spaces {
     /// The collectors.
     collector: Collector,
 
+    /// The hooks called at the start and end of every collection.
+    hooks: EventHooks,
+
+    /// The cumulative counters of the collections (and the bytes allocated
+    /// by unregistered threads and in the large object space).
+    stats: GCStats,
+
+    /// The violations found by the heap verification after the last
+    /// collection.
+    verify_violations: Vec<String>,
+
     /// The current live mark.
     ///
     /// During allocation of objects this value is used as the `mark` state of
@@ -76,56 +138,209 @@ pub struct Spaces {
     current_live_mark: bool,
 }
 
+unsafe impl Send for SharedSpaces {}
+
 impl Spaces {
-    /// Create a new `Spaces`.
-    pub fn new() -> Spaces {
-        Spaces {
-            stack: Stack::new(),
-            immix_space: ImmixSpace::new(),
-            large_object_space: LargeObjectSpace::new(),
-            collector: Collector::new(),
-            current_live_mark: false,
+    /// Create a new `Spaces` using the given (validated) `config` and
+    /// register the current thread.
+    pub fn new(config: &GCConfig) -> Spaces {
+        let spaces = Spaces {
+            threads: ThreadRegistry::new(),
+            shared: Mutex::new(SharedSpaces {
+                static_roots: StaticRoots::new(),
+                weak_refs: WeakReferences::new(),
+                finalizers: Finalizers::new(),
+                pins: Pins::new(),
+                immix_space: ImmixSpace::new(config.heap_size(),
+                                            config.use_nursery()),
+                large_object_space: LargeObjectSpace::new(),
+                collector: Collector::new(config),
+                hooks: EventHooks::new(),
+                stats: GCStats::new(),
+                verify_violations: Vec::new(),
+                current_live_mark: false,
+            }),
+        };
+        spaces.register_thread();
+        spaces
+    }
+
+    /// Register the current thread as a mutator thread.
+    ///
+    /// Returns `false` if the thread was already registered.
+    pub fn register_thread(&self) -> bool {
+        if self.threads.current().is_some() {
+            return false;
+        }
+        let shared = self.shared.lock().unwrap();
+        self.threads.register(shared.immix_space.new_thread_allocator())
+    }
+
+    /// Unregister the current thread.
+    ///
+    /// The blocks of its thread-local allocators are taken over by the immix
+    /// space. Returns `false` if the thread was not registered.
+    pub fn unregister_thread(&self) -> bool {
+        if self.threads.current().is_none() {
+            return false;
+        }
+        let mut shared = self.threads.blocking(|| self.shared.lock().unwrap());
+        match self.threads.unregister() {
+            Some(mut allocator) => {
+                shared.finalizers.register(allocator.get_new_finalizable_objects());
+                shared.stats.bytes_allocated += allocator.bytes_allocated();
+                shared.immix_space.abandon_thread_allocator(allocator);
+                true
+            },
+            None => false,
         }
     }
 
+    /// Park the current thread if a collection was requested until the
+    /// collection completed.
+    pub fn safepoint_poll(&self) {
+        self.threads.safepoint();
+    }
 
-    /// Return if the given address is valid in any of the managed spaces.
-    pub fn is_gc_object(&self, object: GCObjectRef) -> bool {
-        self.immix_space.is_gc_object(object)
-               || self.large_object_space.is_gc_object(object)
+    /// Mark the current thread as executing native code with the given top
+    /// of the stack and `registers`. See `ThreadRegistry::enter_native()`.
+    pub fn enter_native(&self, stack_top: *mut u8, registers: Vec<GCObjectRef>) -> bool {
+        self.threads.enter_native(stack_top, registers)
+    }
+
+    /// Mark the current thread as executing mutator code again, waiting for
+    /// a running collection to complete.
+    pub fn leave_native(&self) {
+        self.threads.leave_native();
+    }
+
+    /// Push a frame of `count` precise root slots starting at `slots` onto
+    /// the shadow stack of the current thread. Returns `false` if the current
+    /// thread is not registered.
+    pub fn push_roots(&self, slots: *mut GCObjectRef, count: usize) -> bool {
+        match self.threads.current() {
+            Some(thread) => { unsafe{ (*thread).stack_mut().push_frame(slots, count); } true },
+            None => false,
+        }
+    }
+
+    /// Pop the last frame from the shadow stack of the current thread.
+    /// Returns `false` if the current thread is not registered or its shadow
+    /// stack is empty.
+    pub fn pop_roots(&self) -> bool {
+        self.threads.current()
+            .map_or(false, |thread| unsafe{ (*thread).stack_mut().pop_frame() })
     }
 
     /// Set an address of an object reference as static root.
-    pub fn set_static_root(&mut self, address: *const GCObjectRef) {
-        self.stack.set_static_root(address);
+    pub fn set_static_root(&self, address: *const GCObjectRef) {
+        let mut shared = self.threads.blocking(|| self.shared.lock().unwrap());
+        shared.static_roots.set_static_root(address);
+    }
+
+    /// Set `count` consecutive object references starting at `start` as
+    /// static roots.
+    pub fn add_root_range(&self, start: *const GCObjectRef, count: usize) {
+        let mut shared = self.threads.blocking(|| self.shared.lock().unwrap());
+        shared.static_roots.add_root_range(start, count);
+    }
+
+    /// Remove the static root or root range starting at `start`. See
+    /// `StaticRoots::remove_root_range()`.
+    pub fn remove_root_range(&self, start: *const GCObjectRef) -> bool {
+        let mut shared = self.threads.blocking(|| self.shared.lock().unwrap());
+        shared.static_roots.remove_root_range(start)
+    }
+
+    /// Create a weak reference to `object` or return `None` if the `object`
+    /// is not managed by the garbage collector.
+    pub fn weak_create(&self, object: GCObjectRef) -> Option<WeakRef> {
+        let mut shared = self.threads.blocking(|| self.shared.lock().unwrap());
+        if shared.is_gc_object(object) {
+            Some(shared.weak_refs.create(object))
+        } else {
+            None
+        }
+    }
+
+    /// Destroy the given weak reference.
+    pub fn weak_destroy(&self, weak_ref: WeakRef) {
+        let mut shared = self.threads.blocking(|| self.shared.lock().unwrap());
+        shared.weak_refs.destroy(weak_ref);
+    }
+
+    /// Pin the `object` or return `false` if the `object` is not managed by
+    /// the garbage collector.
+    pub fn pin(&self, object: GCObjectRef) -> bool {
+        let mut shared = self.threads.blocking(|| self.shared.lock().unwrap());
+        if shared.is_gc_object(object) {
+            shared.pins.pin(object);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Remove one pin of the `object` or return `false` if it is not pinned.
+    pub fn unpin(&self, object: GCObjectRef) -> bool {
+        let mut shared = self.threads.blocking(|| self.shared.lock().unwrap());
+        shared.pins.unpin(object)
     }
 
     /// A write barrier for the given `object` used with the `RCCollector`.
-    pub fn write_barrier(&mut self, object: GCObjectRef) -> bool {
-        if self.is_gc_object(object) {
-            self.collector.write_barrier(object)
+    pub fn write_barrier(&self, object: GCObjectRef) -> bool {
+        self.threads.safepoint();
+        let mut shared = self.threads.blocking(|| self.shared.lock().unwrap());
+        if shared.is_gc_object(object) {
+            shared.collector.write_barrier(object)
         } else {
             false
         }
     }
 
-    /// Allocate a new object described by the `rtti` or returns `None` if
-    /// there is no memory left to fullfill the allocation request.
-    pub fn allocate(&mut self, rtti: *const GCRTTI) -> Option<GCObjectRef>{
-        let size = unsafe{ (*rtti).object_size() };
-        debug!("Request to allocate an object of size {}", size);
-        if size < LARGE_OBJECT { self.immix_space.allocate(rtti) }
-        else { self.large_object_space.allocate(rtti) }
+    /// Set the member at position `num` of the `object` to `member` with the
+    /// write barrier used with the `RCCollector`.
+    ///
+    /// The store is performed while the GC lock is held, so it never
+    /// interleaves with a collection. Returns `None` without storing if the
+    /// `object` is not managed by the garbage collector or has no member
+    /// `num`, otherwise if a collection should be triggered.
+    pub fn write_member(&self, object: GCObjectRef, num: usize,
+                        member: GCObjectRef) -> Option<bool> {
+        self.threads.safepoint();
+        let mut shared = self.threads.blocking(|| self.shared.lock().unwrap());
+        if shared.is_gc_object(object) && num < unsafe{ (*object).member_count() } {
+            Some(shared.collector.write_member(object, num, member))
+        } else {
+            None
+        }
     }
 
-    /// Collect the roots using `Stack::enumerate_roots()` and filter them for
-    /// validity in `LargeObjectSpace` or `ImmixSpace`.
-    fn collect_roots(&self) -> Vec<GCObjectRef> {
-        let los_filter = self.large_object_space.is_gc_object_filter();
-        let immix_filter = self.immix_space.is_gc_object_filter();
-        self.stack.enumerate_roots().into_iter()
-                  .filter(|o| los_filter(*o) || immix_filter(*o))
-                  .collect()
+    /// Allocate a new object described by the `rtti` with `length` array
+    /// elements or returns `None` if there is no memory left to fullfill the
+    /// allocation request, the `length` is invalid or the current thread is
+    /// not registered.
+    pub fn allocate(&self, rtti: *const GCRTTI, length: usize) -> Option<GCObjectRef>{
+        self.threads.safepoint();
+        if length > 0 && unsafe{ (*rtti).element_size() } == 0 {
+            return None;
+        }
+        let size = unsafe{ (*rtti).instance_size(length) }?;
+        trace!("Request to allocate an object of size {}", size);
+        if size < LARGE_OBJECT {
+            self.threads.current()
+                .and_then(|thread| unsafe{ (*thread).allocator().allocate(rtti, length) })
+        } else {
+            let mut shared = self.threads.blocking(|| self.shared.lock().unwrap());
+            let object = shared.large_object_space.allocate(rtti, length);
+            if let Some(object) = object {
+                shared.stats.bytes_allocated += size;
+                if unsafe{ (*rtti).finalizer().is_some() } {
+                    shared.finalizers.register(vec![object]);
+                }
+            }
+            object
+        }
     }
 
     /// Trigger a garbage collection.
@@ -133,39 +348,293 @@ impl Spaces {
     /// This will always run the referece counting collector. If `evacuation`
     /// is set the collectors will try to evacuate. If `cycle_collect` is set
     /// the immix tracing collector will be used.
-    pub fn collect(&mut self, evacuation: bool, cycle_collect: bool) {
+    ///
+    /// All other registered threads are stopped during the collection. The
+    /// `registers` of the current thread must be retrieved beforehand using
+    /// `Stack::get_registers()`. Afterwards the current thread fires the
+    /// `CollectionEnd` event without holding the GC lock and runs the
+    /// enqueued finalizers.
+    pub fn collect(&self, evacuation: bool, cycle_collect: bool,
+                   registers: Vec<GCObjectRef>) {
         debug!("Requested collection (evacuation={}, cycle_collect={})",
                evacuation, cycle_collect);
+        let mut shared = self.threads.blocking(|| self.shared.lock().unwrap());
+        let start = Instant::now();
+        let threads = self.threads.stop_the_world();
+        let current = self.threads.current();
+        if let Some(thread) = current {
+            unsafe{ (*thread).stack_mut().save_context(Stack::get_stack_top(), registers); }
+        }
+
+        let end_event = shared.collect(evacuation, cycle_collect, &threads);
+
+        if let Some(thread) = current {
+            unsafe{ (*thread).stack_mut().clear_context(); }
+        }
+        self.threads.restart_the_world();
+        shared.stats.pause_time_ns += duration_ns(start.elapsed());
+        let hooks = shared.hooks.clone();
+        drop(shared);
+        hooks.fire(&end_event);
+        self.run_finalizers();
+    }
+
+    /// Return a `HeapDump` of all objects and the current roots.
+    ///
+    /// All other registered threads are stopped like for a collection. The
+    /// `registers` of the current thread must be retrieved beforehand using
+    /// `Stack::get_registers()`.
+    pub fn dump_heap(&self, registers: Vec<GCObjectRef>) -> HeapDump {
+        self.inspect(registers, |shared, threads| shared.dump_heap(threads))
+    }
+
+    /// Return the graph of the objects reachable from `start` (or the
+    /// current roots if `None`) in the Graphviz DOT format.
+    ///
+    /// All other registered threads are stopped like for a collection. The
+    /// `registers` of the current thread must be retrieved beforehand using
+    /// `Stack::get_registers()`.
+    pub fn object_graph_dot(&self, start: Option<GCObjectRef>,
+                            registers: Vec<GCObjectRef>) -> String {
+        self.inspect(registers, |shared, threads| {
+            let (precise_roots, pinned_roots) = shared.collect_roots(threads);
+            let roots: Vec<GCObjectRef> = precise_roots.into_iter().chain(pinned_roots).collect();
+            let start = start.map_or_else(|| roots.clone(), |object| vec![object]);
+            ObjectGraph::new(&shared.immix_space, &shared.large_object_space)
+                .to_dot(&start, &roots)
+        })
+    }
+
+    /// Register a `hook` called at the start and end of every collection.
+    pub fn add_event_hook(&self, hook: EventHook) {
+        let mut shared = self.threads.blocking(|| self.shared.lock().unwrap());
+        shared.hooks.add(hook);
+    }
+
+    /// Return a snapshot of the heap statistics.
+    pub fn stats(&self) -> GCStats {
+        let shared = self.threads.blocking(|| self.shared.lock().unwrap());
+        let mut stats = shared.stats;
+        stats.bytes_allocated += self.threads.fold_threads(0, |bytes, thread| {
+            bytes + thread.allocator_ref().bytes_allocated()
+        });
+        stats.live_objects = shared.collector.live_objects()
+                             + shared.large_object_space.objects();
+        stats.free_blocks = shared.immix_space.available_blocks();
+        stats.recyclable_blocks = shared.immix_space.recyclable_blocks();
+        stats.evac_headroom_blocks = shared.immix_space.evac_headroom();
+        stats.los_bytes = shared.large_object_space.bytes();
+        stats.objects_evacuated = shared.immix_space.evacuated_objects();
+        stats
+    }
+
+    /// Return the violations found by the heap verification after the last
+    /// collection.
+    pub fn verify_violations(&self) -> Vec<String> {
+        let shared = self.threads.blocking(|| self.shared.lock().unwrap());
+        shared.verify_violations.clone()
+    }
+
+    /// Call `f` with the shared spaces and the stopped threads while all
+    /// other registered threads are stopped and the context of the current
+    /// thread with its `registers` is saved.
+    fn inspect<T, F>(&self, registers: Vec<GCObjectRef>, f: F) -> T
+        where F: FnOnce(&SharedSpaces, &[*mut MutatorThread]) -> T {
+        let shared = self.threads.blocking(|| self.shared.lock().unwrap());
+        let threads = self.threads.stop_the_world();
+        let current = self.threads.current();
+        if let Some(thread) = current {
+            unsafe{ (*thread).stack_mut().save_context(Stack::get_stack_top(), registers); }
+        }
+
+        let result = f(&shared, &threads);
+
+        if let Some(thread) = current {
+            unsafe{ (*thread).stack_mut().clear_context(); }
+        }
+        self.threads.restart_the_world();
+        result
+    }
+
+    /// Run the enqueued finalizers without holding the GC lock, so they may
+    /// use the garbage collector.
+    fn run_finalizers(&self) {
+        loop {
+            let object = {
+                let mut shared = self.threads.blocking(|| self.shared.lock().unwrap());
+                shared.finalizers.next()
+            };
+            let object = match object {
+                Some(object) => object,
+                None => break,
+            };
+            if let Some(finalizer) = unsafe{ (*object).finalizer() } {
+                trace!("Run finalizer of object {:p}", object);
+                finalizer(object);
+            }
+            let mut shared = self.threads.blocking(|| self.shared.lock().unwrap());
+            shared.finalizers.complete(object);
+        }
+    }
+}
+
+impl SharedSpaces {
+    /// Return if the given address is valid in any of the managed spaces.
+    fn is_gc_object(&self, object: GCObjectRef) -> bool {
+        self.immix_space.is_gc_object(object)
+               || self.large_object_space.is_gc_object(object)
+    }
+
+    /// Collect the roots filtered for validity in `LargeObjectSpace` or
+    /// `ImmixSpace` and return the precise and the pinned roots.
+    ///
+    /// The precise roots are those of `Stack::enumerate_precise_roots()` of
+    /// every thread and `StaticRoots::enumerate_roots()`. Their slots are
+    /// known and updated if the objects are evacuated (see
+    /// `update_precise_roots()`). The pinned roots must not be moved: those
+    /// found by `Stack::enumerate_roots()` (if the stacks are scanned) of
+    /// every thread, `Finalizers::roots()` and `Pins::roots()`.
+    fn collect_roots(&self, threads: &[*mut MutatorThread])
+                     -> (Vec<GCObjectRef>, Vec<GCObjectRef>) {
+        let los_filter = self.large_object_space.is_gc_object_filter();
+        let immix_filter = self.immix_space.is_gc_object_filter();
+        let scan_stack = self.collector.config().scan_stack();
+        let precise_roots = threads.iter()
+               .flat_map(|&thread| unsafe{ (*thread).stack().enumerate_precise_roots() })
+               .chain(self.static_roots.enumerate_roots())
+               .filter(|o| los_filter(*o) || immix_filter(*o))
+               .collect();
+        let pinned_roots = threads.iter()
+               .filter(|_| scan_stack)
+               .flat_map(|&thread| unsafe{ (*thread).stack().enumerate_roots() })
+               .chain(self.finalizers.roots())
+               .chain(self.pins.roots())
+               .filter(|o| los_filter(*o) || immix_filter(*o))
+               .collect();
+        (precise_roots, pinned_roots)
+    }
+
+    /// Return a `HeapDump` of all objects that are not forwarded and the
+    /// roots while all `threads` are stopped.
+    fn dump_heap(&self, threads: &[*mut MutatorThread]) -> HeapDump {
+        let dump_object = |object: GCObjectRef, block: Option<usize>| unsafe {
+            DumpedObject {
+                address: object as usize,
+                size: (*object).object_size(),
+                rtti: (*object).rtti() as usize,
+                reference_count: (*object).reference_count(),
+                pinned: (*object).is_pinned(),
+                marked: (*object).is_marked(self.current_live_mark),
+                block: block,
+                edges: (*object).children().map(|c| c as usize).collect(),
+            }
+        };
+        let mut objects = Vec::new();
+        for block in self.immix_space.allocated_blocks() {
+            for object in unsafe{ (*block).get_object_map() } {
+                if unsafe{ (*object).is_forwarded().is_none() } {
+                    objects.push(dump_object(object, Some(block as usize)));
+                }
+            }
+        }
+        for object in self.large_object_space.get_objects() {
+            objects.push(dump_object(object, None));
+        }
+        objects.sort_by_key(|o| o.address);
+        debug!("Dumped {} objects", objects.len());
+        let (precise_roots, pinned_roots) = self.collect_roots(threads);
+        HeapDump {
+            roots: precise_roots.into_iter().chain(pinned_roots)
+                                .map(|o| o as usize).collect(),
+            objects: objects,
+        }
+    }
+
+    /// Update the shadow stack slots of the `threads` and the static roots
+    /// to the new addresses of evacuated objects.
+    fn update_precise_roots(&mut self, threads: &[*mut MutatorThread]) {
+        let immix_space = &self.immix_space;
+        let resolve = |mut object: GCObjectRef| {
+            if immix_space.is_in_space(object) {
+                while let Some(new_object) = unsafe{ (*object).is_forwarded() } {
+                    object = new_object;
+                }
+            }
+            object
+        };
+        for &thread in threads {
+            unsafe{ (*thread).stack_mut().update_precise_roots(&resolve); }
+        }
+        self.static_roots.update_roots(&resolve);
+    }
 
-        let roots = self.collect_roots();
+    /// Perform a garbage collection while all `threads` are stopped and
+    /// return its `CollectionEnd` event.
+    fn collect(&mut self, evacuation: bool, cycle_collect: bool,
+               threads: &[*mut MutatorThread]) -> GCEvent {
+        let (precise_roots, pinned_roots) = self.collect_roots(threads);
+        for &thread in threads {
+            let blocks = unsafe{ (*thread).allocator().get_all_blocks() };
+            self.collector.extend_all_blocks(blocks);
+            let objects = unsafe{ (*thread).allocator().get_new_finalizable_objects() };
+            self.finalizers.register(objects);
+        }
         self.collector.extend_all_blocks(self.immix_space.get_all_blocks());
 
-        for root in roots.iter().map(|o| *o) {
+        for root in pinned_roots.iter().map(|o| *o) {
             unsafe{ (*root).set_pinned(true); }
         }
+        let roots: Vec<GCObjectRef> = precise_roots.iter().chain(pinned_roots.iter())
+                                                   .map(|o| *o).collect();
 
+        if self.collector.config().use_nursery() {
+            let evac_headroom = self.collector.config().evac_headroom();
+            self.immix_space.fill_evac_headroom(evac_headroom);
+        }
         let collection_type = self.collector.prepare_collection(evacuation,
                                 cycle_collect,
                                 self.immix_space.available_blocks(),
                                 self.immix_space.evac_headroom());
+        self.hooks.fire(&GCEvent::new(GCEventKind::CollectionStart, collection_type));
         self.collector.collect(&collection_type, roots.as_ref(),
                                &mut self.immix_space,
                                &mut self.large_object_space,
+                               &mut self.finalizers,
                                !self.current_live_mark);
         self.collector.complete_collection(&collection_type, &mut self.immix_space,
                                            &mut self.large_object_space);
+        if self.collector.config().verify_heap() {
+            self.verify_violations = HeapVerifier::new(&self.immix_space,
+                                                       &self.large_object_space)
+                                         .verify(self.collector.counted_roots());
+        }
+        self.weak_refs.process(&self.immix_space, &self.large_object_space);
+        self.update_precise_roots(threads);
+        self.finalizers.process(&self.immix_space, &self.large_object_space);
 
-        for root in roots.iter().map(|o| *o) {
-            unsafe{ (*root).set_pinned(false); }
+        match collection_type {
+            CollectionType::RCCollection => self.stats.rc_collections += 1,
+            CollectionType::RCEvacCollection => self.stats.rc_evac_collections += 1,
+            CollectionType::ImmixCollection => self.stats.immix_collections += 1,
+            CollectionType::ImmixEvacCollection => self.stats.immix_evac_collections += 1,
+        }
+        let end_event = self.collector.end_event(collection_type);
+
+        for root in pinned_roots.iter().map(|o| *o) {
+            if !self.pins.is_pinned(root) {
+                unsafe{ (*root).set_pinned(false); }
+            }
         }
 
         if collection_type.is_immix() {
             self.current_live_mark = !self.current_live_mark;
             self.immix_space.set_current_live_mark(self.current_live_mark);
             self.large_object_space.set_current_live_mark(self.current_live_mark);
-
+            for &thread in threads {
+                unsafe{ (*thread).allocator().set_current_live_mark(self.current_live_mark); }
+            }
         }
         valgrind_assert_no_leaks!();
+        end_event
     }
 }
-
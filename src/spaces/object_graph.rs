@@ -0,0 +1,98 @@
+// Copyright (c) <2015> <lummax>
+// Licensed under MIT (http://opensource.org/licenses/MIT)
+
+use std::collections::{HashSet, VecDeque};
+use std::fmt::Write;
+
+use spaces::immix_space::ImmixSpace;
+use spaces::large_object_space::LargeObjectSpace;
+use constants::BLOCK_SIZE;
+use gc_object::GCObjectRef;
+
+/// The `ObjectGraph` renders the objects reachable from a set of start
+/// objects in the Graphviz DOT format.
+///
+/// Every node is labeled with the address, the reference count and the
+/// space of the object (the immix block or the LOS) followed by its pinned
+/// and forwarded flags. Roots are drawn as bold boxes, forwarded objects
+/// with a dashed edge to their new copy and members that are no valid
+/// objects as red dashed nodes that are not followed.
+pub struct ObjectGraph<'a> {
+    /// The immix space of the objects.
+    immix_space: &'a ImmixSpace,
+
+    /// The large object space of the objects.
+    large_object_space: &'a LargeObjectSpace,
+}
+
+impl<'a> ObjectGraph<'a> {
+    /// Create a new `ObjectGraph` for the given spaces.
+    pub fn new(immix_space: &'a ImmixSpace,
+               large_object_space: &'a LargeObjectSpace) -> ObjectGraph<'a> {
+        ObjectGraph {
+            immix_space: immix_space,
+            large_object_space: large_object_space,
+        }
+    }
+
+    /// Return the graph of the objects reachable from `start` in the DOT
+    /// format with the `roots` drawn distinctly.
+    pub fn to_dot(&self, start: &[GCObjectRef], roots: &[GCObjectRef]) -> String {
+        let roots: HashSet<GCObjectRef> = roots.iter().map(|&o| o).collect();
+        let mut visited = HashSet::new();
+        let mut queue: VecDeque<GCObjectRef> = start.iter().map(|&o| o).collect();
+        let mut dot = String::from("digraph heap {\n    node [shape=ellipse];\n");
+        while let Some(object) = queue.pop_front() {
+            if !visited.insert(object) {
+                continue;
+            }
+            if !self.is_gc_object(object) {
+                writeln!(dot, "    \"{:p}\" [label=\"{:p}\\ninvalid\", color=red, style=dashed];",
+                         object, object).unwrap();
+                continue;
+            }
+            let style = if roots.contains(&object) { ", shape=box, style=bold" } else { "" };
+            writeln!(dot, "    \"{:p}\" [label=\"{}\"{}];",
+                     object, self.label(object), style).unwrap();
+            if let Some(new_object) = unsafe{ (*object).is_forwarded() } {
+                writeln!(dot, "    \"{:p}\" -> \"{:p}\" [style=dashed, label=\"forwarded\"];",
+                         object, new_object).unwrap();
+                queue.push_back(new_object);
+                continue;
+            }
+            for child in unsafe{ (*object).children() } {
+                writeln!(dot, "    \"{:p}\" -> \"{:p}\";", object, child).unwrap();
+                queue.push_back(child);
+            }
+        }
+        dot.push_str("}\n");
+        debug!("Exported {} objects as DOT", visited.len());
+        dot
+    }
+}
+
+impl<'a> ObjectGraph<'a> {
+    /// Return if the address is a valid object in any space.
+    fn is_gc_object(&self, object: GCObjectRef) -> bool {
+        self.immix_space.is_gc_object(object)
+            || self.large_object_space.is_gc_object(object)
+    }
+
+    /// Return the node label of a valid `object`.
+    fn label(&self, object: GCObjectRef) -> String {
+        let space = if self.immix_space.is_gc_object(object) {
+            format!("block {:#x}", object as usize - object as usize % BLOCK_SIZE)
+        } else {
+            String::from("LOS")
+        };
+        let mut label = format!("{:p}\\nrc={}\\n{}", object,
+                                unsafe{ (*object).reference_count() }, space);
+        if unsafe{ (*object).is_pinned() } {
+            label.push_str("\\npinned");
+        }
+        if unsafe{ (*object).is_forwarded().is_some() } {
+            label.push_str("\\nforwarded");
+        }
+        label
+    }
+}
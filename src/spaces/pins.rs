@@ -0,0 +1,59 @@
+// Copyright (c) <2015> <lummax>
+// Licensed under MIT (http://opensource.org/licenses/MIT)
+
+use std::collections::HashMap;
+
+use gc_object::GCObjectRef;
+
+/// The `Pins` counts the pins of the objects pinned by the mutator (see
+/// `RCImmixCons::pin()`).
+///
+/// The `GCHeader.pinned` flag of an object is set while it is pinned at
+/// least once, so the collectors never evacuate it. Pinned objects are
+/// roots until they are unpinned as often as they were pinned.
+pub struct Pins {
+    /// The number of pins of every pinned object.
+    counts: HashMap<GCObjectRef, usize>,
+}
+
+impl Pins {
+    /// Create a new `Pins`.
+    pub fn new() -> Pins {
+        Pins {
+            counts: HashMap::new(),
+        }
+    }
+
+    /// Pin the `object` once more.
+    pub fn pin(&mut self, object: GCObjectRef) {
+        let count = self.counts.entry(object).or_insert(0);
+        *count += 1;
+        trace!("Pin object {:p} (count {})", object, *count);
+        unsafe{ (*object).set_pinned(true); }
+    }
+
+    /// Remove one pin of the `object` and return `false` if it was not
+    /// pinned.
+    pub fn unpin(&mut self, object: GCObjectRef) -> bool {
+        let count = match self.counts.get_mut(&object) {
+            Some(count) => { *count -= 1; *count },
+            None => return false,
+        };
+        trace!("Unpin object {:p} (count {})", object, count);
+        if count == 0 {
+            self.counts.remove(&object);
+            unsafe{ (*object).set_pinned(false); }
+        }
+        true
+    }
+
+    /// Return if the `object` is pinned by the mutator.
+    pub fn is_pinned(&self, object: GCObjectRef) -> bool {
+        self.counts.contains_key(&object)
+    }
+
+    /// Return the pinned objects that must be treated as roots.
+    pub fn roots(&self) -> Vec<GCObjectRef> {
+        self.counts.keys().map(|&o| o).collect()
+    }
+}
@@ -0,0 +1,325 @@
+// Copyright (c) <2015> <lummax>
+// Licensed under MIT (http://opensource.org/licenses/MIT)
+
+use std::cell::RefCell;
+use std::sync::{Mutex, Condvar};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use events::debug_assert_not_in_start_hook;
+use gc_object::GCObjectRef;
+use spaces::immix_space::ThreadAllocator;
+use stack::Stack;
+
+/// The source of unique ids for `ThreadRegistry`s.
+static NEXT_REGISTRY_ID: AtomicUsize = AtomicUsize::new(1);
+
+thread_local!(
+    /// The `MutatorThread`s of the current thread (one per `ThreadRegistry`
+    /// id the current thread is registered with).
+    static CURRENT_THREADS: RefCell<Vec<(usize, *mut MutatorThread)>> = RefCell::new(Vec::new())
+);
+
+/// The state of a mutator thread in the stop-the-world handshake.
+///
+/// A thread starts `Running`. It moves to `Parked` in
+/// `ThreadRegistry::safepoint()` if a collection was requested and back to
+/// `Running` once the world is restarted. It moves to `Native` in
+/// `ThreadRegistry::enter_native()` and back to `Running` in
+/// `ThreadRegistry::leave_native()`, which waits for a running collection
+/// to complete. Only `Running` threads prevent the world from stopping.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ThreadState {
+    /// The thread executes mutator code and may access the heap.
+    Running,
+
+    /// The thread is stopped at a safepoint for a collection.
+    Parked,
+
+    /// The thread executes code that does not access the heap.
+    Native,
+}
+
+/// A mutator thread registered with the garbage collector.
+///
+/// It is owned by the `ThreadRegistry` and only accessed by the thread it
+/// represents, except while this thread is stopped for a collection.
+pub struct MutatorThread {
+    /// The stack of this thread to scan for roots.
+    stack: Stack,
+
+    /// The thread-local allocators in the immix space.
+    allocator: ThreadAllocator,
+
+    /// The state of this thread. If it is not `Running` its context is
+    /// saved in the `stack`.
+    state: ThreadState,
+}
+
+unsafe impl Send for MutatorThread {}
+
+impl MutatorThread {
+    /// Return the stack of this thread.
+    pub fn stack(&self) -> &Stack {
+        &self.stack
+    }
+
+    /// Return the stack of this thread.
+    pub fn stack_mut(&mut self) -> &mut Stack {
+        &mut self.stack
+    }
+
+    /// Return the thread-local allocators of this thread.
+    pub fn allocator(&mut self) -> &mut ThreadAllocator {
+        &mut self.allocator
+    }
+
+    /// Return the thread-local allocators of this thread for reading.
+    pub fn allocator_ref(&self) -> &ThreadAllocator {
+        &self.allocator
+    }
+}
+
+/// The state of a `ThreadRegistry` protected by its mutex.
+struct RegistryState {
+    /// The registered threads.
+    threads: Vec<Box<MutatorThread>>,
+
+    /// If a collector requested all threads to stop.
+    stop_requested: bool,
+}
+
+/// The `ThreadRegistry` keeps track of the mutator threads and implements the
+/// cooperative stop-the-world handshake.
+///
+/// A thread that holds the GC lock requests all other threads to stop with
+/// `stop_the_world()` and waits until no thread is `Running` (see
+/// `ThreadState`). The other threads park when they poll `safepoint()`.
+/// Threads in native code (between `enter_native()` and `leave_native()`)
+/// count as stopped. A stopped thread saved the top of its stack and its
+/// registers, so the collector can scan its stack.
+pub struct ThreadRegistry {
+    /// The unique id of this registry used for the thread-local lookup.
+    id: usize,
+
+    /// The registered threads and the handshake state.
+    state: Mutex<RegistryState>,
+
+    /// Signaled if a thread stopped or the world was restarted.
+    condvar: Condvar,
+
+    /// A copy of `RegistryState.stop_requested` to poll without locking.
+    stop_pending: AtomicBool,
+}
+
+impl ThreadRegistry {
+    /// Create a new `ThreadRegistry` without registered threads.
+    pub fn new() -> ThreadRegistry {
+        ThreadRegistry {
+            id: NEXT_REGISTRY_ID.fetch_add(1, Ordering::SeqCst),
+            state: Mutex::new(RegistryState {
+                threads: Vec::new(),
+                stop_requested: false,
+            }),
+            condvar: Condvar::new(),
+            stop_pending: AtomicBool::new(false),
+        }
+    }
+
+    /// Return the `MutatorThread` of the current thread or `None` if the
+    /// current thread is not registered.
+    pub fn current(&self) -> Option<*mut MutatorThread> {
+        let id = self.id;
+        CURRENT_THREADS.with(|threads| threads.borrow().iter()
+                                              .find(|&&(i, _)| i == id)
+                                              .map(|&(_, thread)| thread))
+    }
+
+    /// Register the current thread with the given `allocator`.
+    ///
+    /// Returns `false` if the current thread is already registered.
+    pub fn register(&self, allocator: ThreadAllocator) -> bool {
+        if self.current().is_some() {
+            return false;
+        }
+        let mut state = self.state.lock().unwrap();
+        while state.stop_requested {
+            state = self.condvar.wait(state).unwrap();
+        }
+        let mut thread = Box::new(MutatorThread {
+            stack: Stack::new(),
+            allocator: allocator,
+            state: ThreadState::Running,
+        });
+        let thread_ptr = &mut *thread as *mut MutatorThread;
+        debug!("Register thread {:p} with registry {}", thread_ptr, self.id);
+        state.threads.push(thread);
+        let id = self.id;
+        CURRENT_THREADS.with(|threads| threads.borrow_mut().push((id, thread_ptr)));
+        true
+    }
+
+    /// Unregister the current thread and return its allocator or `None` if
+    /// the current thread is not registered.
+    ///
+    /// This must only be called while holding the GC lock.
+    pub fn unregister(&self) -> Option<ThreadAllocator> {
+        let id = self.id;
+        let thread_ptr = match self.current() {
+            Some(thread_ptr) => thread_ptr,
+            None => return None,
+        };
+        debug!("Unregister thread {:p} from registry {}", thread_ptr, self.id);
+        CURRENT_THREADS.with(|threads| threads.borrow_mut().retain(|&(i, _)| i != id));
+        let mut state = self.state.lock().unwrap();
+        let position = state.threads.iter()
+                            .position(|t| &**t as *const MutatorThread == thread_ptr)
+                            .expect("Registered thread is not in the registry");
+        let thread = state.threads.swap_remove(position);
+        Some(thread.allocator)
+    }
+
+    /// Fold `f` over all registered threads starting with `init`.
+    pub fn fold_threads<T, F: Fn(T, &MutatorThread) -> T>(&self, init: T, f: F) -> T {
+        let state = self.state.lock().unwrap();
+        state.threads.iter().fold(init, |acc, thread| f(acc, thread))
+    }
+
+    /// Park the current thread if a collection was requested and wait until
+    /// the world is restarted.
+    ///
+    /// This only checks an atomic flag if no collection was requested.
+    pub fn safepoint(&self) {
+        debug_assert_not_in_start_hook();
+        if !self.stop_pending.load(Ordering::SeqCst) {
+            return;
+        }
+        let thread = match self.current() {
+            Some(thread) => thread,
+            None => return,
+        };
+        let mut state = self.state.lock().unwrap();
+        if !state.stop_requested || unsafe{ (*thread).state } != ThreadState::Running {
+            return;
+        }
+        let registers = Stack::get_registers();
+        self.stop(thread, ThreadState::Parked, Stack::get_stack_top(), registers);
+        while state.stop_requested {
+            state = self.condvar.wait(state).unwrap();
+        }
+        self.resume(thread);
+    }
+
+    /// Move the current thread into the `Native` state with the given
+    /// context.
+    ///
+    /// Until `leave_native()` the thread must not access the heap and the
+    /// stack frame of `stack_top` must stay live. Returns `false` if the
+    /// current thread is not registered or already in native code.
+    pub fn enter_native(&self, stack_top: *mut u8, registers: Vec<GCObjectRef>) -> bool {
+        let thread = match self.current() {
+            Some(thread) => thread,
+            None => return false,
+        };
+        let _state = self.state.lock().unwrap();
+        if unsafe{ (*thread).state } != ThreadState::Running {
+            return false;
+        }
+        self.stop(thread, ThreadState::Native, stack_top, registers);
+        true
+    }
+
+    /// Move the current thread from the `Native` back into the `Running`
+    /// state, waiting for a running collection to complete.
+    pub fn leave_native(&self) {
+        let thread = match self.current() {
+            Some(thread) => thread,
+            None => return,
+        };
+        let mut state = self.state.lock().unwrap();
+        if unsafe{ (*thread).state } != ThreadState::Native {
+            return;
+        }
+        while state.stop_requested {
+            state = self.condvar.wait(state).unwrap();
+        }
+        self.resume(thread);
+    }
+
+    /// Execute `f` while the current thread is in the `Native` state.
+    ///
+    /// Use this around operations that may block on a thread which waits for
+    /// the world to stop (like acquiring the GC lock). `f` must not access
+    /// the heap.
+    pub fn blocking<T, F: FnOnce() -> T>(&self, f: F) -> T {
+        debug_assert_not_in_start_hook();
+        let registers = Stack::get_registers();
+        if self.enter_native(Stack::get_stack_top(), registers) {
+            let result = f();
+            self.leave_native();
+            result
+        } else {
+            f()
+        }
+    }
+
+    /// Request all other registered threads to stop and wait until they
+    /// are. Return all registered threads.
+    ///
+    /// This must only be called while holding the GC lock.
+    pub fn stop_the_world(&self) -> Vec<*mut MutatorThread> {
+        let current = self.current();
+        let mut state = self.state.lock().unwrap();
+        debug!("Stop the world with {} registered threads", state.threads.len());
+        state.stop_requested = true;
+        self.stop_pending.store(true, Ordering::SeqCst);
+        while state.threads.iter()
+                   .any(|t| t.state == ThreadState::Running
+                            && Some(&**t as *const MutatorThread as *mut MutatorThread) != current) {
+            state = self.condvar.wait(state).unwrap();
+        }
+        state.threads.iter_mut().map(|t| &mut **t as *mut MutatorThread).collect()
+    }
+
+    /// Restart the threads stopped by `stop_the_world()`.
+    pub fn restart_the_world(&self) {
+        let mut state = self.state.lock().unwrap();
+        debug!("Restart the world");
+        state.stop_requested = false;
+        self.stop_pending.store(false, Ordering::SeqCst);
+        self.condvar.notify_all();
+    }
+}
+
+impl ThreadRegistry {
+    /// Save the context of the given thread and move it into the stopped
+    /// `state`.
+    ///
+    /// The registry state must be locked.
+    fn stop(&self, thread: *mut MutatorThread, state: ThreadState,
+            stack_top: *mut u8, registers: Vec<GCObjectRef>) {
+        trace!("Stop thread {:p} ({:?})", thread, state);
+        unsafe {
+            (*thread).stack.save_context(stack_top, registers);
+            (*thread).state = state;
+        }
+        self.condvar.notify_all();
+    }
+
+    /// Move the given thread into the `Running` state again.
+    ///
+    /// The registry state must be locked.
+    fn resume(&self, thread: *mut MutatorThread) {
+        trace!("Resume thread {:p}", thread);
+        unsafe {
+            (*thread).state = ThreadState::Running;
+            (*thread).stack.clear_context();
+        }
+    }
+}
+
+impl Drop for ThreadRegistry {
+    fn drop(&mut self) {
+        let id = self.id;
+        CURRENT_THREADS.with(|threads| threads.borrow_mut().retain(|&(i, _)| i != id));
+    }
+}
@@ -0,0 +1,160 @@
+// Copyright (c) <2015> <lummax>
+// Licensed under MIT (http://opensource.org/licenses/MIT)
+
+use std::collections::HashMap;
+use std::mem;
+
+use spaces::immix_space::{BlockInfo, ImmixSpace};
+use spaces::large_object_space::LargeObjectSpace;
+use constants::{BLOCK_SIZE, LARGE_OBJECT, LINE_SIZE, NUM_LINES_PER_BLOCK};
+use gc_object::{GCObject, GCObjectRef, RC_MAX};
+
+/// The `HeapVerifier` checks the invariants of the heap after a collection.
+///
+/// It walks all allocated blocks of the `ImmixSpace` and all objects of the
+/// `LargeObjectSpace` and checks that:
+///
+/// - every object in an object map has a valid `GCRTTI` (or is forwarded to
+///   a valid object),
+/// - the line counters of the blocks match the live objects,
+/// - no live object points to a freed or forwarded object and
+/// - the reference counts match the references of all live objects and the
+///   counted roots (if the `RCCollector` is used). Reference counts that are
+///   stuck at the maximum are not checked.
+///
+/// Every violation is logged with the addresses involved.
+pub struct HeapVerifier<'a> {
+    /// The immix space to verify.
+    immix_space: &'a ImmixSpace,
+
+    /// The large object space to verify.
+    large_object_space: &'a LargeObjectSpace,
+
+    /// The violations found so far.
+    violations: Vec<String>,
+}
+
+impl<'a> HeapVerifier<'a> {
+    /// Create a new `HeapVerifier` for the given spaces.
+    pub fn new(immix_space: &'a ImmixSpace,
+               large_object_space: &'a LargeObjectSpace) -> HeapVerifier<'a> {
+        HeapVerifier {
+            immix_space: immix_space,
+            large_object_space: large_object_space,
+            violations: Vec::new(),
+        }
+    }
+
+    /// Verify the heap and return the violations found.
+    ///
+    /// The `counted_roots` are the roots whose references are included in
+    /// the reference counts or `None` if the reference counts are not
+    /// maintained.
+    pub fn verify(mut self, counted_roots: Option<&[GCObjectRef]>) -> Vec<String> {
+        debug!(target: "verifier", "Verify heap");
+        let mut objects = Vec::new();
+        for block in self.immix_space.allocated_blocks() {
+            objects.extend(self.verify_block(block));
+        }
+        for object in self.large_object_space.get_objects() {
+            if self.verify_rtti(object) {
+                objects.push(object);
+            }
+        }
+
+        let mut references: HashMap<GCObjectRef, usize> = HashMap::new();
+        for &object in &objects {
+            for child in unsafe{ (*object).children() } {
+                if !self.is_gc_object(child) {
+                    self.report(format!("Object {:p} points to freed object {:p}",
+                                        object, child));
+                } else if unsafe{ (*child).is_forwarded().is_some() } {
+                    self.report(format!("Object {:p} points to forwarded object {:p}",
+                                        object, child));
+                } else {
+                    *references.entry(child).or_insert(0) += 1;
+                }
+            }
+        }
+
+        if let Some(roots) = counted_roots {
+            for &root in roots {
+                *references.entry(root).or_insert(0) += 1;
+            }
+            for &object in &objects {
+                let expected = *references.get(&object).unwrap_or(&0);
+                let reference_count = unsafe{ (*object).reference_count() };
+                if reference_count != RC_MAX && reference_count != expected {
+                    self.report(format!("Object {:p} has reference count {} but {} references",
+                                        object, reference_count, expected));
+                }
+            }
+        }
+        debug!(target: "verifier", "Verified {} objects with {} violations",
+               objects.len(), self.violations.len());
+        self.violations
+    }
+}
+
+impl<'a> HeapVerifier<'a> {
+    /// Log and store a violation.
+    fn report(&mut self, violation: String) {
+        error!(target: "verifier", "{}", violation);
+        self.violations.push(violation);
+    }
+
+    /// Return if the address is a valid object in any space.
+    fn is_gc_object(&self, object: GCObjectRef) -> bool {
+        self.immix_space.is_gc_object(object)
+            || self.large_object_space.is_gc_object(object)
+    }
+
+    /// Verify the objects and the line counters of the `block` and return
+    /// the objects that are not forwarded.
+    fn verify_block(&mut self, block: *mut BlockInfo) -> Vec<GCObjectRef> {
+        let mut objects = Vec::new();
+        // The lines of an object at the end of a block may exceed the block
+        // (see `BlockInfo::object_lines()`).
+        let mut line_counts = vec![0; NUM_LINES_PER_BLOCK + LARGE_OBJECT / LINE_SIZE + 1];
+        for object in unsafe{ (*block).get_object_map() } {
+            if let Some(new_object) = unsafe{ (*object).is_forwarded() } {
+                if !self.immix_space.is_gc_object(new_object) {
+                    self.report(format!("Object {:p} is forwarded to invalid object {:p}",
+                                        object, new_object));
+                }
+            } else if self.verify_rtti(object) {
+                let end = object as usize + unsafe{ (*object).object_size() };
+                if end > block as usize + BLOCK_SIZE {
+                    self.report(format!("Object {:p} exceeds its block {:p}",
+                                        object, block));
+                    continue;
+                }
+                for line in BlockInfo::object_lines(object) {
+                    line_counts[line] += 1;
+                }
+                objects.push(object);
+            }
+        }
+        for (line, &expected) in line_counts.iter().enumerate() {
+            let line_count = unsafe{ (*block).line_count(line) };
+            if line_count != expected {
+                self.report(format!("Line {} of block {:p} has count {} but {} objects",
+                                    line, block, line_count, expected));
+            }
+        }
+        objects
+    }
+
+    /// Verify that the `GCRTTI` of the `object` is valid.
+    fn verify_rtti(&mut self, object: GCObjectRef) -> bool {
+        let rtti = unsafe{ (*object).rtti() };
+        let valid = !rtti.is_null()
+            && (rtti as usize) % mem::align_of::<usize>() == 0
+            && unsafe{ (*rtti).instance_size((*object).length()) }
+                   .map_or(false, |size| size >= mem::size_of::<GCObject>());
+        if !valid {
+            self.report(format!("Object {:p} has an invalid rtti {:p}", object, rtti));
+        }
+        valid
+    }
+}
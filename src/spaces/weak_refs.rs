@@ -0,0 +1,111 @@
+// Copyright (c) <2015> <lummax>
+// Licensed under MIT (http://opensource.org/licenses/MIT)
+
+use std::collections::HashSet;
+use std::ptr;
+
+use spaces::immix_space::ImmixSpace;
+use spaces::large_object_space::LargeObjectSpace;
+use gc_object::GCObjectRef;
+
+/// A weak reference to a `GCObject`.
+///
+/// The referent is neither incremented by the `RCCollector` nor marked by
+/// the `ImmixCollector`. If the referent is reclaimed, the weak reference is
+/// cleared. Create it using `RCImmixCons::weak_create()` and read it using
+/// `RCImmixCons::weak_get()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WeakRef {
+    /// The slot in the `WeakReferences` holding the referent.
+    slot: *mut GCObjectRef,
+}
+
+impl WeakRef {
+    /// Create a `WeakRef` from a slot returned by `WeakRef::into_raw()`.
+    pub fn from_raw(slot: *mut GCObjectRef) -> WeakRef {
+        WeakRef {
+            slot: slot,
+        }
+    }
+
+    /// Return the slot of this `WeakRef`.
+    pub fn into_raw(self) -> *mut GCObjectRef {
+        self.slot
+    }
+
+    /// Return the referent or `None` if it was reclaimed.
+    ///
+    /// This must not be called while a collection is running.
+    pub fn get(&self) -> Option<GCObjectRef> {
+        let object = unsafe{ *self.slot };
+        if object.is_null() { None } else { Some(object) }
+    }
+}
+
+/// The `WeakReferences` contains the slots of all weak references.
+///
+/// After every collection the slots are updated to point to the forwarded
+/// referents or cleared if the referents were reclaimed.
+pub struct WeakReferences {
+    /// The slots of the weak references.
+    slots: HashSet<*mut GCObjectRef>,
+}
+
+impl WeakReferences {
+    /// Create a new `WeakReferences`.
+    pub fn new() -> WeakReferences {
+        WeakReferences {
+            slots: HashSet::new(),
+        }
+    }
+
+    /// Create a new weak reference to `object`.
+    pub fn create(&mut self, object: GCObjectRef) -> WeakRef {
+        let slot = Box::into_raw(Box::new(object));
+        trace!("Create weak reference {:p} to object {:p}", slot, object);
+        self.slots.insert(slot);
+        WeakRef::from_raw(slot)
+    }
+
+    /// Destroy the given weak reference.
+    pub fn destroy(&mut self, weak_ref: WeakRef) {
+        trace!("Destroy weak reference {:p}", weak_ref.slot);
+        if self.slots.remove(&weak_ref.slot) {
+            let _to_be_dropped = unsafe{ Box::from_raw(weak_ref.slot) };
+        }
+    }
+
+    /// Update the slots after a collection.
+    ///
+    /// Referents that were evacuated are replaced by their new address and
+    /// referents that are no valid objects any more are cleared. This must
+    /// be called before any new object is allocated.
+    pub fn process(&mut self, immix_space: &ImmixSpace,
+                   large_object_space: &LargeObjectSpace) {
+        debug!("Process {} weak references", self.slots.len());
+        for &slot in &self.slots {
+            let mut object = unsafe{ *slot };
+            if object.is_null() || large_object_space.is_gc_object(object) {
+                continue;
+            }
+            if immix_space.is_in_space(object) {
+                while let Some(new_object) = unsafe{ (*object).is_forwarded() } {
+                    object = new_object;
+                }
+            }
+            if !immix_space.is_gc_object(object) {
+                trace!("Clear weak reference {:p} to object {:p}", slot, object);
+                object = ptr::null_mut();
+            }
+            unsafe{ *slot = object; }
+        }
+    }
+}
+
+impl Drop for WeakReferences {
+    fn drop(&mut self) {
+        for &slot in &self.slots {
+            let _to_be_dropped = unsafe{ Box::from_raw(slot) };
+        }
+    }
+}
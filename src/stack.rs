@@ -4,9 +4,22 @@
 extern crate libc;
 
 use std::{ptr, mem};
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+use std::arch::asm;
 
 use gc_object::GCObjectRef;
 
+/// Read the contents of the register `$name` as a `GCObjectRef`.
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+macro_rules! read_register(
+    ($name: expr) => {{
+        let value: GCObjectRef;
+        unsafe{ asm!(concat!("mov {}, ", $name), out(reg) value,
+                     options(nomem, nostack, preserves_flags)); }
+        value
+    }};
+);
+
 mod pthread {
     extern crate libc;
 
@@ -21,6 +34,28 @@ mod pthread {
     }
 }
 
+#[cfg(all(target_os = "linux", not(any(target_arch = "x86_64",
+                                       target_arch = "aarch64"))))]
+mod setjmp {
+    extern crate libc;
+
+    /// The number of words reserved for a `jmp_buf`. This is larger than the
+    /// `jmp_buf` of glibc on any supported target.
+    pub const JMP_BUF_WORDS: usize = 128;
+
+    /// A buffer large enough to hold a `jmp_buf`.
+    pub type JmpBuf = [usize; JMP_BUF_WORDS];
+
+    extern {
+        /// The `setjmp()` variant that does not save the signal mask.
+        ///
+        /// It is only used to spill the callee-saved registers into the
+        /// buffer. We never `longjmp()` to it, so it returns exactly once.
+        pub fn _setjmp(env: *mut JmpBuf) -> libc::c_int;
+    }
+}
+
+/// The stack of one mutator thread.
 pub struct Stack {
     /// The bottom of the stack.
     ///
@@ -28,32 +63,64 @@ pub struct Stack {
     /// stack buffer minus the buffer size.
     stack_bottom: *mut u8,
 
-    /// The static roots added via `set_static_root()`.
-    static_roots: Vec<*const GCObjectRef>,
+    /// The top of the stack saved by `save_context()`.
+    stack_top: *mut u8,
+
+    /// The register contents saved by `save_context()`.
+    registers: Vec<GCObjectRef>,
+
+    /// The shadow stack of precise root frames pushed by the mutator. Every
+    /// frame is the address of its first slot and the number of slots.
+    shadow_frames: Vec<(*mut GCObjectRef, usize)>,
+}
+
+/// The static roots added via `set_static_root()` or `add_root_range()`.
+///
+/// These are shared by all mutator threads.
+pub struct StaticRoots {
+    /// The addresses of the first object references of the ranges with the
+    /// number of object references.
+    roots: Vec<(*const GCObjectRef, usize)>,
 }
 
 /// Abstractions over the stack to scan the stack and the registers for
 /// garbage collection roots.
 impl Stack {
-    /// Create a new `Stack`.
+    /// Create a new `Stack` for the current thread.
     pub fn new() -> Stack {
         Stack {
             stack_bottom: Stack::get_stack_bottom()
                                 .expect("Can not get the stack bottom."),
-            static_roots: Vec::new(),
+            stack_top: ptr::null_mut(),
+            registers: Vec::new(),
+            shadow_frames: Vec::new(),
         }
     }
 
+    /// Return the top of the stack (the stack pointer).
+    #[inline(always)]
+    #[cfg(target_arch = "x86_64")]
+    pub fn get_stack_top() -> *mut u8 {
+        read_register!("rsp") as *mut u8
+    }
+
+    /// Return the top of the stack (the stack pointer).
+    #[inline(always)]
+    #[cfg(target_arch = "aarch64")]
+    pub fn get_stack_top() -> *mut u8 {
+        read_register!("sp") as *mut u8
+    }
+
     /// Return the top of the stack.
     ///
-    /// See the `llvm.frameaddress` intrinsic for details.
+    /// This is the address of a local variable. If this is not inlined the
+    /// address is below the frame of the caller, which is still scanned
+    /// completely.
     #[inline(always)]
-    fn get_stack_top() -> *mut u8 {
-        extern {
-            #[link_name = "llvm.frameaddress"]
-            fn frameaddress(level: i32) -> *mut u8;
-        }
-        unsafe{ frameaddress(0) }
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    pub fn get_stack_top() -> *mut u8 {
+        let marker: usize = 0;
+        &marker as *const usize as *mut u8
     }
 
     /// Return the bottom of the stack.
@@ -80,43 +147,184 @@ impl Stack {
 
     /// Get the contents of the registers
     #[inline(always)]
-    #[allow(unused_assignments)]
     #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
     pub fn get_registers() -> Vec<GCObjectRef> {
-        let mut rbx = ptr::null_mut(); unsafe{ asm!("movq %rbx, %rax": "=rax" (rbx));}
-        let mut rsp = ptr::null_mut(); unsafe{ asm!("movq %rsp, %rax": "=rax" (rsp));}
-        let mut rbp = ptr::null_mut(); unsafe{ asm!("movq %rbp, %rax": "=rax" (rbp));}
-        let mut r12 = ptr::null_mut(); unsafe{ asm!("movq %r12, %rax": "=rax" (r12));}
-        let mut r13 = ptr::null_mut(); unsafe{ asm!("movq %r13, %rax": "=rax" (r13));}
-        let mut r14 = ptr::null_mut(); unsafe{ asm!("movq %r14, %rax": "=rax" (r14));}
-        let mut r15 = ptr::null_mut(); unsafe{ asm!("movq %r15, %rax": "=rax" (r15));}
-        let registers = vec![rbx, rsp, rbp, r12, r13, r14, r15];
-        debug!("Register values: {:?}", registers);
+        let registers = vec![read_register!("rbx"), read_register!("rsp"),
+                             read_register!("rbp"), read_register!("r12"),
+                             read_register!("r13"), read_register!("r14"),
+                             read_register!("r15")];
+        trace!("Register values: {:?}", registers);
         registers
     }
 
-    /// Set an address of an object reference as static root.
-    pub fn set_static_root(&mut self, address: *const GCObjectRef) {
-        debug!("Set address {:p} as static root", address);
-        self.static_roots.push(address);
+    /// Get the contents of the registers
+    #[inline(always)]
+    #[cfg(all(target_os = "linux", target_arch = "aarch64"))]
+    pub fn get_registers() -> Vec<GCObjectRef> {
+        let registers = vec![read_register!("x19"), read_register!("x20"),
+                             read_register!("x21"), read_register!("x22"),
+                             read_register!("x23"), read_register!("x24"),
+                             read_register!("x25"), read_register!("x26"),
+                             read_register!("x27"), read_register!("x28"),
+                             read_register!("x29"), read_register!("sp")];
+        trace!("Register values: {:?}", registers);
+        registers
+    }
+
+    /// Get the contents of the registers
+    ///
+    /// This is the portable fallback for targets without a dedicated
+    /// implementation: `_setjmp()` spills the callee-saved registers into a
+    /// `jmp_buf` whose words are returned. Some of them may be mangled by
+    /// the libc (like the stack pointer), but those point into the stack
+    /// which is scanned anyway.
+    #[inline(always)]
+    #[cfg(all(target_os = "linux", not(any(target_arch = "x86_64",
+                                           target_arch = "aarch64"))))]
+    pub fn get_registers() -> Vec<GCObjectRef> {
+        let mut env: setjmp::JmpBuf = [0; setjmp::JMP_BUF_WORDS];
+        unsafe{ setjmp::_setjmp(&mut env); }
+        let registers: Vec<GCObjectRef> = env.iter()
+                                             .map(|&word| word as GCObjectRef)
+                                             .collect();
+        trace!("Register values: {:?}", registers);
+        registers
+    }
+
+    /// Save the top of the stack and the register contents of the owning
+    /// thread before it is stopped for a collection.
+    ///
+    /// The `stack_top` must be within a stack frame that is live while the
+    /// thread is stopped.
+    pub fn save_context(&mut self, stack_top: *mut u8, registers: Vec<GCObjectRef>) {
+        trace!("Save context with stack top {:p}", stack_top);
+        self.stack_top = stack_top;
+        self.registers = registers;
+    }
+
+    /// Clear the saved context after the owning thread resumed.
+    pub fn clear_context(&mut self) {
+        self.stack_top = ptr::null_mut();
+        self.registers.clear();
+    }
+
+    /// Push a frame of `count` precise root slots starting at `slots` onto
+    /// the shadow stack.
+    pub fn push_frame(&mut self, slots: *mut GCObjectRef, count: usize) {
+        trace!("Push shadow stack frame {:p} with {} slots", slots, count);
+        self.shadow_frames.push((slots, count));
+    }
+
+    /// Pop the last frame from the shadow stack and return `false` if the
+    /// shadow stack is empty.
+    pub fn pop_frame(&mut self) -> bool {
+        trace!("Pop shadow stack frame");
+        self.shadow_frames.pop().is_some()
+    }
+
+    /// Return the addresses of all slots of the shadow stack.
+    fn shadow_slots<'a>(&'a self) -> Box<Iterator<Item=*mut GCObjectRef> + 'a> {
+        Box::new(self.shadow_frames.iter().flat_map(|&(slots, count)| {
+            (0..count).map(move |o| unsafe{ slots.offset(o as isize) })
+        }))
+    }
+
+    /// Return the non-null object references in the slots of the shadow
+    /// stack.
+    pub fn enumerate_precise_roots(&self) -> Vec<GCObjectRef> {
+        let roots: Vec<GCObjectRef> = self.shadow_slots()
+                                          .map(|slot| unsafe{ *slot })
+                                          .filter(|o| !o.is_null())
+                                          .collect();
+        debug!("There are {} precise roots on the shadow stack", roots.len());
+        roots
+    }
+
+    /// Replace the object reference in every non-null slot of the shadow
+    /// stack with the result of `update` (e.g. the new address of an
+    /// evacuated object).
+    pub fn update_precise_roots<F: Fn(GCObjectRef) -> GCObjectRef>(&mut self, update: F) {
+        for slot in self.shadow_slots() {
+            let object = unsafe{ *slot };
+            if !object.is_null() {
+                unsafe{ *slot = update(object); }
+            }
+        }
     }
 
     /// Scan the stack for garbage collection roots.
     ///
-    /// This will filter all non-null values on the stack and append the
-    /// static roots added via `Stack::set_static_root()`. The registers must
-    /// be stored on the stack beforehand using `Stack::get_registers().
+    /// This will filter all non-null values on the stack between the saved
+    /// top and the bottom and append the saved registers (see
+    /// `Stack::save_context()`).
     pub fn enumerate_roots(&self) -> Vec<GCObjectRef> {
-        let top = Stack::get_stack_top();
-        let stack_size = (self.stack_bottom as usize) - (top as usize) - 8;
+        let top = self.stack_top;
+        debug_assert!(!top.is_null(), "Scanning a stack without saved context");
+        let stack_size = (self.stack_bottom as usize) - (top as usize)
+                         - mem::size_of::<GCObjectRef>();
         debug!("Scanning stack of size {} ({:p} - {:p})",
                stack_size, top, self.stack_bottom);
-        debug!("There are {} possible static roots: {:?}",
-               self.static_roots.len(),
-               self.static_roots.iter().map(|o| unsafe{ **o }).collect::<Vec<GCObjectRef>>());
-        (0..stack_size).map(|o| unsafe{ *(top.offset(o as isize) as *const GCObjectRef) })
+        (0..stack_size).map(|o| unsafe{
+                           ptr::read_unaligned(top.offset(o as isize) as *const GCObjectRef)
+                       })
+                       .chain(self.registers.iter().map(|o| *o))
                        .filter(|o| !o.is_null())
-                       .chain(self.static_roots.iter().map(|o| unsafe{ **o }))
                        .collect()
     }
 }
+
+impl StaticRoots {
+    /// Create a new empty `StaticRoots`.
+    pub fn new() -> StaticRoots {
+        StaticRoots {
+            roots: Vec::new(),
+        }
+    }
+
+    /// Set an address of an object reference as static root.
+    pub fn set_static_root(&mut self, address: *const GCObjectRef) {
+        self.add_root_range(address, 1);
+    }
+
+    /// Set the `count` consecutive object references starting at `start` as
+    /// static roots.
+    pub fn add_root_range(&mut self, start: *const GCObjectRef, count: usize) {
+        trace!("Set {} addresses starting at {:p} as static roots", count, start);
+        self.roots.push((start, count));
+    }
+
+    /// Remove the static root or root range starting at `start` that was
+    /// added last and return `false` if there is none.
+    pub fn remove_root_range(&mut self, start: *const GCObjectRef) -> bool {
+        trace!("Remove static roots starting at {:p}", start);
+        match self.roots.iter().rposition(|&(address, _)| address == start) {
+            Some(index) => { self.roots.remove(index); true },
+            None => false,
+        }
+    }
+
+    /// Replace the object reference in every non-null static root with the
+    /// result of `update` (e.g. the new address of an evacuated object).
+    pub fn update_roots<F: Fn(GCObjectRef) -> GCObjectRef>(&mut self, update: F) {
+        for &(start, count) in &self.roots {
+            for o in 0..count {
+                let slot = unsafe{ start.offset(o as isize) as *mut GCObjectRef };
+                let object = unsafe{ *slot };
+                if !object.is_null() {
+                    unsafe{ *slot = update(object); }
+                }
+            }
+        }
+    }
+
+    /// Return the static roots.
+    pub fn enumerate_roots(&self) -> Vec<GCObjectRef> {
+        let roots: Vec<GCObjectRef> = self.roots.iter()
+            .flat_map(|&(start, count)| {
+                (0..count).map(move |o| unsafe{ *start.offset(o as isize) })
+            })
+            .collect();
+        debug!("There are {} possible static roots: {:?}", roots.len(), roots);
+        roots
+    }
+}
@@ -0,0 +1,61 @@
+// Copyright (c) <2015> <lummax>
+// Licensed under MIT (http://opensource.org/licenses/MIT)
+
+extern crate libc;
+
+/// The `GCStats` is a snapshot of the heap statistics and the counters of
+/// the collections so far.
+///
+/// Get it with `RCImmixCons::stats()`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct GCStats {
+    /// The number of bytes allocated since the garbage collector was created.
+    pub bytes_allocated: libc::size_t,
+
+    /// The number of objects that survived the last collection.
+    pub live_objects: libc::size_t,
+
+    /// The number of unallocated blocks in the immix space.
+    pub free_blocks: libc::size_t,
+
+    /// The number of blocks with holes waiting to be recycled.
+    pub recyclable_blocks: libc::size_t,
+
+    /// The number of free blocks reserved for evacuation.
+    pub evac_headroom_blocks: libc::size_t,
+
+    /// The number of bytes of the objects in the large object space.
+    pub los_bytes: libc::size_t,
+
+    /// The number of `CollectionType::RCCollection`s.
+    pub rc_collections: libc::size_t,
+
+    /// The number of `CollectionType::RCEvacCollection`s.
+    pub rc_evac_collections: libc::size_t,
+
+    /// The number of `CollectionType::ImmixCollection`s.
+    pub immix_collections: libc::size_t,
+
+    /// The number of `CollectionType::ImmixEvacCollection`s.
+    pub immix_evac_collections: libc::size_t,
+
+    /// The number of objects evacuated by all collections.
+    pub objects_evacuated: libc::size_t,
+
+    /// The time all threads were stopped for collections in nanoseconds.
+    pub pause_time_ns: u64,
+}
+
+impl GCStats {
+    /// Create a new `GCStats` with all counters set to zero.
+    pub fn new() -> GCStats {
+        GCStats::default()
+    }
+
+    /// Return the number of collections of all types.
+    pub fn collections(&self) -> usize {
+        (self.rc_collections + self.rc_evac_collections
+         + self.immix_collections + self.immix_evac_collections) as usize
+    }
+}
@@ -0,0 +1,42 @@
+// Copyright (c) <2015> <lummax>
+// Licensed under MIT (http://opensource.org/licenses/MIT)
+
+#include <rcimmixcons.h>
+#include <stdio.h>
+#include <assert.h>
+
+typedef struct {
+    GCObject object;
+    int data[10];
+} SimpleObject;
+
+static GCRTTI simpleObjectRTTI = {sizeof(SimpleObject), 0};
+
+int main() {
+    GCConfig config = rcx_default_config();
+    config.heap_size = 1000;
+    assert(rcx_create_with_config(&config) == NULL);
+
+    config = rcx_default_config();
+    config.use_rc_collector = 0;
+    config.write_barrier_collect_threshold = 10;
+    assert(rcx_create_with_config(&config) == NULL);
+
+    config = rcx_default_config();
+    config.gc_threads = 0;
+    assert(rcx_create_with_config(&config) == NULL);
+
+    config = rcx_default_config();
+    config.heap_size = 64 * 32 * 1024;
+    config.evac_headroom = 2;
+    config.gc_threads = 4;
+    RCImmixCons* collector = rcx_create_with_config(&config);
+    assert(collector != NULL);
+    for (int i = 0; i < 100000; i++) {
+        SimpleObject* object = (SimpleObject*) rcx_allocate(collector, &simpleObjectRTTI);
+        assert(object != NULL);
+    }
+    rcx_collect(collector, 1, 1);
+    rcx_destroy(collector);
+    return 0;
+}
@@ -0,0 +1,64 @@
+// Copyright (c) <2015> <lummax>
+// Licensed under MIT (http://opensource.org/licenses/MIT)
+
+#include <rcimmixcons.h>
+#include <stdio.h>
+#include <assert.h>
+
+typedef struct {
+    GCObject object;
+    int counter;
+} SimpleObject;
+
+static GCRTTI simpleObjectRTTI = {sizeof(SimpleObject), 0};
+
+typedef struct {
+    GCObject object;
+    int data[4 * 1024];
+} LargeObject;
+
+static GCRTTI largeObjectRTTI = {sizeof(LargeObject), 0};
+
+static SimpleObject* simple_object = NULL;
+static LargeObject* large_object = NULL;
+
+int main() {
+    RCImmixCons* collector = rcx_create();
+    assert(collector != NULL);
+    rcx_set_static_root(collector, &simple_object);
+    rcx_set_static_root(collector, &large_object);
+
+    GCStats stats = rcx_get_stats(collector);
+    assert(stats.bytes_allocated == 0);
+    assert(stats.los_bytes == 0);
+    assert(stats.rc_collections + stats.rc_evac_collections
+           + stats.immix_collections + stats.immix_evac_collections == 0);
+    size_t free_blocks = stats.free_blocks;
+    assert(free_blocks > 0);
+
+    simple_object = (SimpleObject*) rcx_allocate(collector, &simpleObjectRTTI);
+    assert(simple_object != NULL);
+    large_object = (LargeObject*) rcx_allocate(collector, &largeObjectRTTI);
+    assert(large_object != NULL);
+    stats = rcx_get_stats(collector);
+    assert(stats.bytes_allocated >= sizeof(SimpleObject) + sizeof(LargeObject));
+    assert(stats.los_bytes >= sizeof(LargeObject));
+    assert(stats.free_blocks < free_blocks);
+
+    rcx_collect(collector, 0, 0);
+    rcx_collect(collector, 1, 1);
+    stats = rcx_get_stats(collector);
+    printf("(mutator) Collections: %zu/%zu/%zu/%zu, live objects: %zu, pause: %llu ns\n",
+           stats.rc_collections, stats.rc_evac_collections,
+           stats.immix_collections, stats.immix_evac_collections,
+           stats.live_objects, (unsigned long long) stats.pause_time_ns);
+    fflush(stdout);
+    assert(stats.rc_collections + stats.rc_evac_collections
+           + stats.immix_collections + stats.immix_evac_collections == 2);
+    assert(stats.rc_collections >= 1);
+    assert(stats.immix_collections + stats.immix_evac_collections == 1);
+    assert(stats.live_objects >= 2);
+    assert(stats.pause_time_ns > 0);
+    rcx_destroy(collector);
+    return 0;
+}
@@ -0,0 +1,92 @@
+// Copyright (c) <2015> <lummax>
+// Licensed under MIT (http://opensource.org/licenses/MIT)
+
+#include <rcimmixcons.h>
+#include <stdio.h>
+#include <assert.h>
+
+typedef struct {
+    GCObject object;
+    int counter;
+} SimpleObject;
+
+static GCRTTI simpleObjectRTTI = {sizeof(SimpleObject), 0};
+
+typedef struct {
+    int starts;
+    int ends;
+    GCEvent last_end;
+} EventCounter;
+
+void count_event(const GCEvent* event, void* user_data) {
+    EventCounter* counter = (EventCounter*) user_data;
+    if (event->kind == GC_COLLECTION_START) {
+        assert(counter->starts == counter->ends);
+        counter->starts++;
+    } else {
+        assert(event->kind == GC_COLLECTION_END);
+        counter->ends++;
+        counter->last_end = *event;
+    }
+}
+
+typedef struct {
+    RCImmixCons* collector;
+    size_t collections;
+} StatsProbe;
+
+void read_stats(const GCEvent* event, void* user_data) {
+    StatsProbe* probe = (StatsProbe*) user_data;
+    if (event->kind == GC_COLLECTION_END) {
+        GCStats stats = rcx_get_stats(probe->collector);
+        probe->collections = stats.rc_collections + stats.rc_evac_collections
+                             + stats.immix_collections + stats.immix_evac_collections;
+    }
+}
+
+void allocate_garbage(RCImmixCons* collector) {
+    for (int i = 0; i < 100000; i++) {
+        assert(rcx_allocate(collector, &simpleObjectRTTI) != NULL);
+    }
+}
+
+int main() {
+    EventCounter counter = {0, 0};
+    GCConfig config = rcx_default_config();
+    config.use_rc_collector = 0;
+    RCImmixCons* collector = rcx_create_with_config(&config);
+    assert(collector != NULL);
+    rcx_add_event_hook(collector, count_event, &counter);
+
+    allocate_garbage(collector);
+    rcx_collect(collector, 0, 1);
+    assert(counter.starts == 1);
+    assert(counter.ends == 1);
+    assert(counter.last_end.collection_type == GC_IMMIX_COLLECTION);
+    assert(counter.last_end.rc_time_ns == 0);
+    assert(counter.last_end.immix_time_ns > 0);
+    assert(counter.last_end.sweep_time_ns > 0);
+    printf("(mutator) Freed blocks: %zu, recycled blocks: %zu\n",
+           counter.last_end.freed_blocks, counter.last_end.recycled_blocks);
+    fflush(stdout);
+    assert(counter.last_end.freed_blocks + counter.last_end.recycled_blocks > 0);
+    rcx_destroy(collector);
+
+    counter.starts = 0;
+    counter.ends = 0;
+    collector = rcx_create();
+    assert(collector != NULL);
+    rcx_add_event_hook(collector, count_event, &counter);
+    StatsProbe probe = {collector, 0};
+    rcx_add_event_hook(collector, read_stats, &probe);
+    rcx_collect(collector, 0, 0);
+    assert(probe.collections == 1);
+    rcx_collect(collector, 0, 0);
+    assert(probe.collections == 2);
+    assert(counter.starts == 2);
+    assert(counter.ends == 2);
+    assert(counter.last_end.collection_type == GC_RC_COLLECTION);
+    assert(counter.last_end.immix_time_ns == 0);
+    rcx_destroy(collector);
+    return 0;
+}
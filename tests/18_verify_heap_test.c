@@ -0,0 +1,105 @@
+// Copyright (c) <2015> <lummax>
+// Licensed under MIT (http://opensource.org/licenses/MIT)
+
+#include <rcimmixcons.h>
+#include <stdio.h>
+#include <string.h>
+#include <assert.h>
+
+typedef struct ListObject {
+    GCObject object;
+    struct ListObject* next;
+    int value;
+} ListObject;
+
+static GCRTTI listObjectRTTI = {sizeof(ListObject), 1};
+
+static ListObject* list = NULL;
+
+void build_list(RCImmixCons* collector, int length) {
+    for (int i = 0; i < length; i++) {
+        ListObject* object = (ListObject*) rcx_allocate(collector, &listObjectRTTI);
+        assert(object != NULL);
+        object->value = i;
+        object->next = list;
+        list = object;
+    }
+}
+
+void drop_every_other(RCImmixCons* collector) {
+    for (ListObject* object = list; object != NULL && object->next != NULL;
+         object = object->next) {
+        rcx_write_barrier(collector, (GCObject*) object);
+        object->next = object->next->next;
+    }
+}
+
+static char parent_address[32];
+static int parent_reported = 0;
+
+void report_violation(const char* violation, void* user_data) {
+    printf("(mutator) Violation: %s\n", violation);
+    if (strstr(violation, parent_address) != NULL) {
+        (*(int*) user_data)++;
+    }
+}
+
+static ListObject* parent = NULL;
+
+void check_missing_write_barrier() {
+    GCConfig config = rcx_default_config();
+    config.verify_heap = 1;
+    config.scan_stack = 0;
+    RCImmixCons* collector = rcx_create_with_config(&config);
+    assert(collector != NULL);
+    rcx_set_static_root(collector, &parent);
+    parent = (ListObject*) rcx_allocate(collector, &listObjectRTTI);
+    assert(parent != NULL);
+    rcx_collect(collector, 0, 0);
+    assert(rcx_verify_violations(collector, NULL, NULL) == 0);
+
+    // Store the child without the write barrier, so it is never counted.
+    parent->next = (ListObject*) rcx_allocate(collector, &listObjectRTTI);
+    rcx_collect(collector, 0, 0);
+    snprintf(parent_address, sizeof(parent_address), "%p", (void*) parent);
+    size_t violations = rcx_verify_violations(collector, report_violation,
+                                              &parent_reported);
+    assert(violations > 0);
+    assert(parent_reported > 0);
+    parent->next = NULL;
+    rcx_destroy(collector);
+}
+
+int main() {
+    GCConfig config = rcx_default_config();
+    config.verify_heap = 1;
+    RCImmixCons* collector = rcx_create_with_config(&config);
+    assert(collector != NULL);
+    rcx_set_static_root(collector, &list);
+
+    build_list(collector, 10000);
+    rcx_collect(collector, 0, 0);
+    assert(rcx_verify_violations(collector, NULL, NULL) == 0);
+    drop_every_other(collector);
+    rcx_collect(collector, 1, 0);
+    assert(rcx_verify_violations(collector, NULL, NULL) == 0);
+    build_list(collector, 10000);
+    rcx_collect(collector, 1, 1);
+    assert(rcx_verify_violations(collector, NULL, NULL) == 0);
+    drop_every_other(collector);
+    rcx_collect(collector, 0, 0);
+    assert(rcx_verify_violations(collector, NULL, NULL) == 0);
+    rcx_collect(collector, 0, 1);
+    assert(rcx_verify_violations(collector, NULL, NULL) == 0);
+
+    int length = 0;
+    for (ListObject* object = list; object != NULL; object = object->next) {
+        length++;
+    }
+    printf("(mutator) Length of list: %d\n", length);
+    assert(length == 7500);
+    rcx_destroy(collector);
+
+    check_missing_write_barrier();
+    return 0;
+}
@@ -0,0 +1,47 @@
+// Copyright (c) <2015> <lummax>
+// Licensed under MIT (http://opensource.org/licenses/MIT)
+
+#include <rcimmixcons.h>
+#include <stdio.h>
+#include <string.h>
+#include <assert.h>
+
+typedef struct ListObject {
+    GCObject object;
+    struct ListObject* next;
+} ListObject;
+
+static GCRTTI listObjectRTTI = {sizeof(ListObject), 1};
+
+static ListObject* list = NULL;
+
+int main() {
+    RCImmixCons* collector = rcx_create();
+    assert(collector != NULL);
+    rcx_set_static_root(collector, &list);
+    for (int i = 0; i < 100; i++) {
+        ListObject* object = (ListObject*) rcx_allocate(collector, &listObjectRTTI);
+        assert(object != NULL);
+        object->next = list;
+        list = object;
+    }
+    rcx_collect(collector, 0, 0);
+
+    const char* path = "target/19_heap_dump_test.dump";
+    assert(rcx_dump_heap(collector, path));
+    assert(!rcx_dump_heap(collector, "target/does/not/exist.dump"));
+
+    FILE* file = fopen(path, "rb");
+    assert(file != NULL);
+    char magic[8];
+    uint64_t version;
+    assert(fread(magic, 1, sizeof(magic), file) == sizeof(magic));
+    assert(memcmp(magic, "RCXHEAP", sizeof(magic)) == 0);
+    assert(fread(&version, sizeof(version), 1, file) == 1);
+    printf("(mutator) Heap dump version: %lu\n", (unsigned long) version);
+    assert(version == 1);
+    fclose(file);
+    remove(path);
+    rcx_destroy(collector);
+    return 0;
+}
@@ -0,0 +1,56 @@
+// Copyright (c) <2015> <lummax>
+// Licensed under MIT (http://opensource.org/licenses/MIT)
+
+#include <rcimmixcons.h>
+#include <stdio.h>
+#include <string.h>
+#include <assert.h>
+
+typedef struct ListObject {
+    GCObject object;
+    struct ListObject* next;
+} ListObject;
+
+static GCRTTI listObjectRTTI = {sizeof(ListObject), 1};
+
+static ListObject* list = NULL;
+
+int count_edges(const char* path) {
+    FILE* file = fopen(path, "r");
+    assert(file != NULL);
+    char line[256];
+    int edges = 0;
+    while (fgets(line, sizeof(line), file) != NULL) {
+        if (strstr(line, "->") != NULL) {
+            edges++;
+        }
+    }
+    fclose(file);
+    remove(path);
+    return edges;
+}
+
+int main() {
+    RCImmixCons* collector = rcx_create();
+    assert(collector != NULL);
+    rcx_set_static_root(collector, &list);
+    for (int i = 0; i < 10; i++) {
+        ListObject* object = (ListObject*) rcx_allocate(collector, &listObjectRTTI);
+        assert(object != NULL);
+        object->next = list;
+        list = object;
+    }
+    rcx_collect(collector, 0, 0);
+
+    const char* path = "target/21_dot_export_test.dot";
+    assert(rcx_write_dot(collector, NULL, path));
+    int edges = count_edges(path);
+    printf("(mutator) Edges from the roots: %d\n", edges);
+    assert(edges >= 9);
+    assert(rcx_write_dot(collector, (GCObject*) list->next->next, path));
+    edges = count_edges(path);
+    printf("(mutator) Edges from the third object: %d\n", edges);
+    assert(edges == 7);
+    rcx_destroy(collector);
+    return 0;
+}
@@ -0,0 +1,52 @@
+// Copyright (c) <2015> <lummax>
+// Licensed under MIT (http://opensource.org/licenses/MIT)
+
+#include <rcimmixcons.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <assert.h>
+
+typedef struct {
+    GCObject object;
+    int value;
+} SimpleObject;
+
+static GCRTTI simpleObjectRTTI = {sizeof(SimpleObject), 0};
+
+static SimpleObject* objects[100];
+
+int main() {
+    GCConfig config = rcx_default_config();
+    config.verify_heap = 1;
+    RCImmixCons* collector = rcx_create_with_config(&config);
+    assert(collector != NULL);
+    rcx_add_root_range(collector, objects, 100);
+    for (int i = 0; i < 100; i++) {
+        objects[i] = (SimpleObject*) rcx_allocate(collector, &simpleObjectRTTI);
+        assert(objects[i] != NULL);
+        objects[i]->value = i;
+    }
+
+    // A root in memory that is freed after the root was removed.
+    SimpleObject** module_global = malloc(sizeof(SimpleObject*));
+    assert(module_global != NULL);
+    *module_global = objects[0];
+    rcx_set_static_root(collector, module_global);
+    rcx_collect(collector, 1, 1);
+    assert(rcx_remove_static_root(collector, module_global));
+    assert(!rcx_remove_static_root(collector, module_global));
+    free(module_global);
+
+    rcx_collect(collector, 1, 1);
+    rcx_collect(collector, 0, 0);
+    for (int i = 0; i < 100; i++) {
+        assert(objects[i]->value == i);
+    }
+    printf("(mutator) All %d objects in the root range survived\n", 100);
+
+    assert(rcx_remove_root_range(collector, objects));
+    assert(!rcx_remove_root_range(collector, objects));
+    rcx_collect(collector, 0, 0);
+    rcx_destroy(collector);
+    return 0;
+}
@@ -0,0 +1,60 @@
+// Copyright (c) <2015> <lummax>
+// Licensed under MIT (http://opensource.org/licenses/MIT)
+
+#include <rcimmixcons.h>
+#include <stdio.h>
+#include <assert.h>
+
+typedef struct ListObject {
+    GCObject object;
+    struct ListObject* next;
+    int value;
+} ListObject;
+
+static GCRTTI listObjectRTTI = {sizeof(ListObject), 1};
+
+int list_length(ListObject* list) {
+    int length = 0;
+    for (ListObject* object = list; object != NULL; object = object->next) {
+        assert(object->value == length);
+        length++;
+    }
+    return length;
+}
+
+GCWeakRef* build_and_check(RCImmixCons* collector) {
+    ListObject* roots[2] = {NULL, NULL};
+    assert(rcx_push_roots(collector, roots, 2));
+    for (int i = 999; i >= 0; i--) {
+        roots[1] = (ListObject*) rcx_allocate(collector, &listObjectRTTI);
+        assert(roots[1] != NULL);
+        roots[1]->value = i;
+        roots[1]->next = roots[0];
+        roots[0] = roots[1];
+    }
+    rcx_collect(collector, 1, 0);
+    rcx_collect(collector, 1, 1);
+    printf("(mutator) Length of list: %d\n", list_length(roots[0]));
+    assert(list_length(roots[0]) == 1000);
+    GCWeakRef* weak_ref = rcx_weak_create(collector, (GCObject*) roots[0]);
+    assert(rcx_pop_roots(collector));
+    assert(!rcx_pop_roots(collector));
+    return weak_ref;
+}
+
+int main() {
+    GCConfig config = rcx_default_config();
+    config.scan_stack = 0;
+    config.verify_heap = 1;
+    RCImmixCons* collector = rcx_create_with_config(&config);
+    assert(collector != NULL);
+
+    GCWeakRef* weak_ref = build_and_check(collector);
+    assert(weak_ref != NULL);
+    rcx_collect(collector, 0, 1);
+    assert(rcx_weak_get(collector, weak_ref) == NULL);
+
+    rcx_weak_destroy(collector, weak_ref);
+    rcx_destroy(collector);
+    return 0;
+}
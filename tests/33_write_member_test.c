@@ -0,0 +1,78 @@
+// Copyright (c) <2015> <lummax>
+// Licensed under MIT (http://opensource.org/licenses/MIT)
+
+#include <rcimmixcons.h>
+#include <stdio.h>
+#include <assert.h>
+
+typedef struct {
+    GCObject object;
+    int counter;
+} SimpleObject;
+
+static GCRTTI simpleObjectRTTI = {sizeof(SimpleObject), 0};
+
+typedef struct {
+    GCObject object;
+    SimpleObject* attr_a;
+    SimpleObject* attr_b;
+} CompositeObject;
+
+static GCRTTI compositeObjectRTTI = {sizeof(CompositeObject), 2};
+
+static CompositeObject* composite_object = NULL;
+
+SimpleObject* build_simple_object(RCImmixCons* collector, int counter) {
+    SimpleObject* simple_object = (SimpleObject*) rcx_allocate(collector, &simpleObjectRTTI);
+    assert(simple_object != NULL);
+    simple_object->counter = counter;
+    printf("(mutator) Address of simple_object %d: %p\n", counter, simple_object);
+    fflush(stdout);
+    return simple_object;
+}
+
+void build_object(RCImmixCons* collector) {
+    composite_object = (CompositeObject*) rcx_allocate(collector, &compositeObjectRTTI);
+    assert(composite_object != NULL);
+    assert(rcx_write_member(collector, (GCObject*) composite_object, 0,
+                            (GCObject*) build_simple_object(collector, 1)));
+    assert(rcx_write_member(collector, (GCObject*) composite_object, 1,
+                            (GCObject*) build_simple_object(collector, 2)));
+    assert(!rcx_write_member(collector, (GCObject*) composite_object, 2, NULL));
+}
+
+void change_object(RCImmixCons* collector) {
+    rcx_write_member(collector, (GCObject*) composite_object, 0,
+                     (GCObject*) build_simple_object(collector, 3));
+}
+
+void clobber_stack() {
+    volatile char buffer[4096];
+    for (int i = 0; i < 4096; i++) {
+        buffer[i] = 0;
+    }
+}
+
+int main() {
+    RCImmixCons* collector = rcx_create();
+    assert(collector != NULL);
+    rcx_set_static_root(collector, &composite_object);
+    build_object(collector);
+    GCWeakRef* weak_a = rcx_weak_create(collector, (GCObject*) composite_object->attr_a);
+    assert(weak_a != NULL);
+    clobber_stack();
+    rcx_collect(collector, 0, 0);
+    assert(rcx_weak_get(collector, weak_a) == (GCObject*) composite_object->attr_a);
+
+    change_object(collector);
+    clobber_stack();
+    rcx_collect(collector, 0, 0);
+    printf("(mutator) Value of weak_a: %p\n", rcx_weak_get(collector, weak_a));
+    fflush(stdout);
+    assert(rcx_weak_get(collector, weak_a) == NULL);
+    assert(composite_object->attr_a->counter == 3);
+    assert(composite_object->attr_b->counter == 2);
+    rcx_weak_destroy(collector, weak_a);
+    rcx_destroy(collector);
+    return 0;
+}
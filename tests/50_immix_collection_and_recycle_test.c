@@ -36,7 +36,7 @@ int main() {
         for(int tim = 0; tim < 256; tim++) {
             build_object(collector);
         }
-        rcx_collect(collector, 0, 0);
+        rcx_collect(collector, 0, 1);
     }
     rcx_destroy(collector);
     return 0;
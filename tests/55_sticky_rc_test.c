@@ -0,0 +1,92 @@
+// Copyright (c) <2015> <lummax>
+// Licensed under MIT (http://opensource.org/licenses/MIT)
+
+#include <rcimmixcons.h>
+#include <stdio.h>
+#include <assert.h>
+
+typedef struct {
+    GCObject object;
+    int counter;
+} SimpleObject;
+
+static GCRTTI simpleObjectRTTI = {sizeof(SimpleObject), 0};
+
+typedef struct {
+    GCObject object;
+    SimpleObject* attrs[16];
+} HolderObject;
+
+static GCRTTI holderObjectRTTI = {sizeof(HolderObject), 16};
+
+static HolderObject* holder_object = NULL;
+
+void build_object(RCImmixCons* collector) {
+    SimpleObject* simple_object_a = (SimpleObject*) rcx_allocate(collector, &simpleObjectRTTI);
+    assert(simple_object_a != NULL);
+    SimpleObject* simple_object_b = (SimpleObject*) rcx_allocate(collector, &simpleObjectRTTI);
+    assert(simple_object_b != NULL);
+    holder_object = (HolderObject*) rcx_allocate(collector, &holderObjectRTTI);
+    assert(holder_object != NULL);
+    for (int i = 0; i < 8; i++) {
+        holder_object->attrs[i] = simple_object_a;
+        holder_object->attrs[i + 8] = simple_object_b;
+    }
+}
+
+void clobber_stack() {
+    volatile char buffer[4096];
+    for (int i = 0; i < 4096; i++) {
+        buffer[i] = 0;
+    }
+}
+
+int main() {
+    GCConfig config = rcx_default_config();
+    config.use_evacuation = 0;
+    RCImmixCons* collector = rcx_create_with_config(&config);
+    assert(collector != NULL);
+    rcx_set_static_root(collector, &holder_object);
+    build_object(collector);
+    GCWeakRef* weak_a = rcx_weak_create(collector, (GCObject*) holder_object->attrs[0]);
+    assert(weak_a != NULL);
+    GCWeakRef* weak_b = rcx_weak_create(collector, (GCObject*) holder_object->attrs[8]);
+    assert(weak_b != NULL);
+    clobber_stack();
+    rcx_collect(collector, 0, 0);
+
+    // The reference counts of both objects are stuck. Removing all references
+    // to `a` does not reclaim it.
+    rcx_write_barrier(collector, (GCObject*) holder_object);
+    for (int i = 0; i < 16; i++) {
+        if (i != 8) {
+            holder_object->attrs[i] = NULL;
+        }
+    }
+    clobber_stack();
+    rcx_collect(collector, 0, 0);
+    assert(rcx_weak_get(collector, weak_a) != NULL);
+    assert(rcx_weak_get(collector, weak_b) != NULL);
+
+    // The tracing collection reclaims `a` and recomputes the count of `b`.
+    clobber_stack();
+    rcx_collect(collector, 0, 1);
+    printf("(mutator) Value of weak_a: %p\n", rcx_weak_get(collector, weak_a));
+    fflush(stdout);
+    assert(rcx_weak_get(collector, weak_a) == NULL);
+    assert(rcx_weak_get(collector, weak_b) != NULL);
+
+    // Now removing the last reference to `b` reclaims it.
+    rcx_write_barrier(collector, (GCObject*) holder_object);
+    holder_object->attrs[8] = NULL;
+    clobber_stack();
+    rcx_collect(collector, 0, 0);
+    printf("(mutator) Value of weak_b: %p\n", rcx_weak_get(collector, weak_b));
+    fflush(stdout);
+    assert(rcx_weak_get(collector, weak_b) == NULL);
+
+    rcx_weak_destroy(collector, weak_a);
+    rcx_weak_destroy(collector, weak_b);
+    rcx_destroy(collector);
+    return 0;
+}
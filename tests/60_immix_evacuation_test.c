@@ -48,7 +48,9 @@ void exchange_attributes(CompositeObject* object_a, CompositeObject* object_b) {
 }
 
 int main() {
-    RCImmixCons* collector = rcx_create();
+    GCConfig config = rcx_default_config();
+    config.evac_headroom = 1;
+    RCImmixCons* collector = rcx_create_with_config(&config);
     assert(collector != NULL);
 
     for (int times = 0; times < 50; times++) { build_object(collector); }
@@ -58,7 +60,7 @@ int main() {
     assert(composite_object_a != NULL);
     CompositeObject* composite_object_b = build_object(collector);
     assert(composite_object_b != NULL);
-    rcx_collect(collector, 0, 1);
+    rcx_collect(collector, 1, 1);
     assert(composite_object_a != NULL);
     assert(composite_object_b != NULL);
     rcx_write_barrier(collector, (GCObject*) composite_object_a);
@@ -68,7 +70,7 @@ int main() {
     assert(composite_object_a != NULL);
     assert(composite_object_b != NULL);
     exchange_attributes(composite_object_a, composite_object_b);
-    rcx_collect(collector, 0, 1);
+    rcx_collect(collector, 1, 1);
     assert(composite_object_a != NULL);
     assert(composite_object_b != NULL);
 
@@ -0,0 +1,65 @@
+// Copyright (c) <2015> <lummax>
+// Licensed under MIT (http://opensource.org/licenses/MIT)
+
+#include <rcimmixcons.h>
+#include <stdio.h>
+#include <assert.h>
+
+typedef struct ListObject {
+    GCObject object;
+    struct ListObject* next;
+    int value;
+} ListObject;
+
+static GCRTTI listObjectRTTI = {sizeof(ListObject), 1};
+
+int list_length(ListObject* list) {
+    int length = 0;
+    for (ListObject* object = list; object != NULL; object = object->next) {
+        assert(object->value == length);
+        length++;
+    }
+    return length;
+}
+
+void prepend(RCImmixCons* collector, ListObject** roots, int value) {
+    roots[1] = (ListObject*) rcx_allocate(collector, &listObjectRTTI);
+    assert(roots[1] != NULL);
+    roots[1]->value = value;
+    roots[1]->next = roots[0];
+    roots[0] = roots[1];
+}
+
+int main() {
+    GCConfig config = rcx_default_config();
+    config.scan_stack = 0;
+    config.use_nursery = 1;
+    config.verify_heap = 1;
+    RCImmixCons* collector = rcx_create_with_config(&config);
+    assert(collector != NULL);
+
+    ListObject* roots[2] = {NULL, NULL};
+    rcx_push_roots(collector, roots, 2);
+    for (int i = 999; i >= 500; i--) { prepend(collector, roots, i); }
+    ListObject* young = roots[0];
+    rcx_collect(collector, 0, 0);
+    printf("(mutator) Moved list from %p to %p\n", young, roots[0]);
+    fflush(stdout);
+    assert(roots[0] != young);
+
+    for (int i = 499; i >= 0; i--) { prepend(collector, roots, i); }
+    rcx_collect(collector, 0, 0);
+    printf("(mutator) Length of list: %d\n", list_length(roots[0]));
+    fflush(stdout);
+    assert(list_length(roots[0]) == 1000);
+
+    GCStats stats = rcx_get_stats(collector);
+    printf("(mutator) Evacuated objects: %zu\n", stats.objects_evacuated);
+    fflush(stdout);
+    assert(stats.rc_evac_collections == 2);
+    assert(stats.objects_evacuated >= 1000);
+
+    rcx_pop_roots(collector);
+    rcx_destroy(collector);
+    return 0;
+}
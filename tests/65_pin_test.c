@@ -0,0 +1,61 @@
+// Copyright (c) <2015> <lummax>
+// Licensed under MIT (http://opensource.org/licenses/MIT)
+
+#include <rcimmixcons.h>
+#include <stdio.h>
+#include <stdint.h>
+#include <assert.h>
+
+typedef struct {
+    GCObject object;
+    int data[10];
+} SimpleObject;
+
+static GCRTTI simpleObjectRTTI = {sizeof(SimpleObject), 0};
+
+// The address of the pinned object is hidden from the conservative stack
+// scanning, like a pointer held by native code.
+#define HIDE(pointer) ((uintptr_t) (pointer) ^ UINTPTR_MAX)
+
+void allocate_garbage(RCImmixCons* collector) {
+    for (int i = 0; i < 10000; i++) {
+        assert(rcx_allocate(collector, &simpleObjectRTTI) != NULL);
+    }
+}
+
+uintptr_t allocate_pinned(RCImmixCons* collector) {
+    SimpleObject* object = (SimpleObject*) rcx_allocate(collector, &simpleObjectRTTI);
+    assert(object != NULL);
+    object->data[0] = 42;
+    assert(rcx_pin(collector, (GCObject*) object));
+    assert(rcx_pin(collector, (GCObject*) object));
+    return HIDE(object);
+}
+
+int main() {
+    RCImmixCons* collector = rcx_create();
+    assert(collector != NULL);
+
+    allocate_garbage(collector);
+    uintptr_t hidden = allocate_pinned(collector);
+    allocate_garbage(collector);
+    GCWeakRef* weak_ref = rcx_weak_create(collector, (GCObject*) HIDE(hidden));
+    assert(weak_ref != NULL);
+
+    rcx_collect(collector, 1, 0);
+    rcx_collect(collector, 1, 1);
+    assert(rcx_unpin(collector, (GCObject*) HIDE(hidden)));
+    rcx_collect(collector, 1, 0);
+    rcx_collect(collector, 1, 1);
+
+    SimpleObject* object = (SimpleObject*) rcx_weak_get(collector, weak_ref);
+    printf("(mutator) Pinned object %p is at %p\n", (void*) HIDE(hidden), object);
+    assert(object == (SimpleObject*) HIDE(hidden));
+    assert(object->data[0] == 42);
+    assert(rcx_unpin(collector, (GCObject*) object));
+    assert(!rcx_unpin(collector, (GCObject*) object));
+
+    rcx_weak_destroy(collector, weak_ref);
+    rcx_destroy(collector);
+    return 0;
+}
@@ -0,0 +1,76 @@
+// Copyright (c) <2015> <lummax>
+// Licensed under MIT (http://opensource.org/licenses/MIT)
+
+#include <rcimmixcons.h>
+#include <stdio.h>
+#include <assert.h>
+#include <pthread.h>
+
+#define NUM_THREADS 4
+
+typedef struct {
+    GCObject object;
+    int counter;
+} SimpleObject;
+
+static GCRTTI simpleObjectRTTI = {sizeof(SimpleObject), 0};
+
+typedef struct {
+    GCObject object;
+    SimpleObject* attr_a;
+    SimpleObject* attr_b;
+} CompositeObject;
+
+static GCRTTI compositeObjectRTTI = {sizeof(CompositeObject), 2};
+
+CompositeObject* build_object(RCImmixCons* collector, int counter) {
+    SimpleObject* simple_object_a = (SimpleObject*) rcx_allocate(collector, &simpleObjectRTTI);
+    assert(simple_object_a != NULL);
+    SimpleObject* simple_object_b = (SimpleObject*) rcx_allocate(collector, &simpleObjectRTTI);
+    assert(simple_object_b != NULL);
+    CompositeObject* composite_object = (CompositeObject*) rcx_allocate(collector, &compositeObjectRTTI);
+    assert(composite_object != NULL);
+    simple_object_a->counter = counter;
+    simple_object_b->counter = counter;
+    composite_object->attr_a = simple_object_a;
+    composite_object->attr_b = simple_object_b;
+    return composite_object;
+}
+
+void* do_work(void* data) {
+    RCImmixCons* collector = (RCImmixCons*) data;
+    assert(rcx_register_thread(collector));
+    assert(!rcx_register_thread(collector));
+    CompositeObject* composite_object = build_object(collector, 0);
+    for (int i = 1; i <= 1000; i++) {
+        CompositeObject* old = composite_object;
+        composite_object = build_object(collector, old->attr_a->counter + 1);
+        if (i % 100 == 0) {
+            rcx_collect(collector, 1, 1);
+        }
+    }
+    printf("(mutator) Value of attr_a: %d\n", composite_object->attr_a->counter);
+    fflush(stdout);
+    assert(composite_object->attr_a->counter == 1000);
+    assert(composite_object->attr_b->counter == 1000);
+    assert(rcx_unregister_thread(collector));
+    assert(!rcx_unregister_thread(collector));
+    return NULL;
+}
+
+int main() {
+    RCImmixCons* collector = rcx_create();
+    assert(collector != NULL);
+    rcx_enter_native(collector);
+    pthread_t threads[NUM_THREADS];
+    for (int i = 0; i < NUM_THREADS; i++) {
+        assert(pthread_create(&threads[i], NULL, do_work, collector) == 0);
+    }
+    for (int i = 0; i < NUM_THREADS; i++) {
+        assert(pthread_join(threads[i], NULL) == 0);
+    }
+    rcx_leave_native(collector);
+    rcx_collect(collector, 1, 1);
+    rcx_destroy(collector);
+    return 0;
+}
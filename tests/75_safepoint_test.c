@@ -0,0 +1,69 @@
+// Copyright (c) <2015> <lummax>
+// Licensed under MIT (http://opensource.org/licenses/MIT)
+
+#include <rcimmixcons.h>
+#include <stdio.h>
+#include <assert.h>
+#include <pthread.h>
+
+typedef struct {
+    GCObject object;
+    int counter;
+} SimpleObject;
+
+static GCRTTI simpleObjectRTTI = {sizeof(SimpleObject), 0};
+
+static volatile int collections_done = 0;
+
+void* do_poll(void* data) {
+    RCImmixCons* collector = (RCImmixCons*) data;
+    rcx_register_thread(collector);
+    SimpleObject* simple_object = (SimpleObject*) rcx_allocate(collector, &simpleObjectRTTI);
+    assert(simple_object != NULL);
+    simple_object->counter = 42;
+    printf("(mutator) Address of simple_object: %p\n", simple_object);
+    fflush(stdout);
+    while (!collections_done) {
+        simple_object->counter = 42;
+        rcx_safepoint_poll(collector);
+    }
+    assert(simple_object->counter == 42);
+    rcx_unregister_thread(collector);
+    return NULL;
+}
+
+void* do_native(void* data) {
+    RCImmixCons* collector = (RCImmixCons*) data;
+    rcx_register_thread(collector);
+    SimpleObject* simple_object = (SimpleObject*) rcx_allocate(collector, &simpleObjectRTTI);
+    assert(simple_object != NULL);
+    simple_object->counter = 23;
+    assert(rcx_enter_native(collector));
+    assert(!rcx_enter_native(collector));
+    while (!collections_done) { }
+    rcx_leave_native(collector);
+    assert(simple_object->counter == 23);
+    rcx_unregister_thread(collector);
+    return NULL;
+}
+
+int main() {
+    RCImmixCons* collector = rcx_create();
+    assert(collector != NULL);
+    pthread_t poll_thread, native_thread;
+    assert(pthread_create(&poll_thread, NULL, do_poll, collector) == 0);
+    assert(pthread_create(&native_thread, NULL, do_native, collector) == 0);
+    for (int i = 0; i < 10; i++) {
+        for (int j = 0; j < 1000; j++) {
+            assert(rcx_allocate(collector, &simpleObjectRTTI) != NULL);
+        }
+        rcx_collect(collector, 1, 1);
+    }
+    collections_done = 1;
+    rcx_enter_native(collector);
+    assert(pthread_join(poll_thread, NULL) == 0);
+    assert(pthread_join(native_thread, NULL) == 0);
+    rcx_leave_native(collector);
+    rcx_destroy(collector);
+    return 0;
+}
@@ -0,0 +1,80 @@
+// Copyright (c) <2015> <lummax>
+// Licensed under MIT (http://opensource.org/licenses/MIT)
+
+#include <rcimmixcons.h>
+#include <stdio.h>
+#include <assert.h>
+
+typedef struct {
+    GCObject object;
+    int counter;
+} SimpleObject;
+
+static GCRTTI simpleObjectRTTI = {sizeof(SimpleObject), 0};
+
+typedef struct {
+    GCObject object;
+    SimpleObject* attr_a;
+    SimpleObject* attr_b;
+} CompositeObject;
+
+static GCRTTI compositeObjectRTTI = {sizeof(CompositeObject), 2};
+
+static CompositeObject* composite_object = NULL;
+
+void build_object(RCImmixCons* collector) {
+    SimpleObject* simple_object_a = (SimpleObject*) rcx_allocate(collector, &simpleObjectRTTI);
+    assert(simple_object_a != NULL);
+    SimpleObject* simple_object_b = (SimpleObject*) rcx_allocate(collector, &simpleObjectRTTI);
+    assert(simple_object_b != NULL);
+    composite_object = (CompositeObject*) rcx_allocate(collector, &compositeObjectRTTI);
+    assert(composite_object != NULL);
+    simple_object_a->counter = 1;
+    simple_object_b->counter = 2;
+    composite_object->attr_a = simple_object_a;
+    composite_object->attr_b = simple_object_b;
+}
+
+void clobber_stack() {
+    volatile char buffer[4096];
+    for (int i = 0; i < 4096; i++) {
+        buffer[i] = 0;
+    }
+}
+
+int main() {
+    RCImmixCons* collector = rcx_create();
+    assert(collector != NULL);
+    rcx_set_static_root(collector, &composite_object);
+    build_object(collector);
+    GCWeakRef* weak_a = rcx_weak_create(collector, (GCObject*) composite_object->attr_a);
+    assert(weak_a != NULL);
+    GCWeakRef* weak_b = rcx_weak_create(collector, (GCObject*) composite_object->attr_b);
+    assert(weak_b != NULL);
+    int on_stack = 0;
+    assert(rcx_weak_create(collector, (GCObject*) &on_stack) == NULL);
+    clobber_stack();
+    rcx_collect(collector, 1, 1);
+
+    SimpleObject* object_a = (SimpleObject*) rcx_weak_get(collector, weak_a);
+    assert(object_a == composite_object->attr_a);
+    assert(object_a->counter == 1);
+    object_a = NULL;
+
+    rcx_write_barrier(collector, (GCObject*) composite_object);
+    composite_object->attr_a = NULL;
+    clobber_stack();
+    rcx_collect(collector, 0, 0);
+    rcx_collect(collector, 1, 1);
+
+    printf("(mutator) Value of weak_a: %p\n", rcx_weak_get(collector, weak_a));
+    fflush(stdout);
+    assert(rcx_weak_get(collector, weak_a) == NULL);
+    SimpleObject* object_b = (SimpleObject*) rcx_weak_get(collector, weak_b);
+    assert(object_b == composite_object->attr_b);
+    assert(object_b->counter == 2);
+    rcx_weak_destroy(collector, weak_a);
+    rcx_weak_destroy(collector, weak_b);
+    rcx_destroy(collector);
+    return 0;
+}
@@ -0,0 +1,119 @@
+// Copyright (c) <2015> <lummax>
+// Licensed under MIT (http://opensource.org/licenses/MIT)
+
+#include <rcimmixcons.h>
+#include <stdio.h>
+#include <assert.h>
+
+typedef struct {
+    GCObject object;
+    int value;
+} SimpleObject;
+
+typedef struct {
+    GCObject object;
+    int data[4 * 1024];
+} LargeObject;
+
+typedef struct {
+    GCObject object;
+    SimpleObject* attr_a;
+} CompositeObject;
+
+static int finalized = 0;
+static int resurrect = 0;
+static SimpleObject* resurrected = NULL;
+static CompositeObject* composite_object = NULL;
+
+void finalize(GCObject* object) {
+    finalized++;
+    if (resurrect) {
+        resurrected = (SimpleObject*) object;
+    }
+}
+
+static GCRTTI simpleObjectRTTI = {sizeof(SimpleObject), 0, finalize};
+static GCRTTI largeObjectRTTI = {sizeof(LargeObject), 0, finalize};
+static GCRTTI compositeObjectRTTI = {sizeof(CompositeObject), 1, NULL};
+
+void clobber_stack() {
+    volatile char buffer[4096];
+    for (int i = 0; i < 4096; i++) {
+        buffer[i] = 0;
+    }
+}
+
+void allocate_garbage(RCImmixCons* collector, GCRTTI* rtti, int count) {
+    for (int i = 0; i < count; i++) {
+        assert(rcx_allocate(collector, rtti) != NULL);
+    }
+}
+
+void test_new_objects(RCImmixCons* collector, uint8_t cycle_collect) {
+    finalized = 0;
+    allocate_garbage(collector, &simpleObjectRTTI, 100);
+    allocate_garbage(collector, &largeObjectRTTI, 10);
+    clobber_stack();
+    rcx_collect(collector, 0, cycle_collect);
+    printf("(mutator) Finalized new objects: %d\n", finalized);
+    fflush(stdout);
+    assert(finalized >= 100);
+    rcx_collect(collector, 0, cycle_collect);
+    rcx_collect(collector, 1, 1);
+    assert(finalized <= 110);
+}
+
+void test_old_object(RCImmixCons* collector) {
+    finalized = 0;
+    composite_object = (CompositeObject*) rcx_allocate(collector, &compositeObjectRTTI);
+    assert(composite_object != NULL);
+    composite_object->attr_a = (SimpleObject*) rcx_allocate(collector, &simpleObjectRTTI);
+    assert(composite_object->attr_a != NULL);
+    composite_object->attr_a->value = 42;
+    clobber_stack();
+    rcx_collect(collector, 0, 0);
+    assert(finalized == 0);
+
+    resurrect = 1;
+    rcx_write_barrier(collector, (GCObject*) composite_object);
+    composite_object->attr_a = NULL;
+    clobber_stack();
+    rcx_collect(collector, 0, 0);
+    resurrect = 0;
+    assert(finalized == 1);
+    assert(resurrected != NULL);
+    assert(resurrected->value == 42);
+
+    GCWeakRef* weak_ref = rcx_weak_create(collector, (GCObject*) resurrected);
+    assert(weak_ref != NULL);
+    rcx_collect(collector, 1, 1);
+    assert(rcx_weak_get(collector, weak_ref) == (GCObject*) resurrected);
+    assert(resurrected->value == 42);
+
+    resurrected = NULL;
+    clobber_stack();
+    rcx_collect(collector, 0, 0);
+    rcx_collect(collector, 1, 1);
+    assert(finalized == 1);
+    assert(rcx_weak_get(collector, weak_ref) == NULL);
+    rcx_weak_destroy(collector, weak_ref);
+}
+
+int main() {
+    RCImmixCons* collector = rcx_create();
+    assert(collector != NULL);
+    rcx_set_static_root(collector, &composite_object);
+    rcx_set_static_root(collector, &resurrected);
+    test_new_objects(collector, 0);
+    test_new_objects(collector, 1);
+    test_old_object(collector);
+    rcx_destroy(collector);
+
+    GCConfig config = rcx_default_config();
+    config.use_rc_collector = 0;
+    collector = rcx_create_with_config(&config);
+    assert(collector != NULL);
+    test_new_objects(collector, 1);
+    rcx_destroy(collector);
+    return 0;
+}
@@ -0,0 +1,92 @@
+// Copyright (c) <2015> <lummax>
+// Licensed under MIT (http://opensource.org/licenses/MIT)
+
+#include <rcimmixcons.h>
+#include <stdio.h>
+#include <stddef.h>
+#include <assert.h>
+
+typedef struct {
+    GCObject object;
+    int counter;
+} SimpleObject;
+
+static GCRTTI simpleObjectRTTI = {sizeof(SimpleObject), 0, NULL, NULL};
+
+typedef struct {
+    GCObject object;
+    int value_a;
+    SimpleObject* attr_a;
+    double value_b;
+    SimpleObject* attr_b;
+    long value_c;
+} MixedObject;
+
+static const size_t mixedObjectOffsets[] = {
+    offsetof(MixedObject, attr_a),
+    offsetof(MixedObject, attr_b),
+};
+
+static GCRTTI mixedObjectRTTI = {sizeof(MixedObject), 2, NULL, mixedObjectOffsets};
+
+static MixedObject* mixed_object = NULL;
+
+void clobber_stack() {
+    volatile char buffer[4096];
+    for (int i = 0; i < 4096; i++) {
+        buffer[i] = 0;
+    }
+}
+
+void build_object(RCImmixCons* collector) {
+    mixed_object = (MixedObject*) rcx_allocate(collector, &mixedObjectRTTI);
+    assert(mixed_object != NULL);
+    mixed_object->value_a = 1;
+    mixed_object->value_b = 2.0;
+    mixed_object->value_c = 3;
+    mixed_object->attr_a = (SimpleObject*) rcx_allocate(collector, &simpleObjectRTTI);
+    assert(mixed_object->attr_a != NULL);
+    mixed_object->attr_a->counter = 4;
+    mixed_object->attr_b = (SimpleObject*) rcx_allocate(collector, &simpleObjectRTTI);
+    assert(mixed_object->attr_b != NULL);
+    mixed_object->attr_b->counter = 5;
+}
+
+void allocate_garbage(RCImmixCons* collector) {
+    for (int i = 0; i < 1000; i++) {
+        SimpleObject* object = (SimpleObject*) rcx_allocate(collector, &simpleObjectRTTI);
+        assert(object != NULL);
+        object->counter = -1;
+    }
+}
+
+void check_object() {
+    assert(mixed_object->value_a == 1);
+    assert(mixed_object->value_b == 2.0);
+    assert(mixed_object->value_c == 3);
+    assert(mixed_object->attr_a->counter == 4);
+    assert(mixed_object->attr_b->counter == 5);
+}
+
+int main() {
+    RCImmixCons* collector = rcx_create();
+    assert(collector != NULL);
+    rcx_set_static_root(collector, &mixed_object);
+    build_object(collector);
+    for (int i = 0; i < 3; i++) {
+        clobber_stack();
+        rcx_collect(collector, 1, 0);
+        allocate_garbage(collector);
+        check_object();
+        clobber_stack();
+        rcx_collect(collector, 1, 1);
+        allocate_garbage(collector);
+        check_object();
+    }
+    printf("(mutator) Values of mixed_object: %d %f %ld %d %d\n",
+           mixed_object->value_a, mixed_object->value_b, mixed_object->value_c,
+           mixed_object->attr_a->counter, mixed_object->attr_b->counter);
+    fflush(stdout);
+    rcx_destroy(collector);
+    return 0;
+}
@@ -0,0 +1,99 @@
+// Copyright (c) <2015> <lummax>
+// Licensed under MIT (http://opensource.org/licenses/MIT)
+
+#include <rcimmixcons.h>
+#include <stdio.h>
+#include <string.h>
+#include <assert.h>
+
+typedef struct {
+    GCObject object;
+    int counter;
+} SimpleObject;
+
+static GCRTTI simpleObjectRTTI = {sizeof(SimpleObject), 0, NULL, NULL, 0, 0};
+
+typedef struct {
+    GCObject object;
+    size_t capacity;
+    SimpleObject* elements[];
+} VectorObject;
+
+static GCRTTI vectorObjectRTTI = {sizeof(VectorObject), 0, NULL, NULL, sizeof(SimpleObject*), 1};
+
+typedef struct {
+    GCObject object;
+    char chars[];
+} StringObject;
+
+static GCRTTI stringObjectRTTI = {sizeof(StringObject), 0, NULL, NULL, 1, 0};
+
+static VectorObject* small_vector = NULL;
+static VectorObject* large_vector = NULL;
+static StringObject* string = NULL;
+
+void clobber_stack() {
+    volatile char buffer[4096];
+    for (int i = 0; i < 4096; i++) {
+        buffer[i] = 0;
+    }
+}
+
+VectorObject* build_vector(RCImmixCons* collector, size_t length) {
+    VectorObject* vector = (VectorObject*) rcx_allocate_array(collector, &vectorObjectRTTI, length);
+    assert(vector != NULL);
+    assert(rcx_array_length((GCObject*) vector) == length);
+    vector->capacity = length;
+    for (size_t i = 0; i < length; i++) {
+        SimpleObject* element = (SimpleObject*) rcx_allocate(collector, &simpleObjectRTTI);
+        assert(element != NULL);
+        element->counter = i;
+        vector->elements[i] = element;
+    }
+    return vector;
+}
+
+void allocate_garbage(RCImmixCons* collector) {
+    for (int i = 0; i < 1000; i++) {
+        StringObject* object = (StringObject*) rcx_allocate_array(collector, &stringObjectRTTI, i % 100);
+        assert(object != NULL);
+        memset(object->chars, 'x', i % 100);
+    }
+}
+
+void check_vector(VectorObject* vector) {
+    for (size_t i = 0; i < vector->capacity; i++) {
+        assert(vector->elements[i]->counter == (int) i);
+    }
+}
+
+int main() {
+    RCImmixCons* collector = rcx_create();
+    assert(collector != NULL);
+    assert(rcx_allocate_array(collector, &simpleObjectRTTI, 10) == NULL);
+    rcx_set_static_root(collector, &small_vector);
+    rcx_set_static_root(collector, &large_vector);
+    rcx_set_static_root(collector, &string);
+    small_vector = build_vector(collector, 10);
+    large_vector = build_vector(collector, 2000);
+    string = (StringObject*) rcx_allocate_array(collector, &stringObjectRTTI, 13);
+    assert(string != NULL);
+    strcpy(string->chars, "hello world!");
+    for (int i = 0; i < 3; i++) {
+        clobber_stack();
+        rcx_collect(collector, 1, 0);
+        allocate_garbage(collector);
+        check_vector(small_vector);
+        check_vector(large_vector);
+        clobber_stack();
+        rcx_collect(collector, 1, 1);
+        allocate_garbage(collector);
+        check_vector(small_vector);
+        check_vector(large_vector);
+        assert(strcmp(string->chars, "hello world!") == 0);
+    }
+    printf("(mutator) Value of string: %s\n", string->chars);
+    fflush(stdout);
+    rcx_destroy(collector);
+    return 0;
+}
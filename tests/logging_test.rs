@@ -0,0 +1,55 @@
+// Copyright (c) <2015> <lummax>
+// Licensed under MIT (http://opensource.org/licenses/MIT)
+
+// The logger is process-wide, so this test runs in its own binary.
+
+extern crate rcimmixcons;
+extern crate log;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+struct CountingLogger;
+
+static OBJECT: AtomicUsize = AtomicUsize::new(0);
+static DEBUG_RECORDS: AtomicUsize = AtomicUsize::new(0);
+static OBJECT_TRACE_RECORDS: AtomicUsize = AtomicUsize::new(0);
+
+impl log::Log for CountingLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.target() == "rc_collector"
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        match record.level() {
+            log::Level::Debug => { DEBUG_RECORDS.fetch_add(1, Ordering::SeqCst); },
+            log::Level::Trace => {
+                let object = format!("{:p}", OBJECT.load(Ordering::SeqCst) as *const u8);
+                if record.args().to_string().contains(&object) {
+                    OBJECT_TRACE_RECORDS.fetch_add(1, Ordering::SeqCst);
+                }
+            },
+            _ => {},
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: CountingLogger = CountingLogger;
+
+#[test]
+fn logging_test() {
+    log::set_logger(&LOGGER).unwrap();
+    log::set_max_level(log::LevelFilter::Trace);
+    let collector = rcimmixcons::RCImmixCons::new();
+    let rtti = rcimmixcons::GCRTTI::new(128, 0);
+    let object = collector.allocate(&rtti).unwrap();
+    OBJECT.store(object as usize, Ordering::SeqCst);
+    collector.collect(false, false);
+    assert!(DEBUG_RECORDS.load(Ordering::SeqCst) > 0);
+    assert!(OBJECT_TRACE_RECORDS.load(Ordering::SeqCst) > 0);
+    assert_eq!(unsafe{ (*object).rtti() }, &rtti as *const _);
+}
@@ -2,11 +2,20 @@
 // Licensed under MIT (http://opensource.org/licenses/MIT)
 
 extern crate rcimmixcons;
+#[macro_use] extern crate rcimmixcons_derive;
+
+use std::mem;
+use std::ptr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use rcimmixcons::{Gc, GcCell};
 
 #[test]
 #[allow(unused_variables)]
 fn simple_allocate_test() {
-    let mut collector = rcimmixcons::RCImmixCons::new();
+    let collector = rcimmixcons::RCImmixCons::new();
     let rtti = rcimmixcons::GCRTTI::new(128, 0);
     let chunck1 = collector.allocate(&rtti).unwrap();
     let chunck2 = collector.allocate(&rtti).unwrap();
@@ -16,3 +25,574 @@ fn simple_allocate_test() {
     collector.collect(false, false);
 }
 
+
+#[test]
+fn invalid_config_test() {
+    let config = rcimmixcons::GCConfig::new().with_heap_size(1000);
+    assert!(rcimmixcons::RCImmixCons::with_config(config).is_err());
+    let config = rcimmixcons::GCConfig::new().with_cycle_trigger_threshold(2.0);
+    assert!(rcimmixcons::RCImmixCons::with_config(config).is_err());
+    let config = rcimmixcons::GCConfig::new().with_rc_collector(false)
+                                             .with_write_barrier_collect_threshold(10);
+    assert!(rcimmixcons::RCImmixCons::with_config(config).is_err());
+}
+
+#[test]
+#[allow(unused_variables)]
+fn small_heap_allocate_test() {
+    let config = rcimmixcons::GCConfig::new().with_heap_size(64 * 32 * 1024);
+    let collector = rcimmixcons::RCImmixCons::with_config(config).unwrap();
+    let rtti = rcimmixcons::GCRTTI::new(128, 0);
+    let chunck1 = collector.allocate(&rtti).unwrap();
+    let chunck2 = collector.allocate(&rtti).unwrap();
+    collector.collect(true, true);
+}
+
+#[test]
+fn multi_thread_allocate_test() {
+    let collector = Arc::new(rcimmixcons::RCImmixCons::new());
+    let rtti = Arc::new(rcimmixcons::GCRTTI::new(128, 0));
+    assert!(collector.enter_native());
+    let handles: Vec<_> = (0..4).map(|_| {
+        let collector = collector.clone();
+        let rtti = rtti.clone();
+        thread::spawn(move || {
+            assert!(collector.register_thread());
+            for _ in 0..1000 {
+                assert!(collector.allocate(&*rtti).is_some());
+            }
+            collector.collect(true, true);
+            assert!(collector.unregister_thread());
+        })
+    }).collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    collector.leave_native();
+    collector.collect(true, true);
+}
+
+#[test]
+fn weak_ref_test() {
+    let collector = rcimmixcons::RCImmixCons::new();
+    let rtti = rcimmixcons::GCRTTI::new(128, 0);
+    let object = collector.allocate(&rtti).unwrap();
+    let weak_ref = collector.weak_create(object).unwrap();
+    assert_eq!(collector.weak_get(&weak_ref), Some(object));
+    collector.collect(false, true);
+    assert!(collector.weak_get(&weak_ref).is_some());
+    collector.weak_destroy(weak_ref);
+    let mut not_an_object = 0usize;
+    let not_an_object = &mut not_an_object as *mut usize as rcimmixcons::GCObjectRef;
+    assert!(collector.weak_create(not_an_object).is_none());
+}
+
+#[repr(C)]
+struct FinalizedObject {
+    object: rcimmixcons::GCObject,
+    finalized: bool,
+}
+
+static FINALIZED: AtomicUsize = AtomicUsize::new(0);
+
+extern fn count_finalized(object: rcimmixcons::GCObjectRef) {
+    unsafe{ (*(object as *mut FinalizedObject)).finalized = true; }
+    FINALIZED.fetch_add(1, Ordering::SeqCst);
+}
+
+#[inline(never)]
+fn allocate_garbage(collector: &rcimmixcons::RCImmixCons, rtti: &rcimmixcons::GCRTTI) {
+    for _ in 0..100 {
+        collector.allocate(rtti).unwrap();
+    }
+}
+
+#[test]
+fn finalizer_test() {
+    let collector = rcimmixcons::RCImmixCons::new();
+    let rtti = rcimmixcons::GCRTTI::new(128, 0).with_finalizer(count_finalized);
+    let object = collector.allocate(&rtti).unwrap() as *mut FinalizedObject;
+    unsafe{ (*object).finalized = false; }
+    allocate_garbage(&collector, &rtti);
+    collector.collect(false, false);
+    let finalized = FINALIZED.load(Ordering::SeqCst);
+    assert!(finalized > 0 && finalized <= 100);
+    collector.collect(true, true);
+    assert!(!unsafe{ (*object).finalized });
+}
+
+#[repr(C)]
+struct MixedObject {
+    object: rcimmixcons::GCObject,
+    value: usize,
+    child: rcimmixcons::GCObjectRef,
+}
+
+static MIXED_OBJECT_OFFSETS: [usize; 1] = [mem::size_of::<rcimmixcons::GCObject>()
+                                           + mem::size_of::<usize>()];
+
+#[test]
+fn member_offsets_test() {
+    let collector = rcimmixcons::RCImmixCons::new();
+    let rtti = rcimmixcons::GCRTTI::new(mem::size_of::<MixedObject>(), 0)
+                   .with_member_offsets(&MIXED_OBJECT_OFFSETS);
+    let child_rtti = rcimmixcons::GCRTTI::new(128, 0);
+    let object = collector.allocate(&rtti).unwrap() as *mut MixedObject;
+    let child = collector.allocate(&child_rtti).unwrap();
+    unsafe {
+        (*object).value = 1;
+        (*object).child = child;
+    }
+    collector.collect(true, true);
+    assert_eq!(unsafe{ (*object).value }, 1);
+    assert_eq!(unsafe{ (*(*object).child).rtti() }, &child_rtti as *const _);
+}
+
+#[test]
+fn array_allocate_test() {
+    let collector = rcimmixcons::RCImmixCons::new();
+    let rtti = rcimmixcons::GCRTTI::new(mem::size_of::<rcimmixcons::GCObject>(), 0)
+                   .with_elements(mem::size_of::<rcimmixcons::GCObjectRef>(), 1);
+    let child_rtti = rcimmixcons::GCRTTI::new(128, 0);
+    assert!(collector.allocate_array(&child_rtti, 10).is_none());
+    let array = collector.allocate_array(&rtti, 10).unwrap();
+    assert_eq!(unsafe{ (*array).length() }, 10);
+    assert_eq!(unsafe{ (*array).object_size() },
+               mem::size_of::<rcimmixcons::GCObject>()
+               + 10 * mem::size_of::<rcimmixcons::GCObjectRef>());
+    let child = collector.allocate(&child_rtti).unwrap();
+    unsafe{ (*array).set_member(9, child); }
+    collector.collect(true, true);
+    let member = unsafe{ (*array).member(9) };
+    assert!(!member.is_null());
+    assert_eq!(unsafe{ (*member).rtti() }, &child_rtti as *const _);
+}
+
+#[test]
+#[should_panic(expected = "Member 1 is out of bounds")]
+fn member_out_of_bounds_test() {
+    let rtti = rcimmixcons::GCRTTI::new(128, 1);
+    rtti.member_offset(1);
+}
+
+#[test]
+fn packed_header_test() {
+    assert_eq!(mem::size_of::<rcimmixcons::GCHeader>(), mem::size_of::<usize>());
+    let collector = rcimmixcons::RCImmixCons::new();
+    let rtti = rcimmixcons::GCRTTI::new(mem::size_of::<rcimmixcons::GCObject>(), 0)
+                   .with_elements(1, 0);
+    assert!(collector.allocate_array(&rtti, usize::max_value() >> 4).is_none());
+    let array = collector.allocate_array(&rtti, 1000).unwrap();
+    assert_eq!(unsafe{ (*array).length() }, 1000);
+}
+
+#[test]
+fn stats_test() {
+    let config = rcimmixcons::GCConfig::new().with_rc_collector(false);
+    let collector = rcimmixcons::RCImmixCons::with_config(config).unwrap();
+    let rtti = rcimmixcons::GCRTTI::new(128, 0);
+    assert_eq!(collector.stats().bytes_allocated, 0);
+    let object = collector.allocate(&rtti).unwrap();
+    assert_eq!(collector.stats().bytes_allocated, 128);
+    collector.collect(false, true);
+    let stats = collector.stats();
+    assert_eq!(stats.collections(), 1);
+    assert_eq!(stats.immix_collections, 1);
+    assert!(stats.live_objects >= 1);
+    assert_eq!(unsafe{ (*object).rtti() }, &rtti as *const _);
+}
+
+#[test]
+fn event_hook_test() {
+    let collector = rcimmixcons::RCImmixCons::new();
+    let events = Arc::new(AtomicUsize::new(0));
+    let hook_events = events.clone();
+    collector.add_event_hook(move |event| {
+        assert_eq!(event.collection_type, rcimmixcons::CollectionType::RCCollection);
+        hook_events.fetch_add(1, Ordering::SeqCst);
+    });
+    collector.collect(false, false);
+    assert_eq!(events.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn heap_dump_test() {
+    let collector = rcimmixcons::RCImmixCons::new();
+    let rtti = rcimmixcons::GCRTTI::new(mem::size_of::<rcimmixcons::GCObject>()
+                                        + mem::size_of::<rcimmixcons::GCObjectRef>(), 1);
+    let large_rtti = rcimmixcons::GCRTTI::new(16 * 1024, 0);
+    let object = collector.allocate(&rtti).unwrap();
+    let large_object = collector.allocate(&large_rtti).unwrap();
+    unsafe{ (*object).set_member(0, large_object); }
+    let path = std::env::temp_dir().join("rcimmixcons_heap_dump_test.dump");
+    collector.dump_heap(&path).unwrap();
+    let dump = rcimmixcons::HeapDump::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let dumped = dump.object(object as usize).unwrap();
+    assert_eq!(dumped.rtti, &rtti as *const rcimmixcons::GCRTTI as usize);
+    assert_eq!(dumped.size, rtti.object_size());
+    assert!(dumped.block.is_some());
+    assert_eq!(dumped.edges, vec![large_object as usize]);
+    let dumped_large = dump.object(large_object as usize).unwrap();
+    assert_eq!(dumped_large.block, None);
+    assert!(dump.roots.contains(&(object as usize)));
+
+    let mut bytes = Vec::new();
+    dump.write_to(&mut bytes).unwrap();
+    assert_eq!(rcimmixcons::HeapDump::read_from(&mut &bytes[..]).unwrap(), dump);
+    bytes[8] = rcimmixcons::HEAP_DUMP_VERSION as u8 + 1;
+    assert!(rcimmixcons::HeapDump::read_from(&mut &bytes[..]).is_err());
+}
+
+#[test]
+fn object_graph_dot_test() {
+    let collector = rcimmixcons::RCImmixCons::new();
+    let rtti = rcimmixcons::GCRTTI::new(mem::size_of::<rcimmixcons::GCObject>()
+                                        + mem::size_of::<rcimmixcons::GCObjectRef>(), 1);
+    let object = collector.allocate(&rtti).unwrap();
+    let child = collector.allocate(&rtti).unwrap();
+    unsafe{ (*object).set_member(0, child); }
+    let dot = collector.object_graph_dot_from(object);
+    assert!(dot.starts_with("digraph heap {"));
+    assert!(dot.contains(&format!("\"{:p}\" -> \"{:p}\";", object, child)));
+    assert!(dot.contains(&format!("\"{:p}\" [label=\"{:p}\\nrc=0\\nblock ", child, child)));
+    let dot = collector.object_graph_dot();
+    assert!(dot.contains(&format!("\"{:p}\" [label=", object)));
+    assert!(dot.contains("shape=box, style=bold"));
+}
+
+#[test]
+fn pin_test() {
+    let collector = rcimmixcons::RCImmixCons::new();
+    let rtti = rcimmixcons::GCRTTI::new(128, 0);
+    let object = collector.allocate(&rtti).unwrap();
+    {
+        let guard = collector.pin_guard(object).unwrap();
+        assert!(collector.pin(guard.object()));
+        collector.collect(true, true);
+        assert!(collector.unpin(object));
+        assert!(unsafe{ (*object).is_pinned() });
+        collector.collect(true, true);
+        assert_eq!(unsafe{ (*object).rtti() }, &rtti as *const _);
+    }
+    assert!(!unsafe{ (*object).is_pinned() });
+    assert!(!collector.unpin(object));
+    let mut not_an_object = 0usize;
+    let not_an_object = &mut not_an_object as *mut usize as rcimmixcons::GCObjectRef;
+    assert!(collector.pin_guard(not_an_object).is_none());
+}
+
+#[test]
+fn root_scope_test() {
+    let collector = rcimmixcons::RCImmixCons::new();
+    let rtti = rcimmixcons::GCRTTI::new(128, 0);
+    let mut globals = vec![std::ptr::null_mut(); 10].into_boxed_slice();
+    let mut global = Box::new(std::ptr::null_mut());
+    {
+        let mut scope = collector.root_scope();
+        scope.add_range(globals.as_ptr(), globals.len());
+        scope.add(&*global);
+        for slot in globals.iter_mut() {
+            *slot = collector.allocate(&rtti).unwrap();
+        }
+        *global = globals[0];
+        collector.collect(true, true);
+        for &object in globals.iter() {
+            assert_eq!(unsafe{ (*object).rtti() }, &rtti as *const _);
+        }
+        assert_eq!(*global, globals[0]);
+    }
+    assert!(!collector.remove_root_range(globals.as_ptr()));
+    assert!(!collector.remove_static_root(&*global));
+    collector.set_static_root(&*global);
+    assert!(collector.remove_static_root(&*global));
+}
+
+#[test]
+fn shadow_stack_test() {
+    let config = rcimmixcons::GCConfig::new().with_scan_stack(false);
+    let collector = rcimmixcons::RCImmixCons::with_config(config).unwrap();
+    let rtti = rcimmixcons::GCRTTI::new(128, 0);
+    let mut slots = vec![std::ptr::null_mut(); 2].into_boxed_slice();
+    assert!(collector.push_roots(slots.as_mut_ptr(), slots.len()));
+    slots[1] = collector.allocate(&rtti).unwrap();
+    let weak_ref = collector.weak_create(slots[1]).unwrap();
+    collector.collect(true, true);
+    assert_eq!(collector.weak_get(&weak_ref), Some(slots[1]));
+    assert!(collector.pop_roots());
+    assert!(!collector.pop_roots());
+    collector.collect(false, false);
+    assert_eq!(collector.weak_get(&weak_ref), None);
+    collector.weak_destroy(weak_ref);
+}
+
+#[test]
+fn precise_root_evacuation_test() {
+    let config = rcimmixcons::GCConfig::new().with_scan_stack(false)
+                                             .with_heap_size(64 * 32 * 1024)
+                                             .with_verify_heap(true);
+    let collector = rcimmixcons::RCImmixCons::with_config(config).unwrap();
+    let rtti = rcimmixcons::GCRTTI::new(128, 0);
+    let mut slots = vec![std::ptr::null_mut(); 2000].into_boxed_slice();
+    assert!(collector.push_roots(slots.as_mut_ptr(), slots.len()));
+    let mut global = Box::new(std::ptr::null_mut());
+    collector.set_static_root(&*global);
+    for _ in 0..5000 {
+        collector.allocate(&rtti).unwrap();
+    }
+    collector.collect(false, false);
+    for round in 0..4 {
+        for num in 0..slots.len() {
+            let object = collector.allocate(&rtti).unwrap();
+            if num % 7 == round {
+                slots[num] = object;
+            } else if num == 1 {
+                *global = object;
+            }
+        }
+        collector.collect(true, round % 2 == 1);
+        assert!(collector.verify_violations().is_empty());
+    }
+    assert!(collector.stats().objects_evacuated > 0);
+    for &object in slots.iter().chain(Some(&*global)).filter(|o| !o.is_null()) {
+        assert!(unsafe{ (*object).is_forwarded() }.is_none());
+        assert_eq!(unsafe{ (*object).rtti() }, &rtti as *const _);
+    }
+    assert!(collector.remove_static_root(&*global));
+    assert!(collector.pop_roots());
+}
+
+#[test]
+fn nursery_test() {
+    let config = rcimmixcons::GCConfig::new().with_scan_stack(false)
+                                             .with_nursery(true)
+                                             .with_verify_heap(true);
+    let collector = rcimmixcons::RCImmixCons::with_config(config).unwrap();
+    let rtti = rcimmixcons::GCRTTI::new(mem::size_of::<rcimmixcons::GCObject>()
+                                        + mem::size_of::<rcimmixcons::GCObjectRef>(), 1);
+    let leaf_rtti = rcimmixcons::GCRTTI::new(128, 0);
+    let mut slots = vec![std::ptr::null_mut(); 2].into_boxed_slice();
+    assert!(collector.push_roots(slots.as_mut_ptr(), slots.len()));
+    slots[0] = collector.allocate(&rtti).unwrap();
+    for round in 0..4 {
+        for _ in 0..5000 {
+            collector.allocate(&leaf_rtti).unwrap();
+        }
+        let old = collector.allocate(&leaf_rtti).unwrap();
+        collector.write_member(slots[0], 0, old);
+        slots[1] = collector.allocate(&rtti).unwrap();
+        let young = collector.allocate(&leaf_rtti).unwrap();
+        collector.write_member(slots[1], 0, young);
+        let new_objects = [slots[0], slots[1]];
+        collector.collect(false, false);
+        assert!(collector.verify_violations().is_empty());
+        assert!(slots[1] != new_objects[1]);
+        assert_eq!(slots[0] != new_objects[0], round == 0);
+        for &object in slots.iter() {
+            let children: Vec<_> = unsafe{ (*object).children().collect() };
+            assert_eq!(children.len(), 1);
+            assert!(children[0] != old && children[0] != young);
+            assert_eq!(unsafe{ (*children[0]).rtti() }, &leaf_rtti as *const _);
+        }
+    }
+    let stats = collector.stats();
+    assert_eq!(stats.rc_evac_collections, 4);
+    assert_eq!(stats.objects_evacuated, 4 * 3 + 1);
+    assert!(collector.pop_roots());
+}
+
+/// Build a random graph, trace it with `gc_threads` threads and return the
+/// reference count of every node or `None` if it was reclaimed.
+fn trace_random_graph(gc_threads: usize) -> Vec<Option<usize>> {
+    let config = rcimmixcons::GCConfig::new().with_rc_collector(false)
+                                             .with_scan_stack(false)
+                                             .with_verify_heap(true)
+                                             .with_gc_threads(gc_threads);
+    let collector = rcimmixcons::RCImmixCons::with_config(config).unwrap();
+    let rtti = rcimmixcons::GCRTTI::new(mem::size_of::<rcimmixcons::GCObject>()
+                                        + 2 * mem::size_of::<rcimmixcons::GCObjectRef>(), 2);
+    let garbage_rtti = rcimmixcons::GCRTTI::new(256, 0);
+    let nodes: Vec<_> = (0..20000).map(|_| {
+        collector.allocate(&garbage_rtti).unwrap();
+        collector.allocate(&rtti).unwrap()
+    }).collect();
+    for _ in 0..5000 {
+        collector.allocate(&garbage_rtti).unwrap();
+    }
+    let mut seed: u64 = 42;
+    for &node in &nodes {
+        for num in 0..2 {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            let target = (seed >> 33) as usize % (nodes.len() * 4 / 3);
+            if target < nodes.len() {
+                unsafe{ (*node).set_member(num, nodes[target]); }
+            }
+        }
+    }
+    let mut slots: Vec<_> = (0..4).map(|num| nodes[num * 997]).collect();
+    assert!(collector.push_roots(slots.as_mut_ptr(), slots.len()));
+    let weak_refs: Vec<_> = nodes.iter().map(|&o| collector.weak_create(o).unwrap()).collect();
+    collector.collect(false, true);
+    assert!(collector.verify_violations().is_empty());
+    collector.collect(true, true);
+    assert!(collector.verify_violations().is_empty());
+    assert!(collector.stats().objects_evacuated > 0);
+    let counts = weak_refs.iter()
+                          .map(|w| collector.weak_get(w).map(|o| unsafe{ (*o).reference_count() }))
+                          .collect();
+    for weak_ref in weak_refs {
+        collector.weak_destroy(weak_ref);
+    }
+    assert!(collector.pop_roots());
+    counts
+}
+
+#[test]
+fn parallel_trace_test() {
+    let counts = trace_random_graph(1);
+    assert!(counts.iter().any(|c| c.is_none()));
+    assert!(counts.iter().filter(|c| c.is_some()).count() > 1000);
+    assert!(counts == trace_random_graph(4));
+}
+
+fn rc_random_graph(gc_threads: usize) -> (Vec<Option<usize>>, usize) {
+    let config = rcimmixcons::GCConfig::new().with_scan_stack(false)
+                                             .with_nursery(true)
+                                             .with_gc_threads(gc_threads);
+    let collector = rcimmixcons::RCImmixCons::with_config(config).unwrap();
+    let rtti = rcimmixcons::GCRTTI::new(mem::size_of::<rcimmixcons::GCObject>()
+                                        + 2 * mem::size_of::<rcimmixcons::GCObjectRef>(), 2);
+    let large_rtti = rcimmixcons::GCRTTI::new(16 * 1024, 2);
+    let nodes: Vec<_> = (0..20000).map(|num| match num % 100 {
+        0 => collector.allocate(&large_rtti).unwrap(),
+        _ => collector.allocate(&rtti).unwrap(),
+    }).collect();
+    let mut seed: u64 = 42;
+    let mut next = || {
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (seed >> 33) as usize
+    };
+    for (index, &node) in nodes.iter().enumerate() {
+        for num in 0..2 {
+            let target = index + 1 + next() % 50;
+            if target < nodes.len() {
+                unsafe{ (*node).set_member(num, nodes[target]); }
+            }
+        }
+    }
+    let mut roots: Vec<_> = (0..2000).map(|num| nodes[num * 10]).collect();
+    assert!(collector.push_roots(roots.as_mut_ptr(), roots.len()));
+    let weak_refs: Vec<_> = nodes.iter().map(|&o| collector.weak_create(o).unwrap()).collect();
+    collector.collect(true, false);
+    let evacuated = collector.stats().objects_evacuated;
+    for weak_ref in weak_refs.iter().step_by(3) {
+        if let Some(node) = collector.weak_get(weak_ref) {
+            collector.write_barrier(node);
+            unsafe{ (*node).set_member(next() % 2, ptr::null_mut()); }
+        }
+    }
+    roots[0] = ptr::null_mut();
+    collector.collect(true, false);
+    let counts = weak_refs.iter()
+                          .map(|w| collector.weak_get(w).map(|o| unsafe{ (*o).reference_count() }))
+                          .collect();
+    for weak_ref in weak_refs {
+        collector.weak_destroy(weak_ref);
+    }
+    assert!(collector.pop_roots());
+    (counts, evacuated)
+}
+
+#[test]
+fn parallel_rc_test() {
+    let (counts, evacuated) = rc_random_graph(1);
+    assert!(evacuated > 0);
+    assert!(counts.iter().any(|c| c.is_none()));
+    assert!(counts.iter().filter(|c| c.is_some()).count() > 1000);
+    assert!((counts, evacuated) == rc_random_graph(4));
+}
+
+#[derive(Trace)]
+struct Node {
+    value: usize,
+    next: GcCell<Option<Gc<Node>>>,
+}
+
+#[test]
+fn gc_test() {
+    let collector = rcimmixcons::RCImmixCons::new();
+    let first = Gc::new(&collector, Node { value: 1, next: GcCell::new(None) }).unwrap();
+    let second = Gc::new(&collector, Node { value: 2, next: GcCell::new(None) }).unwrap();
+    let rtti = unsafe{ (*first.as_object()).rtti() };
+    assert_eq!(unsafe{ (*rtti).members() }, 1);
+    first.set(&collector, |node| &node.next, Some(second));
+    collector.collect(true, true);
+    assert!(Gc::ptr_eq(&first.next.get().unwrap(), &second));
+    assert_eq!(first.next.get().map(|node| node.value), Some(2));
+    let first = unsafe{ Gc::<Node>::from_object(first.as_object()) };
+    assert_eq!(first.value, 1);
+}
+
+static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Trace)]
+struct Dropped(usize);
+
+impl Drop for Dropped {
+    fn drop(&mut self) {
+        DROPPED.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[inline(never)]
+fn allocate_dropped(collector: &rcimmixcons::RCImmixCons) {
+    for num in 0..100 {
+        Gc::new(collector, Dropped(num)).unwrap();
+    }
+}
+
+#[test]
+fn gc_drop_test() {
+    let collector = rcimmixcons::RCImmixCons::new();
+    allocate_dropped(&collector);
+    collector.collect(false, false);
+    let dropped = DROPPED.load(Ordering::SeqCst);
+    assert!(dropped > 0 && dropped <= 100);
+}
+
+#[test]
+fn write_member_test() {
+    let config = rcimmixcons::GCConfig::new().with_scan_stack(false);
+    let collector = rcimmixcons::RCImmixCons::with_config(config).unwrap();
+    let rtti = rcimmixcons::GCRTTI::new(mem::size_of::<rcimmixcons::GCObject>()
+                                        + 2 * mem::size_of::<rcimmixcons::GCObjectRef>(), 2);
+    let leaf_rtti = rcimmixcons::GCRTTI::new(128, 0);
+    let mut slots = vec![std::ptr::null_mut(); 1].into_boxed_slice();
+    assert!(collector.push_roots(slots.as_mut_ptr(), slots.len()));
+    slots[0] = collector.allocate(&rtti).unwrap();
+    let mut objects: Vec<_> = (0..2).map(|_| collector.allocate(&leaf_rtti).unwrap()).collect();
+    assert!(collector.write_member(slots[0], 0, objects[0]));
+    assert!(collector.write_member(slots[0], 1, objects[1]));
+    assert!(!collector.write_member(slots[0], 2, objects[1]));
+    let mut not_an_object = [0usize; 4];
+    let not_an_object = not_an_object.as_mut_ptr() as rcimmixcons::GCObjectRef;
+    assert!(!collector.write_member(not_an_object, 0, objects[0]));
+    collector.collect(false, false);
+    objects.extend((0..2).map(|_| collector.allocate(&leaf_rtti).unwrap()));
+    let weak_refs: Vec<_> = objects.iter().map(|&o| collector.weak_create(o).unwrap()).collect();
+    collector.write_member(slots[0], 0, objects[2]);
+    collector.write_member(slots[0], 1, objects[3]);
+    collector.write_barrier(slots[0]);
+    unsafe{ (*slots[0]).set_member(1, std::ptr::null_mut()); }
+    collector.collect(false, false);
+    assert_eq!(collector.weak_get(&weak_refs[0]), None);
+    assert_eq!(collector.weak_get(&weak_refs[1]), None);
+    assert_eq!(collector.weak_get(&weak_refs[2]), Some(objects[2]));
+    assert_eq!(collector.weak_get(&weak_refs[3]), None);
+    assert_eq!(unsafe{ (*slots[0]).children().collect::<Vec<_>>() }, vec![objects[2]]);
+    for weak_ref in weak_refs {
+        collector.weak_destroy(weak_ref);
+    }
+    assert!(collector.pop_roots());
+}
@@ -7,7 +7,7 @@ fi
 
 function run_test {
     local file=$1;
-    clang -g -O0 "tests/$file.c" ${CLANG_OPTS} -l rcimmixcons -o "target/$file" || return 1;
+    clang -g -O0 -pthread "tests/$file.c" ${CLANG_OPTS} -l rcimmixcons -o "target/$file" || return 1;
     "./target/$file" || return 2;
     valgrind "./target/$file" || return 3;
     return 0;